@@ -0,0 +1,749 @@
+//! Herramienta de linea de comandos de D-Link.
+//! Usa el mismo nucleo que la aplicacion (`d_link_core`) sin depender de Tauri
+//! ni abrir ninguna ventana, pensada para scripts.
+
+use std::io::{Read, Write};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+
+use d_link_core::backup::{self, BackupFormat, RestoreMode};
+use d_link_core::batch;
+use d_link_core::emulator::{registrar_emulado, EmulatedDevice};
+use d_link_core::error::DLinkError;
+use d_link_core::events::SinEventos;
+use d_link_core::operations::Operacion;
+use d_link_core::ops;
+use d_link_core::queue::{ActionDefaults, QueueAction};
+use d_link_core::repository;
+use d_link_core::sync::{self, SyncAction, SyncMode};
+use d_link_core::types::{
+    AddDevicePayload, ConflictPolicy, DeviceId, FileResult, OperationKind, TransferOutcome,
+};
+use d_link_core::usb::sincronizar_dispositivos;
+
+#[derive(Parser)]
+#[command(
+    name = "d-link-cli",
+    version,
+    about = "Administra calculadoras TI-Nspire por USB"
+)]
+struct Cli {
+    /// Dispositivo a usar: id estable o BUS:DIRECCION (por defecto, el primero encontrado)
+    #[arg(short, long, global = true)]
+    device: Option<String>,
+
+    /// Imprime los resultados como JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Usa una calculadora emulada en memoria en lugar de USB
+    #[arg(long, global = true)]
+    emulated: bool,
+
+    /// Tamano maximo en bytes de un archivo a descargar
+    #[arg(long, global = true)]
+    max_download_size: Option<u64>,
+
+    #[command(subcommand)]
+    comando: Comando,
+}
+
+#[derive(Subcommand)]
+enum Comando {
+    /// Lista las calculadoras conectadas
+    List,
+    /// Muestra la informacion del sistema de la calculadora
+    Info,
+    /// Lista el contenido de un directorio
+    Ls {
+        #[arg(default_value = "/")]
+        path: String,
+    },
+    /// Descarga un archivo (o un directorio con --recursive) de la calculadora
+    Get {
+        src: String,
+        /// Directorio local de destino
+        #[arg(default_value = ".")]
+        dest: PathBuf,
+        #[arg(short, long)]
+        recursive: bool,
+        /// Que hacer con los archivos que ya existen en el directorio local
+        #[arg(long, value_enum, default_value_t = Conflicto::Fail)]
+        on_conflict: Conflicto,
+    },
+    /// Sube un archivo (o un directorio con --recursive) a un directorio de la calculadora
+    Put {
+        src: PathBuf,
+        #[arg(default_value = "/")]
+        dest: String,
+        #[arg(short, long)]
+        recursive: bool,
+        /// Extension de los archivos a subir con --recursive (por defecto, tns)
+        #[arg(long = "ext", requires = "recursive")]
+        extensiones: Vec<String>,
+        /// Sube todos los archivos con --recursive, sin filtrar por extension
+        #[arg(long, requires = "recursive", conflicts_with = "extensiones")]
+        all: bool,
+        /// Que hacer con los archivos que ya existen en la calculadora
+        #[arg(long, value_enum, default_value_t = Conflicto::Overwrite)]
+        on_conflict: Conflicto,
+    },
+    /// Elimina un archivo (o un directorio vacio con --dir, o con su contenido con --recursive)
+    Rm {
+        path: String,
+        // Sin forma corta: -d es --device
+        #[arg(long)]
+        dir: bool,
+        #[arg(short, long, conflicts_with = "dir")]
+        recursive: bool,
+        /// Con --recursive, sigue eliminando aunque algun elemento falle
+        #[arg(long, requires = "recursive")]
+        keep_going: bool,
+    },
+    /// Crea un directorio
+    Mkdir { path: String },
+    /// Mueve o renombra un archivo o directorio
+    Mv { src: String, dest: String },
+    /// Copia un archivo
+    Cp { src: String, dest: String },
+    /// Instala un archivo de sistema operativo
+    OsUpgrade { src: PathBuf },
+    /// Respalda toda la calculadora en un archivo .zip, .tar o .tar.zst
+    Backup {
+        dest: PathBuf,
+        /// Formato del archivo (por defecto, segun la extension)
+        #[arg(long, value_enum)]
+        format: Option<FormatoRespaldo>,
+    },
+    /// Restaura un respaldo zip o tar en la calculadora
+    Restore {
+        src: PathBuf,
+        #[arg(short, long, value_enum, default_value_t = ModoRestauracion::Merge)]
+        mode: ModoRestauracion,
+    },
+    /// Ejecuta en orden las acciones de un archivo JSON (como las de `run_batch`)
+    Batch {
+        /// Archivo con la lista de acciones; `-` lee de la entrada estandar
+        file: PathBuf,
+    },
+    /// Repositorio local de instantaneas incrementales
+    Repo {
+        /// Directorio del repositorio (se crea al tomar la primera instantanea)
+        repo: PathBuf,
+        #[command(subcommand)]
+        accion: AccionRepo,
+    },
+    /// Sincroniza una carpeta local con una carpeta de la calculadora
+    Sync {
+        local: PathBuf,
+        remote: String,
+        #[arg(short, long, value_enum, default_value_t = ModoSync::TwoWay)]
+        mode: ModoSync,
+        /// Solo muestra el plan, sin ejecutarlo
+        #[arg(long)]
+        dry_run: bool,
+        /// Extension de los archivos locales a considerar (por defecto, tns)
+        #[arg(long = "ext")]
+        extensiones: Vec<String>,
+        /// Considera todos los archivos locales, sin filtrar por extension
+        #[arg(long, conflicts_with = "extensiones")]
+        all: bool,
+    },
+}
+
+/// Subcomandos del repositorio de instantaneas
+#[derive(Subcommand)]
+enum AccionRepo {
+    /// Toma una instantanea de la calculadora
+    Snapshot,
+    /// Lista las instantaneas; con --device, solo las de ese id estable
+    List,
+    /// Muestra que cambio entre dos instantaneas
+    Diff { from: String, to: String },
+    /// Restaura una instantanea en la calculadora
+    Restore {
+        snapshot: String,
+        #[arg(short, long, value_enum, default_value_t = ModoRestauracion::Merge)]
+        mode: ModoRestauracion,
+    },
+}
+
+/// Formato de respaldo, ver `BackupFormat`
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatoRespaldo {
+    Zip,
+    Tar,
+    TarZst,
+}
+
+impl From<FormatoRespaldo> for BackupFormat {
+    fn from(formato: FormatoRespaldo) -> Self {
+        match formato {
+            FormatoRespaldo::Zip => BackupFormat::Zip,
+            FormatoRespaldo::Tar => BackupFormat::Tar,
+            FormatoRespaldo::TarZst => BackupFormat::TarZst,
+        }
+    }
+}
+
+/// Modo de restauracion, ver `RestoreMode`
+#[derive(Clone, Copy, ValueEnum)]
+enum ModoRestauracion {
+    /// Agrega lo que falta sin tocar los archivos existentes
+    Merge,
+    /// Agrega lo que falta y sobrescribe los archivos existentes
+    Replace,
+    /// Elimina todo el contenido de la calculadora antes de restaurar
+    Wipe,
+}
+
+impl From<ModoRestauracion> for RestoreMode {
+    fn from(modo: ModoRestauracion) -> Self {
+        match modo {
+            ModoRestauracion::Merge => RestoreMode::Merge,
+            ModoRestauracion::Replace => RestoreMode::ReplaceMatching,
+            ModoRestauracion::Wipe => RestoreMode::WipeThenRestore,
+        }
+    }
+}
+
+/// Modo de sincronizacion, ver `SyncMode`
+#[derive(Clone, Copy, ValueEnum)]
+enum ModoSync {
+    /// La calculadora queda igual que la carpeta local
+    MirrorToDevice,
+    /// La carpeta local queda igual que la calculadora
+    MirrorToLocal,
+    /// Copia lo nuevo o modificado en ambos sentidos, sin eliminar
+    TwoWay,
+}
+
+impl From<ModoSync> for SyncMode {
+    fn from(modo: ModoSync) -> Self {
+        match modo {
+            ModoSync::MirrorToDevice => SyncMode::MirrorToDevice,
+            ModoSync::MirrorToLocal => SyncMode::MirrorToLocal,
+            ModoSync::TwoWay => SyncMode::TwoWay,
+        }
+    }
+}
+
+/// Politica de conflictos al subir o descargar, ver `ConflictPolicy`
+#[derive(Clone, Copy, ValueEnum)]
+enum Conflicto {
+    /// Sobrescribe el archivo existente
+    Overwrite,
+    /// Deja el archivo existente y no transfiere el nuevo
+    Skip,
+    /// Transfiere el nuevo con un sufijo numerado
+    KeepBoth,
+    /// Falla con un error por cada archivo que ya existe
+    Fail,
+}
+
+impl From<Conflicto> for ConflictPolicy {
+    fn from(conflicto: Conflicto) -> Self {
+        match conflicto {
+            Conflicto::Overwrite => ConflictPolicy::Overwrite,
+            Conflicto::Skip => ConflictPolicy::Skip,
+            Conflicto::KeepBoth => ConflictPolicy::KeepBoth,
+            // Sin interfaz no se puede preguntar
+            Conflicto::Fail => ConflictPolicy::Ask,
+        }
+    }
+}
+
+/// Resultado de una operacion sin datos, para la salida JSON
+#[derive(Serialize)]
+struct Confirmacion {
+    ok: bool,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match ejecutar(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            if cli.json {
+                // Los errores del nucleo incluyen codigo, dispositivo y ruta
+                let detalle = match error.downcast_ref::<DLinkError>() {
+                    Some(error) => serde_json::to_value(error).unwrap_or_default(),
+                    None => serde_json::json!({ "code": "internal", "message": error.to_string() }),
+                };
+                println!("{}", serde_json::json!({ "error": detalle }));
+            } else {
+                eprintln!("Error: {:#}", error);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Registra los dispositivos disponibles segun las opciones
+fn descubrir(cli: &Cli) -> Result<Vec<AddDevicePayload>, anyhow::Error> {
+    if cli.emulated {
+        let emulado = Arc::new(EmulatedDevice::con_contenido_de_ejemplo());
+        return Ok(vec![registrar_emulado(
+            emulado,
+            "TI-Nspire CX II (emulada)".to_string(),
+        )?]);
+    }
+
+    let (dispositivos, _) = sincronizar_dispositivos()?;
+    Ok(dispositivos)
+}
+
+/// Elige el dispositivo indicado con --device, o el primero disponible
+fn seleccionar(cli: &Cli, dispositivos: &[AddDevicePayload]) -> Result<DeviceId, anyhow::Error> {
+    let elegido = match &cli.device {
+        Some(texto) => {
+            let ubicacion = texto.split_once(':').and_then(|(bus, direccion)| {
+                Some(DeviceId {
+                    bus_number: bus.parse().ok()?,
+                    address: direccion.parse().ok()?,
+                })
+            });
+            dispositivos
+                .iter()
+                .find(|d| d.stable_id == *texto || Some(d.id) == ubicacion)
+                .ok_or_else(|| anyhow::anyhow!("Dispositivo no encontrado"))?
+        }
+        None => dispositivos
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("No hay calculadoras conectadas"))?,
+    };
+
+    if elegido.needs_drivers {
+        anyhow::bail!("El dispositivo necesita drivers (WinUSB) para comunicarse");
+    }
+    Ok(elegido.id)
+}
+
+/// Crea un callback que dibuja el progreso en stderr (solo en modo texto)
+fn barra_progreso(json: bool) -> impl FnMut(usize, usize) -> ControlFlow<()> {
+    let mut ultimo_porcentaje = None;
+
+    move |restante, total| {
+        if json || total == 0 {
+            return ControlFlow::Continue(());
+        }
+        let porcentaje = (total - restante) * 100 / total;
+        if ultimo_porcentaje != Some(porcentaje) {
+            ultimo_porcentaje = Some(porcentaje);
+            eprint!("\r{:3}%", porcentaje);
+            if restante == 0 {
+                eprintln!();
+            }
+            let _ = std::io::stderr().flush();
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Imprime un valor como JSON o con el formato de texto dado
+fn imprimir<T: Serialize>(json: bool, valor: &T, texto: impl FnOnce(&T)) {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(valor).unwrap_or_default()
+        );
+    } else {
+        texto(valor);
+    }
+}
+
+/// Imprime el resultado de cada archivo y falla si alguno no se pudo procesar
+fn imprimir_resultados(json: bool, resultados: &[FileResult]) -> Result<(), anyhow::Error> {
+    imprimir(json, &resultados, |resultados| {
+        for resultado in resultados.iter() {
+            imprimir_linea(resultado);
+        }
+    });
+
+    let fallidos = resultados.iter().filter(|r| r.error.is_some()).count();
+    if fallidos > 0 {
+        anyhow::bail!("{} de {} archivos fallaron", fallidos, resultados.len());
+    }
+    Ok(())
+}
+
+/// Imprime en texto el resultado de un archivo; en las transferencias, en vez
+/// de `ok` se indica que se hizo con el
+fn imprimir_linea(resultado: &FileResult) {
+    let estado = match (&resultado.error, resultado.outcome) {
+        (Some(error), _) => {
+            println!("error\t{}\t{}", resultado.path, error.message);
+            return;
+        }
+        (None, None) => "ok",
+        (None, Some(TransferOutcome::Created)) => "creado",
+        (None, Some(TransferOutcome::Overwritten)) => "sobrescrito",
+        (None, Some(TransferOutcome::Skipped)) => "omitido",
+        (None, Some(TransferOutcome::Renamed)) => "renombrado",
+    };
+    println!("{}\t{}", estado, resultado.path);
+}
+
+/// Extensiones a filtrar segun --ext y --all
+fn extensiones_elegidas(extensiones: &[String], todas: bool) -> Vec<&str> {
+    if todas {
+        vec![]
+    } else if extensiones.is_empty() {
+        ops::EXTENSIONES_TRANSFERIBLES.to_vec()
+    } else {
+        extensiones.iter().map(String::as_str).collect()
+    }
+}
+
+/// Imprime la confirmacion de una operacion sin datos
+fn confirmar(json: bool) {
+    imprimir(json, &Confirmacion { ok: true }, |_| {});
+}
+
+fn ejecutar(cli: &Cli) -> Result<(), anyhow::Error> {
+    if let Some(maximo) = cli.max_download_size {
+        ops::establecer_limite_descarga(maximo);
+    }
+
+    // Consultas al repositorio que no necesitan una calculadora
+    if let Comando::Repo { repo, accion } = &cli.comando {
+        match accion {
+            AccionRepo::List => {
+                let lista = repository::listar(repo, cli.device.as_deref())?.resumir();
+                imprimir(cli.json, &lista, |lista| {
+                    for r in &lista.snapshots {
+                        println!(
+                            "{}\t{}\t{}\t{} archivos\t{} bytes",
+                            r.id, r.device, r.name, r.files, r.size
+                        );
+                    }
+                    for error in &lista.invalid {
+                        eprintln!("Instantanea omitida: {}", error);
+                    }
+                });
+                return Ok(());
+            }
+            AccionRepo::Diff { from, to } => {
+                let diferencias = repository::comparar(repo, from, to)?;
+                imprimir(cli.json, &diferencias, |diferencias| {
+                    for (signo, entradas) in [
+                        ('+', &diferencias.added),
+                        ('-', &diferencias.removed),
+                        ('~', &diferencias.modified),
+                    ] {
+                        for entrada in entradas {
+                            println!(
+                                "{} {}{}",
+                                signo,
+                                entrada.path,
+                                if entrada.is_dir { "/" } else { "" }
+                            );
+                        }
+                    }
+                });
+                return Ok(());
+            }
+            AccionRepo::Snapshot | AccionRepo::Restore { .. } => {}
+        }
+    }
+
+    let dispositivos = descubrir(cli)?;
+
+    if let Comando::List = cli.comando {
+        imprimir(cli.json, &dispositivos, |lista| {
+            for d in lista {
+                println!(
+                    "{}\t{}:{}\t{}{}",
+                    d.stable_id,
+                    d.id.bus_number,
+                    d.id.address,
+                    d.name,
+                    if d.needs_drivers {
+                        " (necesita drivers)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        });
+        return Ok(());
+    }
+
+    let id = seleccionar(cli, &dispositivos)?;
+    let info = ops::abrir(id)?;
+
+    match &cli.comando {
+        Comando::List => {}
+        Comando::Info => imprimir(cli.json, &info, |info| {
+            println!("Nombre:        {}", info.name);
+            println!("Sistema:       {}", info.os_version);
+            println!("Boot2:         {}", info.boot2_version);
+            println!(
+                "Almacenamiento: {} / {} bytes libres",
+                info.free_storage, info.total_storage
+            );
+            println!(
+                "Bateria:       {}%{}",
+                info.battery,
+                if info.is_charging { " (cargando)" } else { "" }
+            );
+        }),
+        Comando::Ls { path } => {
+            let archivos = ops::listar(id, path)?;
+            imprimir(cli.json, &archivos, |archivos| {
+                for archivo in archivos {
+                    if archivo.is_dir {
+                        println!("{}/", archivo.path);
+                    } else {
+                        println!("{}\t{}", archivo.path, archivo.size);
+                    }
+                }
+            });
+        }
+        Comando::Get {
+            src,
+            dest,
+            recursive: false,
+            on_conflict,
+        } => {
+            // El nucleo obtiene el tamano del listado del directorio padre
+            let (path, outcome) = ops::descargar(
+                id,
+                src,
+                None,
+                dest,
+                (*on_conflict).into(),
+                &mut barra_progreso(cli.json),
+            )?;
+            let resultado = FileResult {
+                path: path.display().to_string(),
+                error: None,
+                outcome: Some(outcome),
+            };
+            imprimir(cli.json, &resultado, imprimir_linea);
+        }
+        Comando::Get {
+            src,
+            dest,
+            recursive: true,
+            on_conflict,
+        } => {
+            let resultados = ops::descargar_directorio(
+                id,
+                src,
+                dest,
+                (*on_conflict).into(),
+                &mut barra_progreso(cli.json),
+            )?;
+            imprimir_resultados(cli.json, &resultados)?;
+        }
+        Comando::Put {
+            src,
+            dest,
+            recursive: false,
+            on_conflict,
+            ..
+        } => {
+            let (path, outcome) = ops::subir(
+                id,
+                src,
+                dest,
+                (*on_conflict).into(),
+                &mut barra_progreso(cli.json),
+            )?;
+            let resultado = FileResult {
+                path,
+                error: None,
+                outcome: Some(outcome),
+            };
+            imprimir(cli.json, &resultado, imprimir_linea);
+        }
+        Comando::Put {
+            src,
+            dest,
+            recursive: true,
+            extensiones,
+            all,
+            on_conflict,
+        } => {
+            let resultados = ops::subir_directorio(
+                id,
+                src,
+                dest,
+                &extensiones_elegidas(extensiones, *all),
+                (*on_conflict).into(),
+                &mut barra_progreso(cli.json),
+            )?;
+            imprimir_resultados(cli.json, &resultados)?;
+        }
+        Comando::Rm {
+            path,
+            recursive: true,
+            keep_going,
+            ..
+        } => {
+            let resultados =
+                ops::eliminar_recursivo(id, path, *keep_going, &mut barra_progreso(cli.json))?;
+            imprimir_resultados(cli.json, &resultados)?;
+        }
+        Comando::Rm { path, dir, .. } => {
+            if *dir {
+                ops::eliminar_directorio(id, path)?;
+            } else {
+                ops::eliminar_archivo(id, path)?;
+            }
+            confirmar(cli.json);
+        }
+        Comando::Mkdir { path } => {
+            ops::crear_directorio(id, path)?;
+            confirmar(cli.json);
+        }
+        Comando::Mv { src, dest } => {
+            ops::mover(id, src, dest)?;
+            confirmar(cli.json);
+        }
+        Comando::Cp { src, dest } => {
+            ops::copiar(id, src, dest)?;
+            confirmar(cli.json);
+        }
+        Comando::OsUpgrade { src } => {
+            ops::subir_os(id, src, &mut barra_progreso(cli.json))?;
+            confirmar(cli.json);
+        }
+        Comando::Backup { dest, format } => {
+            let resultados = backup::respaldar(
+                id,
+                dest,
+                format.map(Into::into),
+                &mut barra_progreso(cli.json),
+            )?;
+            imprimir_resultados(cli.json, &resultados)?;
+        }
+        Comando::Restore { src, mode } => {
+            let resultados =
+                backup::restaurar(id, src, (*mode).into(), &mut barra_progreso(cli.json))?;
+            imprimir_resultados(cli.json, &resultados)?;
+        }
+        Comando::Batch { file } => {
+            let json = if file.as_os_str() == "-" {
+                let mut json = String::new();
+                std::io::stdin().read_to_string(&mut json)?;
+                json
+            } else {
+                std::fs::read_to_string(file)?
+            };
+            let acciones: Vec<QueueAction> = serde_json::from_str(&json)?;
+            let destinos = ActionDefaults {
+                descargas: Some(PathBuf::from(".")),
+                repositorio: None,
+            };
+            let resultados = batch::ejecutar(
+                id,
+                &acciones,
+                &destinos,
+                &Operacion::iniciar(OperationKind::Batch),
+                &SinEventos,
+                &mut barra_progreso(cli.json),
+            )?;
+            imprimir(cli.json, &resultados, |resultados| {
+                for resultado in resultados.iter() {
+                    match &resultado.error {
+                        None => println!("ok\t{}", resultado.index),
+                        Some(error) => {
+                            println!("error\t{}\t{}", resultado.index, error.message)
+                        }
+                    }
+                }
+            });
+            let fallidas = resultados.iter().filter(|r| r.error.is_some()).count();
+            if fallidas > 0 {
+                anyhow::bail!("{} de {} acciones fallaron", fallidas, resultados.len());
+            }
+        }
+        Comando::Repo { repo, accion } => match accion {
+            AccionRepo::Snapshot => {
+                let informe = repository::crear(id, repo, &mut barra_progreso(cli.json))?;
+                imprimir(cli.json, &informe, |informe| {
+                    for resultado in &informe.results {
+                        if let Some(error) = &resultado.error {
+                            println!("error\t{}\t{}", resultado.path, error.message);
+                        }
+                    }
+                    println!(
+                        "Instantanea {}: {} descargados, {} sin cambios",
+                        informe.snapshot.id, informe.downloaded, informe.reused
+                    );
+                });
+                // Los archivos que fallan no entran en la instantanea
+                let fallidos = informe.results.iter().filter(|r| r.error.is_some()).count();
+                if fallidos > 0 {
+                    anyhow::bail!("{} archivos no entraron en la instantanea", fallidos);
+                }
+            }
+            AccionRepo::Restore { snapshot, mode } => {
+                let resultados = repository::restaurar(
+                    id,
+                    repo,
+                    snapshot,
+                    (*mode).into(),
+                    &mut barra_progreso(cli.json),
+                )?;
+                imprimir_resultados(cli.json, &resultados)?;
+            }
+            AccionRepo::List | AccionRepo::Diff { .. } => {}
+        },
+        Comando::Sync {
+            local,
+            remote,
+            mode,
+            dry_run,
+            extensiones,
+            all,
+        } => {
+            let plan = sync::planificar(
+                id,
+                local,
+                remote,
+                (*mode).into(),
+                &extensiones_elegidas(extensiones, *all),
+            )?;
+
+            if *dry_run {
+                imprimir(cli.json, &plan, |plan| {
+                    for accion in &plan.actions {
+                        match accion {
+                            SyncAction::Upload { local, remote, .. } => {
+                                println!("subir\t{}\t{}", local.display(), remote)
+                            }
+                            SyncAction::Download { remote, local, .. } => {
+                                println!("descargar\t{}\t{}", remote, local.display())
+                            }
+                            SyncAction::CreateRemoteDir { remote } => println!("mkdir\t{}", remote),
+                            SyncAction::CreateLocalDir { local } => {
+                                println!("mkdir\t{}", local.display())
+                            }
+                            SyncAction::DeleteRemote { remote, .. } => println!("rm\t{}", remote),
+                            SyncAction::DeleteLocal { local, .. } => {
+                                println!("rm\t{}", local.display())
+                            }
+                        }
+                    }
+                    for conflicto in &plan.conflicts {
+                        println!("conflicto\t{}", conflicto);
+                    }
+                });
+            } else {
+                let resultados = sync::ejecutar(id, &plan, &mut barra_progreso(cli.json))?;
+                imprimir_resultados(cli.json, &resultados)?;
+            }
+        }
+    }
+
+    Ok(ops::cerrar(id)?)
+}
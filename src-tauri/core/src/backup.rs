@@ -0,0 +1,707 @@
+//! Respaldo completo de una calculadora en un unico archivo zip o tar.zst, y
+//! restauracion desde uno de esos archivos.
+//! El archivo contiene el arbol de la calculadora bajo `files/` y un
+//! `manifest.json` con las rutas, tamanos, fechas y la informacion del
+//! dispositivo en el momento del respaldo.
+
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{DLinkError, ErrorCode};
+use crate::ops::{self, Progreso};
+use crate::transport::TransportError;
+use crate::types::{DeviceId, DeviceInfo, FileInfo, FileResult};
+
+/// Version del formato del manifiesto
+pub const VERSION_MANIFIESTO: u32 = 1;
+
+/// Nombre del manifiesto dentro del archivo
+pub const NOMBRE_MANIFIESTO: &str = "manifest.json";
+
+/// Directorio del archivo bajo el que se guarda el arbol de la calculadora
+pub const DIRECTORIO_ARCHIVOS: &str = "files";
+
+/// Formato del archivo de respaldo
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BackupFormat {
+    Zip,
+    Tar,
+    /// tar comprimido con zstd
+    TarZst,
+}
+
+impl BackupFormat {
+    /// Deduce el formato de la extension del archivo (`.zip`, `.tar`, `.tar.zst` o `.tzst`)
+    pub fn desde_ruta(ruta: &Path) -> Option<Self> {
+        let nombre = ruta.file_name()?.to_string_lossy().to_lowercase();
+        if nombre.ends_with(".zip") {
+            Some(BackupFormat::Zip)
+        } else if nombre.ends_with(".tar") {
+            Some(BackupFormat::Tar)
+        } else if nombre.ends_with(".tar.zst") || nombre.ends_with(".tzst") {
+            Some(BackupFormat::TarZst)
+        } else {
+            None
+        }
+    }
+}
+
+/// Contenido de `manifest.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub version: u32,
+    /// Segundos desde la epoca Unix
+    pub created: u64,
+    pub device: DeviceInfo,
+    /// Directorios y archivos guardados, con rutas absolutas de la calculadora.
+    /// Los archivos que no se pudieron leer no aparecen.
+    pub entries: Vec<FileInfo>,
+}
+
+/// Que hacer con el contenido que ya tiene la calculadora al restaurar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RestoreMode {
+    /// Agrega lo que falta; los archivos que ya existen no se tocan
+    Merge,
+    /// Agrega lo que falta y sobrescribe los archivos que ya existen
+    ReplaceMatching,
+    /// Elimina todo el contenido de la calculadora antes de restaurar
+    WipeThenRestore,
+}
+
+/// Escritor de uno de los formatos de respaldo
+enum Escritor {
+    Zip(Box<zip::ZipWriter<BufWriter<File>>>),
+    Tar(tar::Builder<BufWriter<File>>),
+    TarZst(tar::Builder<zstd::Encoder<'static, BufWriter<File>>>),
+}
+
+impl Escritor {
+    fn nuevo(formato: BackupFormat, archivo: File) -> io::Result<Self> {
+        let archivo = BufWriter::with_capacity(64 * 1024, archivo);
+        Ok(match formato {
+            BackupFormat::Zip => Escritor::Zip(Box::new(zip::ZipWriter::new(archivo))),
+            BackupFormat::Tar => Escritor::Tar(tar::Builder::new(archivo)),
+            BackupFormat::TarZst => {
+                Escritor::TarZst(tar::Builder::new(zstd::Encoder::new(archivo, 0)?))
+            }
+        })
+    }
+
+    /// Agrega una entrada; `datos` es `None` para los directorios
+    fn agregar(&mut self, ruta: &str, fecha: u64, datos: Option<&[u8]>) -> io::Result<()> {
+        match self {
+            Escritor::Zip(zip) => {
+                let opciones = zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .last_modified_time(fecha_zip(fecha));
+                match datos {
+                    Some(datos) => {
+                        zip.start_file(ruta, opciones)?;
+                        zip.write_all(datos)
+                    }
+                    None => Ok(zip.add_directory(ruta, opciones)?),
+                }
+            }
+            Escritor::Tar(tar) => agregar_tar(tar, ruta, fecha, datos),
+            Escritor::TarZst(tar) => agregar_tar(tar, ruta, fecha, datos),
+        }
+    }
+
+    /// Cierra el formato y deja el archivo escrito en disco
+    fn terminar(self) -> io::Result<()> {
+        let archivo = match self {
+            Escritor::Zip(zip) => zip.finish()?,
+            Escritor::Tar(tar) => tar.into_inner()?,
+            Escritor::TarZst(tar) => tar.into_inner()?.finish()?,
+        };
+        archivo.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }
+}
+
+/// Agrega una entrada a un tar; `datos` es `None` para los directorios
+fn agregar_tar<W: Write>(
+    tar: &mut tar::Builder<W>,
+    ruta: &str,
+    fecha: u64,
+    datos: Option<&[u8]>,
+) -> io::Result<()> {
+    let mut cabecera = tar::Header::new_gnu();
+    cabecera.set_mtime(fecha);
+    match datos {
+        Some(datos) => {
+            cabecera.set_entry_type(tar::EntryType::Regular);
+            cabecera.set_mode(0o644);
+            cabecera.set_size(datos.len() as u64);
+            tar.append_data(&mut cabecera, ruta, datos)
+        }
+        None => {
+            cabecera.set_entry_type(tar::EntryType::Directory);
+            cabecera.set_mode(0o755);
+            cabecera.set_size(0);
+            tar.append_data(&mut cabecera, ruta, io::empty())
+        }
+    }
+}
+
+/// Convierte segundos desde la epoca Unix (UTC) a la fecha de una entrada zip.
+/// Las fechas fuera del rango del formato (1980-2107) quedan en el valor por defecto.
+fn fecha_zip(segundos: u64) -> zip::DateTime {
+    // Algoritmo de dias civiles de Howard Hinnant
+    let dias = (segundos / 86_400) as i64 + 719_468;
+    let era = dias.div_euclid(146_097);
+    let dia_era = dias.rem_euclid(146_097);
+    let anio_era = (dia_era - dia_era / 1_460 + dia_era / 36_524 - dia_era / 146_096) / 365;
+    let dia_anio = dia_era - (365 * anio_era + anio_era / 4 - anio_era / 100);
+    let mes_desde_marzo = (5 * dia_anio + 2) / 153;
+    let dia = dia_anio - (153 * mes_desde_marzo + 2) / 5 + 1;
+    let mes = if mes_desde_marzo < 10 {
+        mes_desde_marzo + 3
+    } else {
+        mes_desde_marzo - 9
+    };
+    let anio = anio_era + era * 400 + i64::from(mes <= 2);
+
+    let segundo_dia = segundos % 86_400;
+    zip::DateTime::from_date_and_time(
+        anio.clamp(0, u16::MAX as i64) as u16,
+        mes as u8,
+        dia as u8,
+        (segundo_dia / 3_600) as u8,
+        (segundo_dia / 60 % 60) as u8,
+        (segundo_dia % 60) as u8,
+    )
+    .unwrap_or_default()
+}
+
+/// Ruta dentro del archivo para una ruta absoluta de la calculadora
+fn ruta_en_archivo(ruta: &str) -> String {
+    format!("{}/{}", DIRECTORIO_ARCHIVOS, ruta.trim_start_matches('/'))
+}
+
+/// Respalda toda la calculadora, desde `/`, en el archivo `dest` con el formato
+/// indicado o, si no se indica, el que corresponde a su extension.
+///
+/// Cada archivo se lee completo en memoria (lo acota `ops::limite_descarga`) y
+/// se escribe en el respaldo en cuanto llega. El respaldo se escribe en un
+/// archivo temporal que solo al terminar toma el nombre final, asi que una
+/// interrupcion no deja el respaldo a medias. Un archivo que falla no aparece en
+/// el manifiesto.
+pub fn respaldar(
+    id: DeviceId,
+    dest: &Path,
+    formato: Option<BackupFormat>,
+    progreso: Progreso,
+) -> Result<Vec<FileResult>, DLinkError> {
+    let formato = formato
+        .or_else(|| BackupFormat::desde_ruta(dest))
+        .ok_or_else(|| {
+            DLinkError::app(
+                ErrorCode::InvalidArgument,
+                "No se reconoce el formato del respaldo; use .zip, .tar o .tar.zst",
+            )
+            .con_ruta(dest.display())
+        })?;
+
+    let info = ops::actualizar_info(id)?;
+    let entradas = ops::listar_arbol(id, "/")?;
+
+    let temporal = ops::ruta_temporal(dest);
+    let resultado =
+        escribir(id, &temporal, formato, info, entradas, progreso).and_then(|resultados| {
+            fs::rename(&temporal, dest)
+                .map_err(|e| DLinkError::from(e).con_ruta(dest.display()))?;
+            Ok(resultados)
+        });
+    if resultado.is_err() {
+        let _ = fs::remove_file(&temporal);
+    }
+    resultado
+}
+
+/// Escribe el respaldo completo en `temporal`
+fn escribir(
+    id: DeviceId,
+    temporal: &Path,
+    formato: BackupFormat,
+    info: DeviceInfo,
+    entradas: Vec<FileInfo>,
+    progreso: Progreso,
+) -> Result<Vec<FileResult>, DLinkError> {
+    let error_local = |e: io::Error| DLinkError::from(e).con_ruta(temporal.display());
+
+    let total: u64 = entradas.iter().filter(|e| !e.is_dir).map(|e| e.size).sum();
+    let total = total as usize;
+
+    let archivo = File::create(temporal).map_err(error_local)?;
+    let mut escritor = Escritor::nuevo(formato, archivo).map_err(error_local)?;
+
+    let mut guardadas = Vec::with_capacity(entradas.len());
+    let mut resultados = vec![];
+    let mut completado = 0;
+    for entrada in entradas {
+        if progreso(total.saturating_sub(completado), total).is_break() {
+            return Err(DLinkError::from(TransportError::Cancelled).con_dispositivo(id));
+        }
+        if entrada.is_dir {
+            escritor
+                .agregar(&ruta_en_archivo(&entrada.path), entrada.date, None)
+                .map_err(error_local)?;
+            guardadas.push(entrada);
+            continue;
+        }
+
+        let datos = ops::leer(id, &entrada.path, entrada.size, &mut |restante, tamano| {
+            progreso(
+                ops::restante_agregado(total, completado, restante, tamano),
+                total,
+            )
+        });
+        // Cuenta lo que se leyo, que puede no coincidir con el listado
+        completado += datos.as_ref().map_or(entrada.size as usize, Vec::len);
+
+        match datos {
+            Ok(datos) => {
+                escritor
+                    .agregar(&ruta_en_archivo(&entrada.path), entrada.date, Some(&datos))
+                    .map_err(error_local)?;
+                resultados.push(FileResult {
+                    path: entrada.path.clone(),
+                    error: None,
+                    outcome: None,
+                });
+                guardadas.push(entrada);
+            }
+            Err(error) if error.interrumpe() => return Err(error),
+            Err(error) => resultados.push(FileResult {
+                path: entrada.path,
+                error: Some(error),
+                outcome: None,
+            }),
+        }
+    }
+
+    let manifiesto = BackupManifest {
+        version: VERSION_MANIFIESTO,
+        created: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duracion| duracion.as_secs()),
+        device: info,
+        entries: guardadas,
+    };
+    let json = serde_json::to_vec_pretty(&manifiesto).map_err(DLinkError::interno)?;
+    escritor
+        .agregar(NOMBRE_MANIFIESTO, manifiesto.created, Some(&json))
+        .map_err(error_local)?;
+    escritor.terminar().map_err(error_local)?;
+
+    Ok(resultados)
+}
+
+/// Recorre las entradas del archivo `src` en orden. `visitar` recibe el nombre
+/// de la entrada, si es un directorio, su tamano y su contenido.
+fn recorrer(
+    src: &Path,
+    formato: BackupFormat,
+    visitar: &mut dyn FnMut(&str, bool, u64, &mut dyn Read) -> Result<(), DLinkError>,
+) -> Result<(), DLinkError> {
+    let error_local = |e: io::Error| DLinkError::from(e).con_ruta(src.display());
+    let archivo = BufReader::with_capacity(64 * 1024, File::open(src).map_err(error_local)?);
+
+    match formato {
+        BackupFormat::Zip => {
+            let mut zip =
+                zip::ZipArchive::new(archivo).map_err(|e| error_local(io::Error::from(e)))?;
+            for indice in 0..zip.len() {
+                let mut entrada = zip
+                    .by_index(indice)
+                    .map_err(|e| error_local(io::Error::from(e)))?;
+                let nombre = entrada.name().to_string();
+                let (es_dir, tamano) = (entrada.is_dir(), entrada.size());
+                visitar(&nombre, es_dir, tamano, &mut entrada)?;
+            }
+            Ok(())
+        }
+        BackupFormat::Tar => recorrer_tar(archivo, src, visitar),
+        BackupFormat::TarZst => recorrer_tar(
+            zstd::Decoder::with_buffer(archivo).map_err(error_local)?,
+            src,
+            visitar,
+        ),
+    }
+}
+
+/// Recorre las entradas de un tar; ver `recorrer`
+fn recorrer_tar<R: Read>(
+    lector: R,
+    src: &Path,
+    visitar: &mut dyn FnMut(&str, bool, u64, &mut dyn Read) -> Result<(), DLinkError>,
+) -> Result<(), DLinkError> {
+    let error_local = |e: io::Error| DLinkError::from(e).con_ruta(src.display());
+    let mut tar = tar::Archive::new(lector);
+    for entrada in tar.entries().map_err(error_local)? {
+        let mut entrada = entrada.map_err(error_local)?;
+        let tipo = entrada.header().entry_type();
+        if !tipo.is_file() && !tipo.is_dir() {
+            continue;
+        }
+        let nombre = entrada
+            .path()
+            .map_err(error_local)?
+            .to_string_lossy()
+            .into_owned();
+        let tamano = entrada.size();
+        visitar(&nombre, tipo.is_dir(), tamano, &mut entrada)?;
+    }
+    Ok(())
+}
+
+/// Ruta de la calculadora para la entrada `nombre` del archivo. Con `prefijo`
+/// solo cuentan las entradas bajo `files/`. Devuelve `None` para las entradas
+/// que no forman parte del arbol y `Some(Err)` para las rutas que saldrian de el.
+fn ruta_en_calculadora(nombre: &str, prefijo: bool) -> Option<Result<String, DLinkError>> {
+    let mut partes = nombre
+        .split(['/', '\\'])
+        .filter(|p| !p.is_empty() && *p != ".");
+    if prefijo && partes.next() != Some(DIRECTORIO_ARCHIVOS) {
+        return None;
+    }
+
+    let partes: Vec<&str> = partes.collect();
+    if partes.is_empty() {
+        return None;
+    }
+    if partes.contains(&"..") {
+        return Some(Err(DLinkError::app(
+            ErrorCode::InvalidArgument,
+            "La ruta sale del arbol de la calculadora",
+        )
+        .con_ruta(nombre)));
+    }
+    Some(Ok(format!("/{}", partes.join("/"))))
+}
+
+/// Restaura en la calculadora `id` el respaldo `src`, un zip o tar (comprimido
+/// o no) como los que escribe `respaldar`, con el formato de su extension.
+/// El manifiesto es opcional: si existe, o si todas las entradas estan bajo
+/// `files/`, el arbol se toma de ese directorio; si no, de la raiz del archivo.
+/// El respaldo puede venir de otra calculadora.
+///
+/// Antes de tocar nada se comprueba que el espacio libre de `Info`, mas lo que
+/// liberaria el modo elegido, alcance para los archivos a escribir. Luego se
+/// crean los directorios que falten y se suben los archivos. Los archivos que
+/// `Merge` deja como estaban no aparecen en los resultados. Si varias entradas
+/// tienen el mismo nombre solo se restaura la primera, y las demas aparecen
+/// como fallos.
+pub fn restaurar(
+    id: DeviceId,
+    src: &Path,
+    modo: RestoreMode,
+    progreso: Progreso,
+) -> Result<Vec<FileResult>, DLinkError> {
+    let formato = BackupFormat::desde_ruta(src).ok_or_else(|| {
+        DLinkError::app(
+            ErrorCode::InvalidArgument,
+            "No se reconoce el formato del respaldo; use .zip, .tar o .tar.zst",
+        )
+        .con_ruta(src.display())
+    })?;
+
+    // Primera pasada: solo nombres y tamanos, y el manifiesto si lo hay
+    let mut nombres = vec![];
+    let mut manifiesto = None;
+    recorrer(src, formato, &mut |nombre, es_dir, tamano, contenido| {
+        if nombre == NOMBRE_MANIFIESTO && !es_dir {
+            let mut json = vec![];
+            contenido
+                .read_to_end(&mut json)
+                .map_err(|e| DLinkError::from(e).con_ruta(src.display()))?;
+            let leido: BackupManifest = serde_json::from_slice(&json).map_err(|e| {
+                DLinkError::app(
+                    ErrorCode::InvalidArgument,
+                    format!("El manifiesto del respaldo no es valido: {}", e),
+                )
+                .con_ruta(src.display())
+            })?;
+            manifiesto = Some(leido);
+        } else {
+            nombres.push((nombre.to_string(), es_dir, tamano));
+        }
+        Ok(())
+    })?;
+
+    let prefijo = manifiesto.is_some()
+        || nombres.iter().all(|(nombre, _, _)| {
+            nombre.trim_start_matches("./").split('/').next() == Some(DIRECTORIO_ARCHIVOS)
+        });
+
+    let mut resultados = vec![];
+    let mut directorios = BTreeSet::new();
+    let mut archivos = BTreeMap::new();
+    for (nombre, es_dir, tamano) in nombres {
+        let ruta = match ruta_en_calculadora(&nombre, prefijo) {
+            None => continue,
+            Some(Ok(ruta)) => ruta,
+            Some(Err(error)) => {
+                resultados.push(FileResult {
+                    path: nombre,
+                    error: Some(error),
+                    outcome: None,
+                });
+                continue;
+            }
+        };
+        if es_dir {
+            directorios.insert(ruta);
+            continue;
+        }
+        match archivos.entry(ruta) {
+            Entry::Vacant(vacia) => {
+                vacia.insert(tamano);
+            }
+            // Solo se restaura la primera entrada con cada nombre
+            Entry::Occupied(ocupada) => resultados.push(FileResult {
+                path: nombre,
+                error: Some(
+                    DLinkError::app(
+                        ErrorCode::InvalidArgument,
+                        "El respaldo tiene otra entrada con el mismo nombre",
+                    )
+                    .con_ruta(ocupada.key()),
+                ),
+                outcome: None,
+            }),
+        }
+    }
+
+    let mut restauracion =
+        Restauracion::preparar(id, directorios, &mut archivos, modo, resultados, progreso)
+            .map_err(|e| e.con_ruta(src.display()))?;
+
+    // Segunda pasada: subir el contenido de los archivos elegidos
+    recorrer(src, formato, &mut |nombre, es_dir, _, contenido| {
+        let ruta = match (es_dir, ruta_en_calculadora(nombre, prefijo)) {
+            (false, Some(Ok(ruta))) => ruta,
+            _ => return Ok(()),
+        };
+        // Las entradas repetidas ya quedaron en los resultados
+        let Some(tamano) = archivos.remove(&ruta) else {
+            return Ok(());
+        };
+        restauracion.continuar(progreso)?;
+
+        // `preparar` ya comprobo que el tamano cabe en la calculadora; no se
+        // reserva de antemano ni se lee mas de lo que dice la cabecera
+        let mut datos = Vec::new();
+        (&mut *contenido)
+            .take(tamano.saturating_add(1))
+            .read_to_end(&mut datos)
+            .map_err(|e| DLinkError::from(e).con_ruta(src.display()))?;
+        if datos.len() as u64 != tamano {
+            return Err(DLinkError::app(
+                ErrorCode::InvalidArgument,
+                "El contenido de la entrada no tiene el tamano que indica el respaldo",
+            )
+            .con_ruta(nombre));
+        }
+        restauracion.subir(ruta, datos, progreso)
+    })?;
+
+    Ok(restauracion.terminar(progreso))
+}
+
+/// Restauracion en curso sobre una calculadora ya preparada. La comparten
+/// `restaurar` y las instantaneas del repositorio, que solo difieren en de
+/// donde sale el contenido de cada archivo.
+pub(crate) struct Restauracion {
+    id: DeviceId,
+    total: usize,
+    completado: usize,
+    resultados: Vec<FileResult>,
+}
+
+impl Restauracion {
+    /// Deja en `archivos` (ruta y tamano) solo los que `modo` manda escribir,
+    /// comprueba que quepan, vacia la calculadora si el modo lo pide y crea los
+    /// `directorios` que falten, con sus padres y los de cada archivo.
+    /// `resultados` son los fallos previos, que se devuelven al terminar.
+    pub(crate) fn preparar(
+        id: DeviceId,
+        mut directorios: BTreeSet<String>,
+        archivos: &mut BTreeMap<String, u64>,
+        modo: RestoreMode,
+        mut resultados: Vec<FileResult>,
+        progreso: Progreso,
+    ) -> Result<Self, DLinkError> {
+        // Los directorios padre se crean aunque el origen no los incluya
+        let rutas: Vec<String> = directorios.iter().chain(archivos.keys()).cloned().collect();
+        for ruta in rutas {
+            let mut padre = ruta.as_str();
+            while let Some(fin) = padre.rfind('/').filter(|&fin| fin > 0) {
+                padre = &padre[..fin];
+                directorios.insert(padre.to_string());
+            }
+        }
+
+        let existentes: HashMap<String, FileInfo> = ops::listar_arbol(id, "/")?
+            .into_iter()
+            .map(|entrada| (entrada.path.clone(), entrada))
+            .collect();
+
+        // Archivos a escribir y bytes que se liberan antes de escribirlos
+        let mut liberado = 0;
+        match modo {
+            RestoreMode::Merge => archivos.retain(|ruta, _| !existentes.contains_key(ruta)),
+            RestoreMode::ReplaceMatching => {
+                liberado = archivos
+                    .keys()
+                    .filter_map(|ruta| existentes.get(ruta))
+                    .filter(|entrada| !entrada.is_dir)
+                    .map(|entrada| entrada.size)
+                    .sum();
+            }
+            RestoreMode::WipeThenRestore => {
+                liberado = existentes
+                    .values()
+                    .filter(|entrada| !entrada.is_dir)
+                    .map(|entrada| entrada.size)
+                    .sum();
+            }
+        }
+
+        // Los tamanos vienen del origen, que puede estar danado
+        let necesario = archivos
+            .values()
+            .try_fold(0u64, |suma, tamano| suma.checked_add(*tamano))
+            .ok_or_else(|| {
+                DLinkError::app(
+                    ErrorCode::InvalidArgument,
+                    "Los tamanos de los archivos a restaurar no son validos",
+                )
+            })?;
+        let disponible = ops::actualizar_info(id)?
+            .free_storage
+            .saturating_add(liberado);
+        if necesario > disponible {
+            return Err(DLinkError::app(
+                ErrorCode::NoSpace,
+                format!(
+                    "La restauracion necesita {} bytes y la calculadora tiene {} disponibles",
+                    necesario, disponible
+                ),
+            )
+            .con_dispositivo(id));
+        }
+
+        let restauracion = Restauracion {
+            id,
+            total: necesario as usize,
+            completado: 0,
+            resultados: vec![],
+        };
+
+        if modo == RestoreMode::WipeThenRestore {
+            for entrada in ops::listar(id, "/")? {
+                restauracion.continuar(progreso)?;
+                // Si algo no se puede eliminar no se restaura encima
+                if entrada.is_dir {
+                    let eliminados =
+                        ops::eliminar_recursivo(id, &entrada.path, false, &mut |_, _| {
+                            progreso(restauracion.total, restauracion.total)
+                        })?;
+                    if let Some(error) = eliminados.into_iter().find_map(|r| r.error) {
+                        return Err(error);
+                    }
+                } else {
+                    ops::eliminar_archivo(id, &entrada.path)?;
+                }
+            }
+        }
+
+        // El orden del conjunto deja cada directorio antes que sus hijos
+        let recien_vaciada = modo == RestoreMode::WipeThenRestore;
+        for directorio in directorios {
+            if !recien_vaciada && existentes.get(&directorio).is_some_and(|e| e.is_dir) {
+                continue;
+            }
+            restauracion.continuar(progreso)?;
+            match ops::crear_directorio_si_falta(id, &directorio) {
+                Ok(()) => {}
+                Err(error) if error.es_desconexion() => return Err(error),
+                Err(error) => resultados.push(FileResult {
+                    path: directorio,
+                    error: Some(error),
+                    outcome: None,
+                }),
+            }
+        }
+
+        Ok(Restauracion {
+            resultados,
+            ..restauracion
+        })
+    }
+
+    /// Reporta el avance y devuelve error si se pidio cancelar
+    pub(crate) fn continuar(&self, progreso: Progreso) -> Result<(), DLinkError> {
+        if progreso(self.total.saturating_sub(self.completado), self.total).is_break() {
+            return Err(DLinkError::from(TransportError::Cancelled).con_dispositivo(self.id));
+        }
+        Ok(())
+    }
+
+    /// Escribe un archivo en la calculadora. Un fallo queda en los resultados;
+    /// la cancelacion y la desconexion se devuelven como error.
+    pub(crate) fn subir(
+        &mut self,
+        ruta: String,
+        datos: Vec<u8>,
+        progreso: Progreso,
+    ) -> Result<(), DLinkError> {
+        let (total, completado) = (self.total, self.completado);
+        let tamano = datos.len();
+        let resultado = ops::escribir(self.id, &ruta, datos, &mut |restante, tamano| {
+            progreso(
+                ops::restante_agregado(total, completado, restante, tamano),
+                total,
+            )
+        });
+        self.completado = completado + tamano;
+
+        self.registrar(ruta, resultado)
+    }
+
+    /// Registra el resultado de un archivo; los errores que interrumpen la
+    /// operacion se devuelven
+    pub(crate) fn registrar(
+        &mut self,
+        ruta: String,
+        resultado: Result<(), DLinkError>,
+    ) -> Result<(), DLinkError> {
+        match resultado {
+            Err(error) if error.interrumpe() => Err(error),
+            resultado => {
+                self.resultados.push(FileResult {
+                    path: ruta,
+                    error: resultado.err(),
+                    outcome: None,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Reporta el final y devuelve el resultado de cada archivo
+    pub(crate) fn terminar(self, progreso: Progreso) -> Vec<FileResult> {
+        let _ = progreso(0, self.total);
+        self.resultados
+    }
+}
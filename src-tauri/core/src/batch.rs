@@ -0,0 +1,129 @@
+//! Lotes de operaciones: una lista de acciones, las mismas que las de la cola,
+//! que se ejecutan en orden en una sola llamada, con un progreso y
+//! una cancelacion comunes y el resultado de cada accion. Cada accion emite su
+//! inicio y su fin con su posicion en el lote.
+
+use std::fs;
+
+use serde::Serialize;
+
+use crate::error::DLinkError;
+use crate::events::{notificar_fin, notificar_inicio, EventSink};
+use crate::operations::{Operacion, OperationId};
+use crate::ops::Progreso;
+use crate::queue::{self, ActionDefaults, QueueAction};
+use crate::transport::TransportError;
+use crate::types::DeviceId;
+
+/// Resultado de una accion del lote
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchActionResult {
+    /// Posicion de la accion en el lote
+    pub index: usize,
+    /// `None` si la accion se completo sin errores
+    pub error: Option<DLinkError>,
+}
+
+/// Resultado de un lote completo
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResult {
+    /// Id de la operacion del lote: el de sus eventos de progreso y el que
+    /// acepta la cancelacion
+    pub batch_id: OperationId,
+    pub results: Vec<BatchActionResult>,
+}
+
+/// Peso de una accion en el progreso del lote: los bytes que transfiere, o 1
+/// si no transfiere datos
+fn peso(accion: &QueueAction) -> usize {
+    let bytes = match accion {
+        QueueAction::Download { size, .. } => *size,
+        QueueAction::Upload { src, .. } | QueueAction::UploadOs { src } => {
+            fs::metadata(src).map_or(0, |m| m.len())
+        }
+        QueueAction::Sync { plan } => plan.actions.iter().map(|a| a.bytes()).sum(),
+        _ => 0,
+    };
+    (bytes as usize).max(1)
+}
+
+/// Ejecuta las `acciones` en orden sobre la calculadora `id`; cada una pasa por
+/// el worker del dispositivo como si se hubiera pedido sola. `destinos` son los
+/// de las acciones que no indican uno.
+///
+/// El progreso es el del lote entero, con cada accion pesando los bytes que
+/// transfiere (1 si no transfiere datos). Las acciones que fallan se tratan
+/// como los archivos de las operaciones sobre varios (ver `ops`).
+///
+/// Mientras corre cada accion, los detalles de `operacion` (los que llevan los
+/// eventos de progreso) son los de esa accion, con su posicion en el lote, y en
+/// `eventos` se emiten su inicio y su fin. Al terminar vuelven a ser los del
+/// lote.
+pub fn ejecutar(
+    id: DeviceId,
+    acciones: &[QueueAction],
+    destinos: &ActionDefaults,
+    operacion: &Operacion,
+    eventos: &dyn EventSink,
+    progreso: Progreso,
+) -> Result<Vec<BatchActionResult>, DLinkError> {
+    let pesos: Vec<usize> = acciones.iter().map(peso).collect();
+    let total: usize = pesos.iter().sum();
+
+    let detalles_lote = operacion.detalles();
+    let resultado = ejecutar_acciones(id, acciones, &pesos, destinos, operacion, eventos, progreso);
+    operacion.actualizar(|detalles| *detalles = detalles_lote);
+    if resultado.is_ok() {
+        let _ = progreso(0, total);
+    }
+    resultado
+}
+
+/// Ejecuta las acciones de un lote, cada una con su `peso` en el progreso
+fn ejecutar_acciones(
+    id: DeviceId,
+    acciones: &[QueueAction],
+    pesos: &[usize],
+    destinos: &ActionDefaults,
+    operacion: &Operacion,
+    eventos: &dyn EventSink,
+    progreso: Progreso,
+) -> Result<Vec<BatchActionResult>, DLinkError> {
+    let total: usize = pesos.iter().sum();
+
+    let mut resultados = Vec::with_capacity(acciones.len());
+    let mut completado = 0;
+    for (indice, (accion, &peso)) in acciones.iter().zip(pesos).enumerate() {
+        operacion.actualizar(|detalles| {
+            accion.describir(detalles);
+            detalles.batch_index = Some(indice);
+            detalles.batch_count = Some(acciones.len());
+        });
+        if progreso(total.saturating_sub(completado), total).is_break() {
+            return Err(DLinkError::from(TransportError::Cancelled).con_dispositivo(id));
+        }
+
+        notificar_inicio(eventos, id, operacion);
+        let resultado = queue::ejecutar_accion(id, accion, destinos, &mut |restante, tamano| {
+            let hecho = match tamano {
+                0 => 0,
+                tamano => (tamano - restante.min(tamano)) * peso / tamano,
+            };
+            progreso(total.saturating_sub(completado + hecho), total)
+        });
+        notificar_fin(&resultado, eventos, id, operacion);
+        completado += peso;
+
+        match resultado {
+            Err(error) if error.interrumpe() => return Err(error),
+            resultado => resultados.push(BatchActionResult {
+                index: indice,
+                error: resultado.err(),
+            }),
+        }
+    }
+
+    Ok(resultados)
+}
@@ -0,0 +1,603 @@
+//! Calculadora TI-Nspire emulada en memoria.
+//! Implementa `CalculatorTransport` sobre un sistema de archivos virtual para
+//! manejar la interfaz y los comandos sin hardware USB, con fallos inyectables.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libnspire::PID_CX2;
+
+use crate::error::{DLinkError, ErrorCode};
+use crate::transport::{CalculatorTransport, TransportError};
+use crate::types::{AddDevicePayload, DeviceInfo, FileInfo};
+use crate::{derivar_id_estable, Device, DeviceSource, DeviceState, DEVICES};
+
+/// Bus USB reservado para dispositivos emulados (libusb numera desde 1)
+pub const BUS_EMULADO: u8 = 0;
+
+/// Tamano de bloque con el que se reporta progreso en transferencias emuladas
+const TAMANO_BLOQUE: usize = 4096;
+
+/// Fallo que el emulador producira en una operacion futura
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InjectedFailure {
+    /// Desconexion durante la siguiente transferencia tras `after_bytes` bytes
+    NoDevice { after_bytes: usize },
+    /// La siguiente operacion responde que el dispositivo esta ocupado
+    Busy,
+    /// La siguiente escritura falla por falta de espacio
+    OutOfSpace,
+}
+
+/// Entrada del sistema de archivos virtual
+#[derive(Debug, Clone)]
+enum Nodo {
+    Directorio { date: u64 },
+    Archivo { datos: Vec<u8>, date: u64 },
+}
+
+struct EstadoEmulado {
+    info: DeviceInfo,
+    nodos: BTreeMap<String, Nodo>,
+    fallos: Vec<InjectedFailure>,
+    desconectado: bool,
+    retardo_por_bloque: Duration,
+}
+
+/// Calculadora emulada. Se comparte con `Arc` para poder inyectar fallos o
+/// inspeccionar el contenido mientras los comandos la usan.
+pub struct EmulatedDevice {
+    estado: Mutex<EstadoEmulado>,
+}
+
+/// Informacion de sistema por defecto de una TI-Nspire CX II emulada
+pub fn info_por_defecto() -> DeviceInfo {
+    DeviceInfo {
+        stable_id: String::new(),
+        name: "Nspire Emulada".to_string(),
+        free_storage: 90 * 1024 * 1024,
+        total_storage: 100 * 1024 * 1024,
+        free_ram: 48 * 1024 * 1024,
+        total_ram: 64 * 1024 * 1024,
+        battery: 80,
+        is_charging: false,
+        os_version: "5.4.0.259".to_string(),
+        boot1_version: "4.0.1.52".to_string(),
+        boot2_version: "5.4.0.259".to_string(),
+        hw_type: 3,
+        clock_speed: 198,
+        lcd_width: 320,
+        lcd_height: 240,
+        lcd_bpp: 16,
+        lcd_sample_mode: 1,
+    }
+}
+
+/// Segundos desde la epoca Unix, el formato de `FileInfo::date`
+fn ahora() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Normaliza una ruta de calculadora a la forma `/a/b` (la raiz es `/`)
+fn normalizar(path: &str) -> String {
+    let partes: Vec<&str> = path.split('/').filter(|p| !p.is_empty()).collect();
+    format!("/{}", partes.join("/"))
+}
+
+/// Ruta del directorio padre de una ruta normalizada
+fn padre(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(indice) => &path[..indice],
+    }
+}
+
+/// Indica si `path` esta dentro del directorio `dir` (sin incluirlo)
+fn es_descendiente(path: &str, dir: &str) -> bool {
+    if dir == "/" {
+        path != "/"
+    } else {
+        path.len() > dir.len() && path.starts_with(dir) && path.as_bytes()[dir.len()] == b'/'
+    }
+}
+
+impl EstadoEmulado {
+    fn es_directorio(&self, path: &str) -> bool {
+        path == "/" || matches!(self.nodos.get(path), Some(Nodo::Directorio { .. }))
+    }
+
+    /// Aplica el estado de conexion y los fallos que no dependen de bytes
+    fn comprobar(&mut self, es_escritura: bool) -> Result<(), TransportError> {
+        if self.desconectado {
+            return Err(TransportError::NoDevice);
+        }
+        if let Some(indice) = self.fallos.iter().position(|f| *f == InjectedFailure::Busy) {
+            self.fallos.remove(indice);
+            return Err(TransportError::Busy);
+        }
+        if es_escritura {
+            if let Some(indice) = self
+                .fallos
+                .iter()
+                .position(|f| *f == InjectedFailure::OutOfSpace)
+            {
+                self.fallos.remove(indice);
+                return Err(TransportError::NoSpace);
+            }
+        }
+        Ok(())
+    }
+
+    /// Toma la desconexion programada para la transferencia en curso, si hay
+    fn tomar_desconexion(&mut self) -> Option<usize> {
+        let indice = self
+            .fallos
+            .iter()
+            .position(|f| matches!(f, InjectedFailure::NoDevice { .. }))?;
+        match self.fallos.remove(indice) {
+            InjectedFailure::NoDevice { after_bytes } => Some(after_bytes),
+            _ => None,
+        }
+    }
+}
+
+impl EmulatedDevice {
+    /// Crea una calculadora vacia con la informacion dada
+    pub fn new(info: DeviceInfo) -> Self {
+        EmulatedDevice {
+            estado: Mutex::new(EstadoEmulado {
+                info,
+                nodos: BTreeMap::new(),
+                fallos: Vec::new(),
+                desconectado: false,
+                retardo_por_bloque: Duration::ZERO,
+            }),
+        }
+    }
+
+    /// Crea una calculadora con algunos documentos de ejemplo, util para demos
+    pub fn con_contenido_de_ejemplo() -> Self {
+        let emulado = EmulatedDevice::new(info_por_defecto());
+        let fecha = ahora();
+
+        emulado.agregar_directorio("/Examples", fecha);
+        emulado.agregar_directorio("/MyLib", fecha);
+        emulado.agregar_directorio("/Clase/Algebra", fecha);
+        emulado.agregar_archivo("/Examples/Welcome.tns", vec![0x2a; 12 * 1024], fecha);
+        emulado.agregar_archivo("/MyLib/numtheory.tns", vec![0x2a; 3 * 1024], fecha);
+        emulado.agregar_archivo("/Clase/Algebra/Tarea1.tns", vec![0x2a; 48 * 1024], fecha);
+        emulado.agregar_archivo("/Clase/Algebra/Tarea2.tns", vec![0x2a; 256 * 1024], fecha);
+
+        emulado
+    }
+
+    fn bloquear(&self) -> MutexGuard<'_, EstadoEmulado> {
+        // Un panico en una prueba no debe inutilizar el emulador
+        self.estado.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Crea un directorio y todos sus padres faltantes
+    pub fn agregar_directorio(&self, path: &str, date: u64) {
+        let path = normalizar(path);
+        let mut estado = self.bloquear();
+
+        let mut actual = String::new();
+        for parte in path.split('/').filter(|p| !p.is_empty()) {
+            actual.push('/');
+            actual.push_str(parte);
+            estado
+                .nodos
+                .entry(actual.clone())
+                .or_insert(Nodo::Directorio { date });
+        }
+    }
+
+    /// Agrega (o reemplaza) un archivo, creando los directorios padres.
+    /// No descuenta del almacenamiento libre configurado.
+    pub fn agregar_archivo(&self, path: &str, datos: Vec<u8>, date: u64) {
+        let path = normalizar(path);
+        self.agregar_directorio(padre(&path), date);
+        self.bloquear()
+            .nodos
+            .insert(path, Nodo::Archivo { datos, date });
+    }
+
+    /// Devuelve el contenido de un archivo, si existe
+    pub fn contenido(&self, path: &str) -> Option<Vec<u8>> {
+        match self.bloquear().nodos.get(&normalizar(path)) {
+            Some(Nodo::Archivo { datos, .. }) => Some(datos.clone()),
+            _ => None,
+        }
+    }
+
+    /// Programa un fallo para una operacion futura
+    pub fn programar_fallo(&self, fallo: InjectedFailure) {
+        self.bloquear().fallos.push(fallo);
+    }
+
+    /// Simula que la calculadora se vuelve a conectar tras una desconexion
+    pub fn reconectar(&self) {
+        self.bloquear().desconectado = false;
+    }
+
+    /// Configura el almacenamiento libre y total reportado
+    pub fn establecer_almacenamiento(&self, libre: u64, total: u64) {
+        let mut estado = self.bloquear();
+        estado.info.free_storage = libre;
+        estado.info.total_storage = total;
+    }
+
+    /// Configura el estado de la bateria reportado
+    pub fn establecer_bateria(&self, porcentaje: u8, cargando: bool) {
+        let mut estado = self.bloquear();
+        estado.info.battery = porcentaje;
+        estado.info.is_charging = cargando;
+    }
+
+    /// Reemplaza toda la informacion de sistema reportada
+    pub fn establecer_info(&self, info: DeviceInfo) {
+        self.bloquear().info = info;
+    }
+
+    /// Pausa aplicada por cada bloque transferido, para que el progreso sea visible
+    pub fn establecer_retardo(&self, retardo: Duration) {
+        self.bloquear().retardo_por_bloque = retardo;
+    }
+
+    /// Recorre `total` bytes en bloques reportando progreso.
+    /// Devuelve `NoDevice` si hay una desconexion programada dentro del rango.
+    fn transferir(
+        &self,
+        total: usize,
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError> {
+        let (corte, retardo) = {
+            let mut estado = self.bloquear();
+            (estado.tomar_desconexion(), estado.retardo_por_bloque)
+        };
+
+        let mut enviado = 0;
+        loop {
+            if let Some(corte) = corte {
+                if enviado >= corte {
+                    self.bloquear().desconectado = true;
+                    return Err(TransportError::NoDevice);
+                }
+            }
+            if enviado >= total {
+                return Ok(());
+            }
+
+            enviado = (enviado + TAMANO_BLOQUE).min(total);
+            if !retardo.is_zero() {
+                std::thread::sleep(retardo);
+            }
+            if progreso(total - enviado).is_break() {
+                return Err(TransportError::Cancelled);
+            }
+        }
+    }
+}
+
+impl CalculatorTransport for Arc<EmulatedDevice> {
+    fn info(&self) -> Result<DeviceInfo, TransportError> {
+        let mut estado = self.bloquear();
+        estado.comprobar(false)?;
+        Ok(estado.info.clone())
+    }
+
+    fn list_dir(&self, path: &str) -> Result<Vec<FileInfo>, TransportError> {
+        let path = normalizar(path);
+        let mut estado = self.bloquear();
+        estado.comprobar(false)?;
+
+        if !estado.es_directorio(&path) {
+            return Err(TransportError::NotFound);
+        }
+
+        Ok(estado
+            .nodos
+            .iter()
+            .filter(|(ruta, _)| padre(ruta) == path && ruta.as_str() != "/")
+            .map(|(ruta, nodo)| {
+                let nombre = ruta.rsplit('/').next().unwrap_or_default().to_string();
+                match nodo {
+                    Nodo::Directorio { date } => FileInfo {
+                        path: nombre,
+                        is_dir: true,
+                        date: *date,
+                        size: 0,
+                    },
+                    Nodo::Archivo { datos, date } => FileInfo {
+                        path: nombre,
+                        is_dir: false,
+                        date: *date,
+                        size: datos.len() as u64,
+                    },
+                }
+            })
+            .collect())
+    }
+
+    fn read_file(
+        &self,
+        path: &str,
+        size: u64,
+        destino: &mut dyn Write,
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError> {
+        let path = normalizar(path);
+        let datos = {
+            let mut estado = self.bloquear();
+            estado.comprobar(false)?;
+            match estado.nodos.get(&path) {
+                Some(Nodo::Archivo { datos, .. }) => datos.clone(),
+                _ => return Err(TransportError::NotFound),
+            }
+        };
+
+        // Entregar cada bloque a medida que "llega"
+        let total = datos.len().min(size as usize);
+        let mut escritos = 0;
+        let mut fallo_local = None;
+        let resultado = self.transferir(total, &mut |restante| {
+            let hasta = total - restante;
+            if let Err(error) = destino.write_all(&datos[escritos..hasta]) {
+                fallo_local = Some(error);
+                return ControlFlow::Break(());
+            }
+            escritos = hasta;
+            progreso(restante)
+        });
+
+        match fallo_local {
+            Some(error) => Err(error.into()),
+            None => resultado,
+        }
+    }
+
+    fn write_file(
+        &self,
+        path: &str,
+        datos: &[u8],
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError> {
+        let path = normalizar(path);
+        {
+            let mut estado = self.bloquear();
+            estado.comprobar(true)?;
+
+            if !estado.es_directorio(padre(&path)) {
+                return Err(TransportError::NotFound);
+            }
+            let tamano_anterior = match estado.nodos.get(&path) {
+                Some(Nodo::Archivo { datos, .. }) => datos.len() as u64,
+                Some(Nodo::Directorio { .. }) => return Err(TransportError::Exists),
+                None => 0,
+            };
+            if datos.len() as u64 > estado.info.free_storage + tamano_anterior {
+                return Err(TransportError::NoSpace);
+            }
+        }
+
+        self.transferir(datos.len(), progreso)?;
+
+        let mut estado = self.bloquear();
+        let tamano_anterior = match estado.nodos.get(&path) {
+            Some(Nodo::Archivo { datos, .. }) => datos.len() as u64,
+            _ => 0,
+        };
+        estado.info.free_storage =
+            (estado.info.free_storage + tamano_anterior).saturating_sub(datos.len() as u64);
+        estado.nodos.insert(
+            path,
+            Nodo::Archivo {
+                datos: datos.to_vec(),
+                date: ahora(),
+            },
+        );
+        Ok(())
+    }
+
+    fn send_os(
+        &self,
+        datos: &[u8],
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError> {
+        self.bloquear().comprobar(true)?;
+        // Como en la calculadora real, el envio no se interrumpe
+        self.transferir(datos.len(), &mut |restante| {
+            let _ = progreso(restante);
+            ControlFlow::Continue(())
+        })
+    }
+
+    fn delete_file(&self, path: &str) -> Result<(), TransportError> {
+        let path = normalizar(path);
+        let mut estado = self.bloquear();
+        estado.comprobar(false)?;
+
+        let liberado = match estado.nodos.get(&path) {
+            Some(Nodo::Archivo { datos, .. }) => datos.len() as u64,
+            Some(Nodo::Directorio { .. }) => {
+                return Err(TransportError::Other(
+                    "La ruta es un directorio".to_string(),
+                ))
+            }
+            None => return Err(TransportError::NotFound),
+        };
+
+        estado.info.free_storage += liberado;
+        estado.nodos.remove(&path);
+        Ok(())
+    }
+
+    fn delete_dir(&self, path: &str) -> Result<(), TransportError> {
+        let path = normalizar(path);
+        let mut estado = self.bloquear();
+        estado.comprobar(false)?;
+
+        if path == "/" || !estado.es_directorio(&path) {
+            return Err(TransportError::NotFound);
+        }
+        // Igual que la calculadora real, no elimina directorios con contenido
+        if estado.nodos.keys().any(|ruta| es_descendiente(ruta, &path)) {
+            return Err(TransportError::Other(
+                "El directorio no esta vacio".to_string(),
+            ));
+        }
+
+        estado.nodos.remove(&path);
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(), TransportError> {
+        let path = normalizar(path);
+        let mut estado = self.bloquear();
+        estado.comprobar(false)?;
+
+        if path == "/" || estado.nodos.contains_key(&path) {
+            return Err(TransportError::Exists);
+        }
+        if !estado.es_directorio(padre(&path)) {
+            return Err(TransportError::NotFound);
+        }
+
+        estado
+            .nodos
+            .insert(path, Nodo::Directorio { date: ahora() });
+        Ok(())
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> Result<(), TransportError> {
+        let (src, dest) = (normalizar(src), normalizar(dest));
+        let mut estado = self.bloquear();
+        estado.comprobar(false)?;
+
+        if !estado.nodos.contains_key(&src) {
+            return Err(TransportError::NotFound);
+        }
+        if estado.nodos.contains_key(&dest) || dest == "/" {
+            return Err(TransportError::Exists);
+        }
+        if !estado.es_directorio(padre(&dest)) || es_descendiente(&dest, &src) {
+            return Err(TransportError::NotFound);
+        }
+
+        // Mover la entrada y todo lo que contenga
+        let rutas: Vec<String> = estado
+            .nodos
+            .keys()
+            .filter(|ruta| **ruta == src || es_descendiente(ruta, &src))
+            .cloned()
+            .collect();
+        for ruta in rutas {
+            if let Some(nodo) = estado.nodos.remove(&ruta) {
+                let nueva = format!("{}{}", dest, &ruta[src.len()..]);
+                estado.nodos.insert(nueva, nodo);
+            }
+        }
+        Ok(())
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> Result<(), TransportError> {
+        let (src, dest) = (normalizar(src), normalizar(dest));
+        let mut estado = self.bloquear();
+        estado.comprobar(true)?;
+
+        let datos = match estado.nodos.get(&src) {
+            Some(Nodo::Archivo { datos, .. }) => datos.clone(),
+            Some(Nodo::Directorio { .. }) => {
+                return Err(TransportError::Other(
+                    "La ruta es un directorio".to_string(),
+                ))
+            }
+            None => return Err(TransportError::NotFound),
+        };
+        if estado.nodos.contains_key(&dest) {
+            return Err(TransportError::Exists);
+        }
+        if !estado.es_directorio(padre(&dest)) {
+            return Err(TransportError::NotFound);
+        }
+        if datos.len() as u64 > estado.info.free_storage {
+            return Err(TransportError::NoSpace);
+        }
+
+        estado.info.free_storage -= datos.len() as u64;
+        estado.nodos.insert(
+            dest,
+            Nodo::Archivo {
+                datos,
+                date: ahora(),
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Registra una calculadora emulada en `DEVICES` como si se hubiera conectado.
+/// Devuelve el payload equivalente al evento `addDevice`, o un error si ya no
+/// quedan direcciones libres en el bus emulado.
+pub fn registrar_emulado(
+    emulado: Arc<EmulatedDevice>,
+    nombre: String,
+) -> Result<AddDevicePayload, DLinkError> {
+    // Las emuladas reportan una serie propia para tener un id estable como las reales
+    registrar(emulado, nombre, |address| {
+        Some(format!("EMU{:03}", address))
+    })
+}
+
+/// Como `registrar_emulado`, pero la calculadora no reporta numero de serie,
+/// asi que su id estable sale del nombre de `Info` al abrirla
+pub fn registrar_emulado_sin_serie(
+    emulado: Arc<EmulatedDevice>,
+    nombre: String,
+) -> Result<AddDevicePayload, DLinkError> {
+    registrar(emulado, nombre, |_| None)
+}
+
+fn registrar(
+    emulado: Arc<EmulatedDevice>,
+    nombre: String,
+    serie: impl FnOnce(u8) -> Option<String>,
+) -> Result<AddDevicePayload, DLinkError> {
+    let mut mapa = DEVICES.write().unwrap_or_else(|e| e.into_inner());
+
+    let address = (1..=u8::MAX)
+        .find(|address| !mapa.contains_key(&(BUS_EMULADO, *address)))
+        .ok_or_else(|| {
+            DLinkError::app(
+                ErrorCode::Usb,
+                "No quedan direcciones libres para otra calculadora emulada",
+            )
+        })?;
+
+    let serie = serie(address);
+    let dispositivo = Device {
+        nombre,
+        id_estable: derivar_id_estable(
+            PID_CX2,
+            serie.as_deref(),
+            None,
+            &format!("{}-{}", BUS_EMULADO, address),
+        ),
+        id_producto: PID_CX2,
+        serie,
+        origen: DeviceSource::Emulated(emulado),
+        estado: DeviceState::Closed,
+        necesita_drivers: false,
+        es_cx_ii: true,
+    };
+    let payload = dispositivo.payload((BUS_EMULADO, address));
+    mapa.insert((BUS_EMULADO, address), dispositivo);
+
+    Ok(payload)
+}
@@ -0,0 +1,193 @@
+//! Error estructurado del nucleo.
+//! Se serializa tal cual hacia el frontend y los scripts: `code` es estable y
+//! apto para decidir por programa, `message` es solo para mostrar. El diario
+//! de la cola lo guarda y lo vuelve a leer con el mismo formato.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::transport::TransportError;
+use crate::types::DeviceId;
+
+/// Codigo estable que identifica la causa de un error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// No hay ningun dispositivo registrado con ese identificador
+    DeviceNotFound,
+    /// El dispositivo existe pero no esta abierto
+    DeviceClosed,
+    /// El dispositivo ya estaba abierto
+    DeviceAlreadyOpen,
+    /// El sistema no tiene el driver necesario (WinUSB en Windows)
+    NeedsDrivers,
+    /// La calculadora se desconecto durante la operacion
+    Disconnected,
+    /// La calculadora o el puerto USB estan ocupados
+    Busy,
+    /// Se agoto el tiempo de espera
+    Timeout,
+    /// La operacion se cancelo a pedido del usuario
+    Cancelled,
+    /// La operacion no se puede cancelar una vez iniciada
+    NotCancellable,
+    /// La accion quedo a medias y no se repite sola; se reintenta a pedido
+    Interrupted,
+    /// No queda espacio en el destino
+    NoSpace,
+    /// La ruta no existe
+    NotFound,
+    /// La ruta ya existe
+    AlreadyExists,
+    /// El archivo cambio desde que se listo
+    FileChanged,
+    /// El archivo supera el tamano maximo permitido
+    FileTooLarge,
+    /// El sistema denego el acceso
+    PermissionDenied,
+    /// Un argumento recibido no es valido
+    InvalidArgument,
+    /// Error del protocolo de la calculadora
+    Protocol,
+    /// Error del subsistema USB
+    Usb,
+    /// Error de entrada/salida local
+    Io,
+    /// Error interno de la aplicacion
+    Internal,
+}
+
+/// Capa en la que se origino el error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorSource {
+    /// Protocolo de la calculadora (libnspire o el emulador)
+    Libnspire,
+    /// Acceso al bus USB
+    Rusb,
+    /// Sistema de archivos local
+    Io,
+    /// Validaciones y estado propios de D-Link
+    App,
+}
+
+/// Error devuelto por las operaciones del nucleo y los comandos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DLinkError {
+    pub code: ErrorCode,
+    pub source: ErrorSource,
+    pub device: Option<DeviceId>,
+    pub path: Option<String>,
+    pub message: String,
+}
+
+impl DLinkError {
+    pub fn new(code: ErrorCode, source: ErrorSource, message: impl Into<String>) -> Self {
+        DLinkError {
+            code,
+            source,
+            device: None,
+            path: None,
+            message: message.into(),
+        }
+    }
+
+    /// Error de estado o validacion de la propia aplicacion
+    pub fn app(code: ErrorCode, message: impl Into<String>) -> Self {
+        DLinkError::new(code, ErrorSource::App, message)
+    }
+
+    /// Error inesperado (por ejemplo, un lock envenenado)
+    pub fn interno(error: impl fmt::Display) -> Self {
+        DLinkError::app(ErrorCode::Internal, error.to_string())
+    }
+
+    /// Asocia el error a un dispositivo, si no lo estaba ya
+    pub fn con_dispositivo(mut self, id: DeviceId) -> Self {
+        self.device.get_or_insert(id);
+        self
+    }
+
+    /// Asocia el error a una ruta, si no lo estaba ya
+    pub fn con_ruta(mut self, path: impl fmt::Display) -> Self {
+        if self.path.is_none() {
+            self.path = Some(path.to_string());
+        }
+        self
+    }
+
+    /// Indica si el error proviene de una desconexion del dispositivo
+    pub fn es_desconexion(&self) -> bool {
+        self.code == ErrorCode::Disconnected
+    }
+
+    /// Indica si el error corta una operacion sobre varios archivos: la
+    /// cancelacion o la desconexion. Cualquier otro solo afecta a su archivo.
+    pub fn interrumpe(&self) -> bool {
+        self.code == ErrorCode::Cancelled || self.es_desconexion()
+    }
+}
+
+impl fmt::Display for DLinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{} ({})", self.message, path),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for DLinkError {}
+
+impl From<TransportError> for DLinkError {
+    fn from(error: TransportError) -> Self {
+        let code = match error {
+            TransportError::NoDevice => ErrorCode::Disconnected,
+            TransportError::Busy => ErrorCode::Busy,
+            TransportError::Timeout => ErrorCode::Timeout,
+            TransportError::NoSpace => ErrorCode::NoSpace,
+            TransportError::NotFound => ErrorCode::NotFound,
+            TransportError::Exists => ErrorCode::AlreadyExists,
+            TransportError::Cancelled => ErrorCode::Cancelled,
+            TransportError::TooLarge(_) => ErrorCode::FileTooLarge,
+            TransportError::Local(kind, mensaje) => {
+                return DLinkError::from(std::io::Error::new(kind, mensaje))
+            }
+            TransportError::Other(_) => ErrorCode::Protocol,
+        };
+        DLinkError::new(code, ErrorSource::Libnspire, error.to_string())
+    }
+}
+
+impl From<rusb::Error> for DLinkError {
+    fn from(error: rusb::Error) -> Self {
+        let code = match error {
+            rusb::Error::Access => ErrorCode::PermissionDenied,
+            rusb::Error::NoDevice => ErrorCode::Disconnected,
+            rusb::Error::NotFound => ErrorCode::DeviceNotFound,
+            rusb::Error::Busy => ErrorCode::Busy,
+            rusb::Error::Timeout => ErrorCode::Timeout,
+            rusb::Error::NotSupported => ErrorCode::NeedsDrivers,
+            _ => ErrorCode::Usb,
+        };
+        DLinkError::new(code, ErrorSource::Rusb, error.to_string())
+    }
+}
+
+impl From<std::io::Error> for DLinkError {
+    fn from(error: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+
+        let code = match error.kind() {
+            ErrorKind::NotFound => ErrorCode::NotFound,
+            ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+            ErrorKind::AlreadyExists => ErrorCode::AlreadyExists,
+            ErrorKind::StorageFull => ErrorCode::NoSpace,
+            ErrorKind::InvalidInput => ErrorCode::InvalidArgument,
+            _ => ErrorCode::Io,
+        };
+        DLinkError::new(code, ErrorSource::Io, error.to_string())
+    }
+}
@@ -0,0 +1,204 @@
+//! Eventos que el nucleo envia a la interfaz.
+//! Cada interfaz implementa `EventSink` (la app Tauri los reenvia al webview,
+//! otras herramientas pueden usar un canal).
+
+use std::ops::ControlFlow;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use crate::error::DLinkError;
+use crate::operations::Operacion;
+use crate::ops::Progreso;
+use crate::queue::DeviceQueue;
+use crate::types::{AddDevicePayload, DeviceId, OperationEvent, ProgressUpdate};
+
+/// Evento emitido por el nucleo
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// Se detecto una calculadora
+    AddDevice(AddDevicePayload),
+    /// Una calculadora se desconecto
+    RemoveDevice(DeviceId),
+    /// Avance de una operacion larga
+    Progress(ProgressUpdate),
+    /// Cambio en la cola de operaciones de una calculadora
+    QueueChanged(DeviceQueue),
+    /// Empezo una operacion larga, o una accion dentro de un lote
+    OperationStarted(OperationEvent),
+    /// Termino bien una operacion larga o una accion de un lote
+    OperationFinished(OperationEvent),
+    /// Fallo o se cancelo una operacion larga o una accion de un lote
+    OperationFailed(OperationEvent),
+}
+
+impl Event {
+    /// Nombre con el que el frontend escucha el evento
+    pub fn nombre(&self) -> &'static str {
+        match self {
+            Event::AddDevice(_) => "addDevice",
+            Event::RemoveDevice(_) => "removeDevice",
+            Event::Progress(_) => "progress",
+            Event::QueueChanged(_) => "queueChanged",
+            Event::OperationStarted(_) => "operationStarted",
+            Event::OperationFinished(_) => "operationFinished",
+            Event::OperationFailed(_) => "operationFailed",
+        }
+    }
+}
+
+/// Destino de los eventos del nucleo
+pub trait EventSink: Send + Sync {
+    fn emitir(&self, evento: Event);
+}
+
+/// Permite recibir los eventos por un canal
+impl EventSink for Sender<Event> {
+    fn emitir(&self, evento: Event) {
+        let _ = self.send(evento);
+    }
+}
+
+/// Descarta los eventos; para las interfaces que no los muestran
+pub struct SinEventos;
+
+impl EventSink for SinEventos {
+    fn emitir(&self, _evento: Event) {}
+}
+
+/// Emite `RemoveDevice` si el resultado de una operacion indica desconexion
+pub fn notificar_desconexion<T>(
+    resultado: &Result<T, DLinkError>,
+    id: DeviceId,
+    eventos: &dyn EventSink,
+) {
+    if let Err(error) = resultado {
+        if error.es_desconexion() {
+            eventos.emitir(Event::RemoveDevice(id));
+        }
+    }
+}
+
+/// Tiempo minimo entre dos reportes de progreso, aunque avance mas del 1%
+const INTERVALO_MINIMO: Duration = Duration::from_millis(100);
+
+/// Tiempo tras el que se reporta cualquier avance, aunque no llegue al 1%, para
+/// que la velocidad y el tiempo restante sigan actualizandose
+const INTERVALO_MAXIMO: Duration = Duration::from_secs(1);
+
+/// Constante de tiempo, en segundos, del promedio exponencial de la velocidad:
+/// las muestras de hace unos segundos pesan poco
+const SUAVIZADO_VELOCIDAD: f64 = 3.0;
+
+/// Crea un callback para reportar progreso de la operacion `operacion`.
+/// Optimizado: reporta cuando avanza al menos un 1% y pasaron 100 ms desde el
+/// ultimo reporte, cuando pasa un segundo con cualquier avance, y al finalizar.
+/// El primer reporte se emite siempre, para que la interfaz conozca el id de la
+/// operacion, y el callback pide cortar la operacion si se cancelo.
+///
+/// Cada reporte lleva el tiempo transcurrido, la velocidad promediada en los
+/// ultimos segundos y el tiempo restante estimado con ella.
+pub fn crear_reporter_progreso<'a>(
+    eventos: &'a dyn EventSink,
+    id: DeviceId,
+    operacion: &'a Operacion,
+) -> impl FnMut(usize, usize) -> ControlFlow<()> + 'a {
+    let inicio = Instant::now();
+    let mut ultimo_reportado: Option<(usize, Instant)> = None;
+    let mut velocidad: Option<f64> = None;
+
+    move |restante, total| {
+        let ahora = Instant::now();
+        let umbral = (total / 100).max(1); // 1% del total, minimo 1 byte
+        let (anterior, momento) = ultimo_reportado.unwrap_or((total, inicio));
+        let diferencia = anterior.saturating_sub(restante);
+        let intervalo = ahora.duration_since(momento);
+
+        // Reportar si es el primero, si hay cambio suficiente para el tiempo
+        // transcurrido o si es el final
+        if ultimo_reportado.is_none()
+            || (diferencia >= umbral && intervalo >= INTERVALO_MINIMO)
+            || (diferencia > 0 && intervalo >= INTERVALO_MAXIMO)
+            || restante == 0
+        {
+            let segundos = intervalo.as_secs_f64();
+            if ultimo_reportado.is_some() && segundos > 0.0 {
+                let muestra = diferencia as f64 / segundos;
+                let alfa = 1.0 - (-segundos / SUAVIZADO_VELOCIDAD).exp();
+                velocidad = Some(match velocidad {
+                    Some(v) => v + alfa * (muestra - v),
+                    None => muestra,
+                });
+            }
+            ultimo_reportado = Some((restante, ahora));
+
+            let rate = velocidad.unwrap_or(0.0);
+            eventos.emitir(Event::Progress(ProgressUpdate {
+                id,
+                operation: operacion.detalles(),
+                remaining: restante,
+                total,
+                rate,
+                elapsed_ms: ahora.duration_since(inicio).as_millis() as u64,
+                eta_ms: match restante {
+                    0 => Some(0),
+                    _ if rate > 0.0 => Some((restante as f64 / rate * 1000.0) as u64),
+                    _ => None,
+                },
+            }));
+        }
+
+        if operacion.cancelada() {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+/// Emite el inicio de la operacion, con lo que hace en este momento
+pub fn notificar_inicio(eventos: &dyn EventSink, id: DeviceId, operacion: &Operacion) {
+    eventos.emitir(Event::OperationStarted(OperationEvent {
+        id,
+        operation: operacion.detalles(),
+        error: None,
+    }));
+}
+
+/// Emite el fin o el fallo de la operacion segun `resultado`
+pub fn notificar_fin<T>(
+    resultado: &Result<T, DLinkError>,
+    eventos: &dyn EventSink,
+    id: DeviceId,
+    operacion: &Operacion,
+) {
+    let operation = operacion.detalles();
+    eventos.emitir(match resultado {
+        Ok(_) => Event::OperationFinished(OperationEvent {
+            id,
+            operation,
+            error: None,
+        }),
+        Err(error) => Event::OperationFailed(OperationEvent {
+            id,
+            operation,
+            error: Some(error.clone()),
+        }),
+    });
+}
+
+/// Ejecuta `trabajo` como la operacion `operacion`: emite su inicio, su
+/// progreso con `crear_reporter_progreso` y su fin o su fallo, y
+/// `RemoveDevice` si el dispositivo se desconecto.
+pub fn ejecutar_operacion<T>(
+    eventos: &dyn EventSink,
+    id: DeviceId,
+    operacion: &Operacion,
+    trabajo: impl FnOnce(Progreso) -> Result<T, DLinkError>,
+) -> Result<T, DLinkError> {
+    notificar_inicio(eventos, id, operacion);
+    let resultado =
+        operacion.ejecutar(|| trabajo(&mut crear_reporter_progreso(eventos, id, operacion)));
+    notificar_fin(&resultado, eventos, id, operacion);
+    notificar_desconexion(&resultado, id, eventos);
+    resultado
+}
@@ -0,0 +1,157 @@
+//! Nucleo de D-Link: registro de dispositivos, transporte USB y operaciones
+//! sobre calculadoras TI-Nspire, sin dependencia de Tauri.
+//!
+//! La aplicacion de escritorio, la CLI y cualquier otra interfaz dependen de
+//! este crate y reciben los eventos a traves de un `EventSink`.
+
+#![allow(clippy::type_complexity)]
+
+use std::sync::{Arc, RwLock};
+
+use hashbrown::HashMap;
+use rusb::GlobalContext;
+
+pub mod backup;
+pub mod batch;
+pub mod emulator;
+pub mod error;
+pub mod events;
+pub mod operations;
+pub mod ops;
+pub mod queue;
+pub mod repository;
+pub mod sync;
+pub mod transport;
+pub mod types;
+pub mod usb;
+pub mod worker;
+
+use types::{AddDevicePayload, DeviceId, DeviceInfo};
+use worker::DeviceWorker;
+
+// Estado global de dispositivos conectados
+lazy_static::lazy_static! {
+    pub static ref DEVICES: RwLock<HashMap<(u8, u8), Device>> = RwLock::new(HashMap::new());
+}
+
+/// Convierte un Info de libnspire a nuestro DeviceInfo
+fn convertir_info(info: &libnspire::info::Info) -> DeviceInfo {
+    use libnspire::info::{Battery, HardwareType};
+
+    // El enum Battery no representa porcentaje real, solo estados
+    // Powered = conectado a corriente, Ok = bateria bien, Low = bateria baja
+    let battery_percent = match info.battery {
+        Battery::Powered => 100,
+        Battery::Ok => 80,
+        Battery::Low => 20,
+        Battery::Unknown(_) => 50, // Valor desconocido, usar 50% por defecto
+    };
+
+    let hw_type_num = match info.hw_type {
+        HardwareType::Cas => 0,
+        HardwareType::NonCas => 1,
+        HardwareType::CasCx => 2,
+        HardwareType::NonCasCx => 3,
+        HardwareType::Unknown(v) => v,
+    };
+
+    DeviceInfo {
+        stable_id: String::new(),
+        name: info.name.clone(),
+        free_storage: info.free_storage,
+        total_storage: info.total_storage,
+        free_ram: info.free_ram,
+        total_ram: info.total_ram,
+        battery: battery_percent,
+        is_charging: info.is_charging,
+        os_version: info.version.to_string(),
+        boot1_version: info.boot1_version.to_string(),
+        boot2_version: info.boot2_version.to_string(),
+        hw_type: hw_type_num,
+        clock_speed: info.clock_speed,
+        lcd_width: info.lcd.width,
+        lcd_height: info.lcd.height,
+        lcd_bpp: info.lcd.bpp,
+        lcd_sample_mode: info.lcd.sample_mode,
+    }
+}
+
+/// Estado de conexion del dispositivo.
+/// Un dispositivo abierto tiene su propio hilo de trabajo, dueno del transporte.
+pub enum DeviceState {
+    Open(Arc<DeviceWorker>, DeviceInfo),
+    Closed,
+}
+
+/// Origen de un dispositivo: una calculadora USB real o una emulada en memoria
+#[derive(Clone)]
+pub enum DeviceSource {
+    Usb(Arc<rusb::Device<GlobalContext>>),
+    Emulated(Arc<emulator::EmulatedDevice>),
+}
+
+/// Datos de un dispositivo TI-Nspire
+pub struct Device {
+    pub nombre: String,
+    /// Identificador que sobrevive a reconexiones, ver `derivar_id_estable`
+    pub id_estable: String,
+    /// Product ID del descriptor USB
+    pub id_producto: u16,
+    /// Numero de serie del descriptor USB, si la calculadora lo reporta
+    pub serie: Option<String>,
+    pub origen: DeviceSource,
+    pub estado: DeviceState,
+    pub necesita_drivers: bool,
+    pub es_cx_ii: bool,
+}
+
+impl Device {
+    /// Payload del evento `addDevice` para este dispositivo
+    pub fn payload(&self, clave: (u8, u8)) -> AddDevicePayload {
+        AddDevicePayload {
+            id: DeviceId {
+                bus_number: clave.0,
+                address: clave.1,
+            },
+            stable_id: self.id_estable.clone(),
+            name: self.nombre.clone(),
+            is_cx_ii: self.es_cx_ii,
+            needs_drivers: self.necesita_drivers,
+        }
+    }
+}
+
+/// Deriva el identificador estable de una calculadora.
+///
+/// El bus y la direccion USB cambian en cada reconexion y tras el reinicio de
+/// una instalacion de sistema operativo, asi que se usa el Product ID junto al
+/// numero de serie del descriptor. Si la calculadora no reporta serie se usa el
+/// nombre de `Info`, que solo se conoce al abrirla; hasta entonces se recurre a
+/// la ubicacion fisica (`ubicacion`), que se mantiene al reconectar en el mismo puerto.
+/// Dos calculadoras sin serie pueden tener el mismo nombre: ver
+/// `desambiguar_id_estable`.
+pub fn derivar_id_estable(
+    id_producto: u16,
+    serie: Option<&str>,
+    nombre_info: Option<&str>,
+    ubicacion: &str,
+) -> String {
+    match (serie, nombre_info) {
+        (Some(serie), _) => format!("{:04x}-{}", id_producto, serie),
+        (None, Some(nombre)) => format!("{:04x}-nombre-{}", id_producto, nombre),
+        (None, None) => format!("{:04x}-puerto-{}", id_producto, ubicacion),
+    }
+}
+
+/// Devuelve `base` si ningun otro dispositivo conectado lo usa (`ocupado`), o
+/// `base` con el primer sufijo libre (`-2`, `-3`...). La calculadora que se
+/// abrio primero conserva el id sin sufijo.
+pub fn desambiguar_id_estable(base: String, ocupado: impl Fn(&str) -> bool) -> String {
+    if !ocupado(&base) {
+        return base;
+    }
+    (2..)
+        .map(|numero| format!("{}-{}", base, numero))
+        .find(|candidato| !ocupado(candidato))
+        .unwrap_or(base)
+}
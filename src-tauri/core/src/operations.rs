@@ -0,0 +1,157 @@
+//! Registro de operaciones largas en curso.
+//! Cada transferencia recibe un `OperationId` con el que la interfaz puede
+//! pedir su cancelacion; el callback de progreso consulta la marca y corta la
+//! operacion en el siguiente bloque. La operacion tambien guarda que hace
+//! (`OperationDetails`), que acompana a todos sus eventos, y los trabajos que
+//! envia al hilo del dispositivo quedan a su nombre (ver `Operacion::ejecutar`).
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use hashbrown::HashMap;
+
+use crate::error::{DLinkError, ErrorCode};
+use crate::types::{OperationDetails, OperationKind};
+
+/// Identificador de una operacion larga
+pub type OperationId = u64;
+
+static SIGUIENTE_ID: AtomicU64 = AtomicU64::new(1);
+
+lazy_static::lazy_static! {
+    static ref OPERACIONES: Mutex<HashMap<OperationId, Arc<Estado>>> =
+        Mutex::new(HashMap::new());
+}
+
+thread_local! {
+    /// Operacion que ejecuta este hilo, ver `Operacion::ejecutar`
+    static EN_CURSO: Cell<Option<OperationId>> = const { Cell::new(None) };
+}
+
+/// Operacion que ejecuta el hilo actual, si hay
+pub(crate) fn en_curso() -> Option<OperationId> {
+    EN_CURSO.get()
+}
+
+/// Restaura la operacion anterior del hilo al soltarse, aunque haya un panico
+struct Restaurar(Option<OperationId>);
+
+impl Drop for Restaurar {
+    fn drop(&mut self) {
+        EN_CURSO.set(self.0);
+    }
+}
+
+/// Estado de una operacion, compartido con el registro
+struct Estado {
+    cancelada: AtomicBool,
+    detalles: Mutex<OperationDetails>,
+}
+
+impl Estado {
+    fn detalles(&self) -> MutexGuard<'_, OperationDetails> {
+        self.detalles.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Operacion registrada. Se quita del registro al soltarla.
+pub struct Operacion {
+    id: OperationId,
+    estado: Arc<Estado>,
+}
+
+impl Operacion {
+    /// Registra una operacion nueva de tipo `tipo` con un id unico
+    pub fn iniciar(tipo: OperationKind) -> Self {
+        let id = SIGUIENTE_ID.fetch_add(1, Ordering::Relaxed);
+        let estado = Arc::new(Estado {
+            cancelada: AtomicBool::new(false),
+            detalles: Mutex::new(OperationDetails {
+                operation_id: id,
+                kind: tipo,
+                src: None,
+                dest: None,
+                batch_index: None,
+                batch_count: None,
+            }),
+        });
+
+        OPERACIONES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, estado.clone());
+
+        Operacion { id, estado }
+    }
+
+    /// Indica la ruta de origen de la operacion
+    pub fn con_origen(self, src: impl fmt::Display) -> Self {
+        self.actualizar(|detalles| detalles.src = Some(src.to_string()));
+        self
+    }
+
+    /// Indica la ruta de destino de la operacion
+    pub fn con_destino(self, dest: impl fmt::Display) -> Self {
+        self.actualizar(|detalles| detalles.dest = Some(dest.to_string()));
+        self
+    }
+
+    pub fn id(&self) -> OperationId {
+        self.id
+    }
+
+    /// Que hace la operacion en este momento
+    pub fn detalles(&self) -> OperationDetails {
+        self.estado.detalles().clone()
+    }
+
+    /// Cambia los detalles, por ejemplo al pasar a otra accion de un lote
+    pub fn actualizar(&self, cambio: impl FnOnce(&mut OperationDetails)) {
+        cambio(&mut self.estado.detalles());
+    }
+
+    /// Indica si se pidio cancelar la operacion
+    pub fn cancelada(&self) -> bool {
+        self.estado.cancelada.load(Ordering::SeqCst)
+    }
+
+    /// Ejecuta `trabajo` en este hilo como parte de la operacion: lo que envie
+    /// al hilo del dispositivo aparece con su id en `WorkerStatus`
+    pub fn ejecutar<T>(&self, trabajo: impl FnOnce() -> T) -> T {
+        let _restaurar = Restaurar(EN_CURSO.replace(Some(self.id)));
+        trabajo()
+    }
+}
+
+impl Drop for Operacion {
+    fn drop(&mut self) {
+        OPERACIONES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
+
+/// Pide cancelar la operacion `id`.
+/// Devuelve `false` si no hay ninguna operacion en curso con ese id, y
+/// `ErrorCode::NotCancellable` si esta enviando un sistema operativo, que no se
+/// puede interrumpir (ver `CalculatorTransport::send_os`).
+pub fn cancelar(id: OperationId) -> Result<bool, DLinkError> {
+    let operaciones = OPERACIONES.lock().map_err(DLinkError::interno)?;
+
+    Ok(match operaciones.get(&id) {
+        Some(estado) if estado.detalles().kind == OperationKind::UploadOs => {
+            return Err(DLinkError::app(
+                ErrorCode::NotCancellable,
+                "El envio del sistema operativo no se puede cancelar",
+            ))
+        }
+        Some(estado) => {
+            estado.cancelada.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    })
+}
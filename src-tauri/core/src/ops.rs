@@ -0,0 +1,995 @@
+//! Operaciones sobre dispositivos independientes de la interfaz.
+//! Los comandos Tauri y la herramienta de linea de comandos (`d-link-cli`)
+//! comparten esta capa; el progreso se reporta mediante un callback.
+//!
+//! Las operaciones sobre varios archivos (aqui y en `backup`, `sync`,
+//! `repository` y `batch`) informan el resultado de cada uno: un archivo que
+//! falla no detiene el resto, salvo que el error interrumpa la operacion
+//! (`DLinkError::interrumpe`), que entonces se devuelve como error. Su progreso
+//! es el agregado de todos los archivos (`restante_agregado`).
+
+use std::collections::{hash_map::Entry, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use crate::error::{DLinkError, ErrorCode, ErrorSource};
+use crate::transport::{CalculatorTransport, TransportError};
+use crate::types::{
+    ConflictPolicy, DeviceId, DeviceInfo, FileInfo, FileResult, TransferOutcome, WorkerStatus,
+};
+use crate::worker::DeviceWorker;
+use crate::{derivar_id_estable, desambiguar_id_estable, DeviceSource, DeviceState, DEVICES};
+
+/// Callback de progreso: recibe los bytes restantes y el total de la operacion.
+/// Devolver `ControlFlow::Break` cancela la operacion (`ErrorCode::Cancelled`).
+pub type Progreso<'a> = &'a mut dyn FnMut(usize, usize) -> ControlFlow<()>;
+
+/// Tamano maximo por defecto de un archivo a descargar
+pub const LIMITE_DESCARGA_POR_DEFECTO: u64 = 256 * 1024 * 1024;
+
+static LIMITE_DESCARGA: AtomicU64 = AtomicU64::new(LIMITE_DESCARGA_POR_DEFECTO);
+
+/// Cambia el tamano maximo de archivo que `descargar` acepta leer
+pub fn establecer_limite_descarga(bytes: u64) {
+    LIMITE_DESCARGA.store(bytes, Ordering::Relaxed);
+}
+
+/// Tamano maximo de archivo que `descargar` acepta leer
+pub fn limite_descarga() -> u64 {
+    LIMITE_DESCARGA.load(Ordering::Relaxed)
+}
+
+/// Obtiene el hilo de trabajo de un dispositivo abierto
+fn obtener_dispositivo_abierto(id: &DeviceId) -> Result<Arc<DeviceWorker>, DLinkError> {
+    let mapa = DEVICES.read().map_err(DLinkError::interno)?;
+
+    if let Some(dispositivo) = mapa.get(&(id.bus_number, id.address)) {
+        match &dispositivo.estado {
+            DeviceState::Open(worker, _) => Ok(worker.clone()),
+            DeviceState::Closed => Err(DLinkError::app(
+                ErrorCode::DeviceClosed,
+                "Dispositivo cerrado",
+            )
+            .con_dispositivo(*id)),
+        }
+    } else {
+        Err(
+            DLinkError::app(ErrorCode::DeviceNotFound, "Dispositivo no encontrado")
+                .con_dispositivo(*id),
+        )
+    }
+}
+
+/// Ejecuta `operacion` en el hilo de trabajo del dispositivo, reenviando su
+/// progreso sobre `total` bytes. Si el transporte reporta desconexion, el
+/// dispositivo se quita del registro.
+fn con_transporte_y_progreso<T: Send + 'static>(
+    id: DeviceId,
+    operacion: impl FnOnce(
+            &dyn CalculatorTransport,
+            &mut dyn FnMut(usize) -> ControlFlow<()>,
+        ) -> Result<T, TransportError>
+        + Send
+        + 'static,
+    total: usize,
+    progreso: Progreso,
+) -> Result<T, DLinkError> {
+    let worker = obtener_dispositivo_abierto(&id)?;
+
+    let resultado = worker.ejecutar(operacion, total, &mut |restante| progreso(restante, total));
+    if matches!(&resultado, Err(error) if error.es_desconexion()) {
+        if let Ok(mut mapa) = DEVICES.write() {
+            mapa.remove(&(id.bus_number, id.address));
+        }
+    }
+    resultado
+}
+
+/// Ejecuta `operacion` sin progreso en el hilo de trabajo del dispositivo
+fn con_transporte<T: Send + 'static>(
+    id: DeviceId,
+    operacion: impl FnOnce(&dyn CalculatorTransport) -> Result<T, TransportError> + Send + 'static,
+) -> Result<T, DLinkError> {
+    con_transporte_y_progreso(id, move |t, _| operacion(t), 0, &mut |_, _| {
+        ControlFlow::Continue(())
+    })
+}
+
+/// Indica si el dispositivo esta ocupado y que operaciones esperan turno
+pub fn estado(id: DeviceId) -> Result<WorkerStatus, DLinkError> {
+    Ok(obtener_dispositivo_abierto(&id)?.estado())
+}
+
+/// Busca el dispositivo conectado con el identificador estable `id_estable`
+pub fn resolver(id_estable: &str) -> Result<DeviceId, DLinkError> {
+    let mapa = DEVICES.read().map_err(DLinkError::interno)?;
+
+    mapa.iter()
+        .find(|(_, dispositivo)| dispositivo.id_estable == id_estable)
+        .map(|(clave, _)| DeviceId {
+            bus_number: clave.0,
+            address: clave.1,
+        })
+        .ok_or_else(|| {
+            DLinkError::app(
+                ErrorCode::DeviceNotFound,
+                format!("Dispositivo no encontrado: {}", id_estable),
+            )
+        })
+}
+
+/// Completa `stable_id` en la informacion leida del transporte
+fn con_id_estable(id: DeviceId, mut info: DeviceInfo) -> Result<DeviceInfo, DLinkError> {
+    let mapa = DEVICES.read().map_err(DLinkError::interno)?;
+    if let Some(dispositivo) = mapa.get(&(id.bus_number, id.address)) {
+        info.stable_id = dispositivo.id_estable.clone();
+    }
+    Ok(info)
+}
+
+/// Abre un dispositivo registrado y devuelve su informacion
+pub fn abrir(id: DeviceId) -> Result<DeviceInfo, DLinkError> {
+    let origen = {
+        let mapa = DEVICES.read().map_err(DLinkError::interno)?;
+        let dispositivo = mapa.get(&(id.bus_number, id.address)).ok_or_else(|| {
+            DLinkError::app(ErrorCode::DeviceNotFound, "Dispositivo no encontrado")
+                .con_dispositivo(id)
+        })?;
+
+        if !matches!(dispositivo.estado, DeviceState::Closed) {
+            return Err(DLinkError::app(
+                ErrorCode::DeviceAlreadyOpen,
+                "El dispositivo ya esta abierto",
+            )
+            .con_dispositivo(id));
+        }
+
+        dispositivo.origen.clone()
+    };
+
+    // Abrir conexion con la calculadora; el transporte pasa al hilo de trabajo
+    let transporte: Box<dyn CalculatorTransport> = match origen {
+        DeviceSource::Usb(dispositivo_usb) => {
+            let handle = dispositivo_usb
+                .open()
+                .map_err(|e| DLinkError::from(e).con_dispositivo(id))?;
+            let handle = libnspire::Handle::new(handle)
+                .map_err(|e| DLinkError::from(TransportError::from(e)).con_dispositivo(id))?;
+            Box::new(handle)
+        }
+        DeviceSource::Emulated(emulado) => Box::new(emulado),
+    };
+    let worker = Arc::new(DeviceWorker::iniciar(id, transporte)?);
+    let mut info = worker.ejecutar(|t, _| t.info(), 0, &mut |_| ControlFlow::Continue(()))?;
+
+    // Guardar el transporte abierto
+    {
+        let mut mapa = DEVICES.write().map_err(DLinkError::interno)?;
+        let clave = (id.bus_number, id.address);
+
+        // Sin numero de serie el id estable pasa a basarse en el nombre de la
+        // calculadora, que puede coincidir con el de otra conectada
+        let por_nombre = mapa
+            .get(&clave)
+            .filter(|dispositivo| dispositivo.serie.is_none())
+            .map(|dispositivo| {
+                let base = derivar_id_estable(dispositivo.id_producto, None, Some(&info.name), "");
+                desambiguar_id_estable(base, |candidato| {
+                    mapa.iter()
+                        .any(|(otra, d)| *otra != clave && d.id_estable == candidato)
+                })
+            });
+
+        let dispositivo = mapa.get_mut(&clave).ok_or_else(|| {
+            DLinkError::app(
+                ErrorCode::DeviceNotFound,
+                "Dispositivo perdido durante apertura",
+            )
+            .con_dispositivo(id)
+        })?;
+        if let Some(id_estable) = por_nombre {
+            dispositivo.id_estable = id_estable;
+        }
+        info.stable_id = dispositivo.id_estable.clone();
+
+        dispositivo.estado = DeviceState::Open(worker, info.clone());
+    }
+
+    Ok(info)
+}
+
+/// Cierra la conexion con un dispositivo
+pub fn cerrar(id: DeviceId) -> Result<(), DLinkError> {
+    let mut mapa = DEVICES.write().map_err(DLinkError::interno)?;
+    let dispositivo = mapa.get_mut(&(id.bus_number, id.address)).ok_or_else(|| {
+        DLinkError::app(ErrorCode::DeviceNotFound, "Dispositivo no encontrado").con_dispositivo(id)
+    })?;
+
+    dispositivo.estado = DeviceState::Closed;
+    Ok(())
+}
+
+/// Lee de nuevo la informacion del dispositivo
+pub fn actualizar_info(id: DeviceId) -> Result<DeviceInfo, DLinkError> {
+    let info = con_transporte(id, |t| t.info())?;
+    con_id_estable(id, info)
+}
+
+/// Lista el contenido de un directorio
+pub fn listar(id: DeviceId, path: &str) -> Result<Vec<FileInfo>, DLinkError> {
+    let ruta = path.to_string();
+    con_transporte(id, move |t| t.list_dir(&ruta)).map_err(|e| e.con_ruta(path))
+}
+
+/// Bytes que le quedan a una operacion sobre varios archivos de `total` bytes
+/// con `completado` ya transferidos, cuando al archivo en curso, de `tamano`, le
+/// quedan `restante`. Satura si los tamanos no cuadran, por ejemplo si un
+/// archivo cambio desde que se listo.
+pub(crate) fn restante_agregado(
+    total: usize,
+    completado: usize,
+    restante: usize,
+    tamano: usize,
+) -> usize {
+    total.saturating_sub(completado + tamano.saturating_sub(restante))
+}
+
+/// Une un directorio de la calculadora con un nombre
+pub(crate) fn unir_ruta(dir: &str, nombre: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), nombre)
+}
+
+/// Lista recursivamente `raiz`. Cada entrada lleva la ruta completa y los
+/// directorios aparecen antes que su contenido.
+pub fn listar_arbol(id: DeviceId, raiz: &str) -> Result<Vec<FileInfo>, DLinkError> {
+    let mut entradas = vec![];
+    let mut pendientes = vec![raiz.to_string()];
+
+    while let Some(dir) = pendientes.pop() {
+        let hijos = listar(id, &dir)?;
+        let inicio = entradas.len();
+        for mut entrada in hijos {
+            entrada.path = unir_ruta(&dir, &entrada.path);
+            entradas.push(entrada);
+        }
+        // Recorrer los subdirectorios en orden despues de sus hermanos
+        pendientes.extend(
+            entradas[inicio..]
+                .iter()
+                .rev()
+                .filter(|entrada| entrada.is_dir)
+                .map(|entrada| entrada.path.clone()),
+        );
+    }
+
+    Ok(entradas)
+}
+
+/// Busca `path` en el listado de su directorio padre
+pub fn buscar_entrada(id: DeviceId, path: &str) -> Result<FileInfo, DLinkError> {
+    let (padre, nombre) = path.rsplit_once('/').unwrap_or(("", path));
+    let padre = if padre.is_empty() { "/" } else { padre };
+
+    listar(id, padre)?
+        .into_iter()
+        .find(|entrada| entrada.path == nombre)
+        .ok_or_else(|| {
+            DLinkError::app(ErrorCode::NotFound, "La ruta no existe")
+                .con_dispositivo(id)
+                .con_ruta(path)
+        })
+}
+
+/// Ruta temporal junto a `destino`, en el mismo sistema de archivos para que
+/// el renombrado final sea atomico
+pub(crate) fn ruta_temporal(destino: &Path) -> PathBuf {
+    static CONTADOR: AtomicU64 = AtomicU64::new(0);
+
+    let nombre = destino
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    destino.with_file_name(format!(
+        ".{}.{}-{}.part",
+        nombre,
+        std::process::id(),
+        CONTADOR.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Escribe `datos` en `destino` mediante un archivo temporal, para que nunca
+/// quede a medias
+pub(crate) fn escribir_atomico(destino: &Path, datos: &[u8]) -> Result<(), DLinkError> {
+    let temporal = ruta_temporal(destino);
+    let resultado = fs::write(&temporal, datos).and_then(|()| fs::rename(&temporal, destino));
+    if resultado.is_err() {
+        let _ = fs::remove_file(&temporal);
+    }
+    resultado.map_err(|e| DLinkError::from(e).con_ruta(destino.display()))
+}
+
+/// Descarga `src` de la calculadora dentro del directorio local `dest`.
+///
+/// El tamano real se toma del listado del directorio padre; si se indica
+/// `tamano_esperado` (el de un listado anterior) y no coincide, el archivo cambio
+/// y se devuelve `ErrorCode::FileChanged`. Archivos mayores que `limite_descarga`
+/// se rechazan con `ErrorCode::FileTooLarge`.
+///
+/// Si en `dest` ya hay algo con el mismo nombre se aplica `politica`, y se
+/// devuelve la ruta local con la que quedo el archivo y lo que se hizo.
+///
+/// Los datos se escriben en un archivo temporal a medida que el transporte los
+/// entrega (con la calculadora real, al terminar de leer el archivo completo en
+/// memoria; ver `CalculatorTransport::read_file`) y solo al terminar se renombra
+/// al nombre final; si algo falla, incluida la desconexion, el temporal se borra.
+pub fn descargar(
+    id: DeviceId,
+    src: &str,
+    tamano_esperado: Option<u64>,
+    dest: &Path,
+    politica: ConflictPolicy,
+    progreso: Progreso,
+) -> Result<(PathBuf, TransferOutcome), DLinkError> {
+    let nombre = src.rsplit('/').next().unwrap_or_default();
+    if nombre.is_empty() {
+        return Err(DLinkError::app(
+            ErrorCode::InvalidArgument,
+            "La ruta de origen no tiene nombre de archivo",
+        )
+        .con_ruta(src));
+    }
+
+    let archivo = buscar_entrada(id, src)?;
+    let error = |code, mensaje: String| {
+        Err(DLinkError::app(code, mensaje)
+            .con_dispositivo(id)
+            .con_ruta(src))
+    };
+    if archivo.is_dir {
+        return error(
+            ErrorCode::InvalidArgument,
+            "La ruta es un directorio".to_string(),
+        );
+    }
+    if let Some(esperado) = tamano_esperado {
+        if esperado != archivo.size {
+            return error(
+                ErrorCode::FileChanged,
+                format!(
+                    "El archivo cambio desde que se listo ({} bytes, ahora {})",
+                    esperado, archivo.size
+                ),
+            );
+        }
+    }
+    descargar_entrada(id, src, nombre, archivo.size, dest, politica, progreso)
+}
+
+/// Descarga en `dest` el archivo `src`, llamado `nombre` y de `size` bytes segun
+/// un listado ya hecho, como en `descargar` pero sin volver a listar su
+/// directorio
+fn descargar_entrada(
+    id: DeviceId,
+    src: &str,
+    nombre: &str,
+    size: u64,
+    dest: &Path,
+    politica: ConflictPolicy,
+    progreso: Progreso,
+) -> Result<(PathBuf, TransferOutcome), DLinkError> {
+    comprobar_limite(id, src, size)?;
+
+    let ocupado = |nombre: &str| {
+        fs::symlink_metadata(dest.join(nombre))
+            .ok()
+            .map(|m| m.is_dir())
+    };
+    let (nombre_local, resultado) = resolver_conflicto(nombre, ocupado, politica)
+        .map_err(|e| e.con_ruta(dest.join(nombre).display()))?;
+    let ruta_local = dest.join(nombre_local);
+    if resultado == TransferOutcome::Skipped {
+        return Ok((ruta_local, resultado));
+    }
+    let temporal = ruta_temporal(&ruta_local);
+
+    let descarga = descargar_en(id, src, size, &temporal, progreso).and_then(|()| {
+        fs::rename(&temporal, &ruta_local)
+            .map_err(|e| DLinkError::from(e).con_ruta(ruta_local.display()))
+    });
+    if let Err(error) = descarga {
+        let _ = fs::remove_file(&temporal);
+        return Err(error);
+    }
+    Ok((ruta_local, resultado))
+}
+
+/// Descarga el directorio `src` de la calculadora dentro del directorio local
+/// `dest`, recreando su jerarquia. Los archivos locales que ya existen se
+/// tratan segun `politica`, y el resultado de cada uno dice que se hizo.
+pub fn descargar_directorio(
+    id: DeviceId,
+    src: &str,
+    dest: &Path,
+    politica: ConflictPolicy,
+    progreso: Progreso,
+) -> Result<Vec<FileResult>, DLinkError> {
+    let raiz = src.trim_end_matches('/');
+    let nombre = raiz.rsplit('/').next().unwrap_or_default();
+    let raiz_local = if nombre.is_empty() {
+        dest.to_path_buf()
+    } else {
+        dest.join(nombre)
+    };
+
+    let entradas = listar_arbol(id, if raiz.is_empty() { "/" } else { raiz })?;
+    let total: u64 = entradas.iter().filter(|e| !e.is_dir).map(|e| e.size).sum();
+    let total = total as usize;
+
+    let local = |ruta: &str| -> PathBuf {
+        let relativa = ruta[raiz.len()..].trim_start_matches('/');
+        relativa
+            .split('/')
+            .fold(raiz_local.clone(), |acumulada, parte| acumulada.join(parte))
+    };
+
+    fs::create_dir_all(&raiz_local)
+        .map_err(|e| DLinkError::from(e).con_ruta(raiz_local.display()))?;
+
+    let mut resultados = vec![];
+    let mut completado = 0;
+    for entrada in &entradas {
+        let destino = local(&entrada.path);
+        let resultado = if entrada.is_dir {
+            fs::create_dir_all(&destino)
+                .map(|()| None)
+                .map_err(|e| DLinkError::from(e).con_ruta(destino.display()))
+        } else {
+            let dir_local = destino.parent().unwrap_or(&raiz_local);
+            let nombre = entrada.path.rsplit('/').next().unwrap_or_default();
+            descargar_entrada(
+                id,
+                &entrada.path,
+                nombre,
+                entrada.size,
+                dir_local,
+                politica,
+                &mut |restante, tamano| {
+                    progreso(
+                        restante_agregado(total, completado, restante, tamano),
+                        total,
+                    )
+                },
+            )
+            .map(|(_, resultado)| Some(resultado))
+        };
+
+        match resultado {
+            Err(error) if error.interrumpe() => return Err(error),
+            // Los directorios solo aparecen en el resumen si fallan
+            Ok(None) => {}
+            resultado => {
+                if !entrada.is_dir {
+                    completado += entrada.size as usize;
+                }
+                let (outcome, error) = match resultado {
+                    Ok(outcome) => (outcome, None),
+                    Err(error) => (None, Some(error)),
+                };
+                resultados.push(FileResult {
+                    path: entrada.path.clone(),
+                    error,
+                    outcome,
+                });
+            }
+        }
+    }
+
+    Ok(resultados)
+}
+
+/// Rechaza con `ErrorCode::FileTooLarge` los archivos mayores que `limite_descarga`
+fn comprobar_limite(id: DeviceId, src: &str, size: u64) -> Result<(), DLinkError> {
+    if size > limite_descarga() {
+        return Err(DLinkError::app(
+            ErrorCode::FileTooLarge,
+            format!(
+                "El archivo ocupa {} bytes y el maximo permitido es {}",
+                size,
+                limite_descarga()
+            ),
+        )
+        .con_dispositivo(id)
+        .con_ruta(src));
+    }
+    Ok(())
+}
+
+/// Lee `src`, de `size` bytes, de la calculadora en `destino` y lo devuelve
+fn leer_en<W: Write + Send + 'static>(
+    id: DeviceId,
+    src: &str,
+    size: u64,
+    destino: W,
+    progreso: Progreso,
+) -> Result<W, DLinkError> {
+    let ruta = src.to_string();
+    con_transporte_y_progreso(
+        id,
+        move |t, avance| {
+            let mut destino = destino;
+            t.read_file(&ruta, size, &mut destino, avance)?;
+            Ok(destino)
+        },
+        size as usize,
+        progreso,
+    )
+}
+
+/// Lee en memoria el archivo `src` de la calculadora, de `size` bytes segun un
+/// listado previo. Pensado para archivos que se escriben en otro destino (un
+/// respaldo, por ejemplo); respeta `limite_descarga`.
+pub fn leer(id: DeviceId, src: &str, size: u64, progreso: Progreso) -> Result<Vec<u8>, DLinkError> {
+    comprobar_limite(id, src, size)?;
+    let ruta = src.to_string();
+    con_transporte_y_progreso(
+        id,
+        move |t, avance| t.read_to_vec(&ruta, size, avance),
+        size as usize,
+        progreso,
+    )
+    .map_err(|e| e.con_ruta(src))
+}
+
+/// Descarga `src` en el archivo local `temporal` y lo deja escrito en disco
+fn descargar_en(
+    id: DeviceId,
+    src: &str,
+    size: u64,
+    temporal: &Path,
+    progreso: Progreso,
+) -> Result<(), DLinkError> {
+    let error_local = |e: std::io::Error| DLinkError::from(e).con_ruta(temporal.display());
+
+    let archivo = File::create(temporal).map_err(error_local)?;
+    let escritor = BufWriter::with_capacity(64 * 1024, archivo); // 64KB buffer
+
+    let escritor = leer_en(id, src, size, escritor, progreso).map_err(|e| match e.source {
+        ErrorSource::Io => e.con_ruta(temporal.display()),
+        _ => e.con_ruta(src),
+    })?;
+
+    let archivo = escritor
+        .into_inner()
+        .map_err(|e| error_local(e.into_error()))?;
+    archivo.sync_all().map_err(error_local)
+}
+
+/// Lee un archivo local completo
+fn leer_local(src: &Path) -> Result<Vec<u8>, DLinkError> {
+    let mut buffer = vec![];
+    File::open(src)
+        .and_then(|mut archivo| archivo.read_to_end(&mut buffer))
+        .map_err(|e| DLinkError::from(e).con_ruta(src.display()))?;
+    Ok(buffer)
+}
+
+/// Sube el archivo local `src` al directorio `dest` de la calculadora. Si ya
+/// hay algo con el mismo nombre se aplica `politica`. Devuelve la ruta con la
+/// que quedo el archivo y lo que se hizo.
+pub fn subir(
+    id: DeviceId,
+    src: &Path,
+    dest: &str,
+    politica: ConflictPolicy,
+    progreso: Progreso,
+) -> Result<(String, TransferOutcome), DLinkError> {
+    let mut existentes = listar(id, dest)?;
+    subir_sin_listar(id, src, dest, &mut existentes, politica, progreso)
+}
+
+/// Como `subir`, con el contenido del directorio `dest` ya listado en
+/// `existentes`, al que se agrega el archivo subido
+fn subir_sin_listar(
+    id: DeviceId,
+    src: &Path,
+    dest: &str,
+    existentes: &mut Vec<FileInfo>,
+    politica: ConflictPolicy,
+    progreso: Progreso,
+) -> Result<(String, TransferOutcome), DLinkError> {
+    let nombre_archivo = src
+        .file_name()
+        .ok_or_else(|| {
+            DLinkError::app(
+                ErrorCode::InvalidArgument,
+                "No se pudo obtener el nombre del archivo",
+            )
+            .con_ruta(src.display())
+        })?
+        .to_string_lossy()
+        .to_string();
+
+    let ocupado = |nombre: &str| {
+        existentes
+            .iter()
+            .find(|e| e.path == nombre)
+            .map(|e| e.is_dir)
+    };
+    let (nombre, resultado) = resolver_conflicto(&nombre_archivo, ocupado, politica)
+        .map_err(|e| e.con_ruta(unir_ruta(dest, &nombre_archivo)))?;
+    let ruta = unir_ruta(dest, &nombre);
+    if resultado == TransferOutcome::Skipped {
+        return Ok((ruta, resultado));
+    }
+
+    let buffer = leer_local(src)?;
+    let tamano = buffer.len() as u64;
+    escribir(id, &ruta, buffer, progreso)?;
+
+    if resultado != TransferOutcome::Overwritten {
+        existentes.push(FileInfo {
+            path: nombre,
+            is_dir: false,
+            date: 0,
+            size: tamano,
+        });
+    }
+    Ok((ruta, resultado))
+}
+
+/// Decide con que nombre transferir `nombre` a un directorio segun `politica`.
+/// `existente` dice si un nombre ya esta ocupado en el destino: `None` si esta
+/// libre y si no, si es un directorio.
+fn resolver_conflicto(
+    nombre: &str,
+    existente: impl Fn(&str) -> Option<bool>,
+    politica: ConflictPolicy,
+) -> Result<(String, TransferOutcome), DLinkError> {
+    let Some(es_dir) = existente(nombre) else {
+        return Ok((nombre.to_string(), TransferOutcome::Created));
+    };
+
+    match politica {
+        // Un directorio no se puede reemplazar por un archivo
+        ConflictPolicy::Overwrite if es_dir => Err(DLinkError::app(
+            ErrorCode::AlreadyExists,
+            "Ya existe un directorio con ese nombre",
+        )),
+        ConflictPolicy::Overwrite => Ok((nombre.to_string(), TransferOutcome::Overwritten)),
+        ConflictPolicy::Skip => Ok((nombre.to_string(), TransferOutcome::Skipped)),
+        ConflictPolicy::KeepBoth => {
+            // El sufijo va antes de la extension: `tarea.tns` -> `tarea_1.tns`
+            let (base, extension) = match nombre.rsplit_once('.') {
+                Some((base, extension)) if !base.is_empty() => (base, format!(".{}", extension)),
+                _ => (nombre, String::new()),
+            };
+            let libre = (1..)
+                .map(|n| format!("{}_{}{}", base, n, extension))
+                .find(|candidato| existente(candidato).is_none())
+                .unwrap_or_default();
+            Ok((libre, TransferOutcome::Renamed))
+        }
+        ConflictPolicy::Ask => Err(DLinkError::app(
+            ErrorCode::AlreadyExists,
+            "Ya existe un archivo con ese nombre",
+        )),
+    }
+}
+
+/// Escribe `datos` en el archivo `path` de la calculadora
+pub fn escribir(
+    id: DeviceId,
+    path: &str,
+    datos: Vec<u8>,
+    progreso: Progreso,
+) -> Result<(), DLinkError> {
+    let total = datos.len();
+    let ruta = path.to_string();
+    con_transporte_y_progreso(
+        id,
+        move |t, avance| t.write_file(&ruta, &datos, avance),
+        total,
+        progreso,
+    )
+    .map_err(|e| e.con_ruta(path))
+}
+
+/// Extensiones que se suben por defecto al subir un directorio: los documentos
+/// que la calculadora puede abrir
+pub const EXTENSIONES_TRANSFERIBLES: &[&str] = &["tns"];
+
+/// Archivo o directorio local dentro de un directorio a subir
+pub(crate) struct EntradaLocal {
+    pub ruta: PathBuf,
+    /// Ruta relativa a la raiz, separada con `/`
+    pub relativa: String,
+    pub es_dir: bool,
+    pub tamano: u64,
+    /// Fecha de modificacion en segundos desde la epoca Unix
+    pub fecha: u64,
+}
+
+/// Lista recursivamente el directorio local `raiz`, en orden alfabetico y con
+/// los directorios antes que su contenido. Omite los archivos cuya extension no
+/// esta en `extensiones`; si la lista esta vacia no se filtra nada.
+pub(crate) fn listar_arbol_local(
+    raiz: &Path,
+    extensiones: &[&str],
+) -> Result<Vec<EntradaLocal>, DLinkError> {
+    let transferible = |ruta: &Path| {
+        extensiones.is_empty()
+            || ruta.extension().is_some_and(|extension| {
+                extensiones
+                    .iter()
+                    .any(|permitida| extension.eq_ignore_ascii_case(permitida))
+            })
+    };
+
+    let mut entradas = vec![];
+    let mut pendientes = vec![(raiz.to_path_buf(), String::new())];
+
+    while let Some((dir, relativa)) = pendientes.pop() {
+        let error_local = |e: io::Error| DLinkError::from(e).con_ruta(dir.display());
+        let mut hijos = fs::read_dir(&dir)
+            .map_err(error_local)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error_local)?;
+        hijos.sort_by_key(|hijo| hijo.file_name());
+
+        let inicio = entradas.len();
+        for hijo in hijos {
+            let ruta = hijo.path();
+            // Sigue los enlaces simbolicos
+            let metadatos =
+                fs::metadata(&ruta).map_err(|e| DLinkError::from(e).con_ruta(ruta.display()))?;
+            if !metadatos.is_dir() && !transferible(&ruta) {
+                continue;
+            }
+
+            let nombre = hijo.file_name().to_string_lossy().to_string();
+            entradas.push(EntradaLocal {
+                relativa: if relativa.is_empty() {
+                    nombre
+                } else {
+                    format!("{}/{}", relativa, nombre)
+                },
+                ruta,
+                es_dir: metadatos.is_dir(),
+                tamano: metadatos.len(),
+                fecha: metadatos
+                    .modified()
+                    .ok()
+                    .and_then(|fecha| fecha.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |duracion| duracion.as_secs()),
+            });
+        }
+        // Recorrer los subdirectorios en orden despues de sus hermanos
+        pendientes.extend(
+            entradas[inicio..]
+                .iter()
+                .rev()
+                .filter(|entrada| entrada.es_dir)
+                .map(|entrada| (entrada.ruta.clone(), entrada.relativa.clone())),
+        );
+    }
+
+    Ok(entradas)
+}
+
+/// Crea el directorio `path` en la calculadora si todavia no existe
+pub(crate) fn crear_directorio_si_falta(id: DeviceId, path: &str) -> Result<(), DLinkError> {
+    match crear_directorio(id, path) {
+        Err(error) if error.code == ErrorCode::AlreadyExists => Ok(()),
+        resultado => resultado,
+    }
+}
+
+/// Sube el directorio local `src` dentro del directorio `dest` de la
+/// calculadora, creando los directorios que falten. Solo se suben los archivos
+/// con alguna de las `extensiones` (todos si esta vacia). Los archivos que ya
+/// existen se tratan segun `politica`, y el resultado de cada uno dice que se
+/// hizo.
+pub fn subir_directorio(
+    id: DeviceId,
+    src: &Path,
+    dest: &str,
+    extensiones: &[&str],
+    politica: ConflictPolicy,
+    progreso: Progreso,
+) -> Result<Vec<FileResult>, DLinkError> {
+    let nombre = src
+        .file_name()
+        .ok_or_else(|| {
+            DLinkError::app(
+                ErrorCode::InvalidArgument,
+                "No se pudo obtener el nombre del directorio",
+            )
+            .con_ruta(src.display())
+        })?
+        .to_string_lossy()
+        .to_string();
+    let raiz_remota = unir_ruta(dest, &nombre);
+
+    let entradas = listar_arbol_local(src, extensiones)?;
+    let total: u64 = entradas
+        .iter()
+        .filter(|e| !e.es_dir)
+        .map(|e| e.tamano)
+        .sum();
+    let total = total as usize;
+
+    crear_directorio_si_falta(id, &raiz_remota)?;
+
+    // Contenido de cada directorio remoto, para resolver los conflictos
+    let mut listados: HashMap<String, Vec<FileInfo>> = HashMap::new();
+    let mut resultados = vec![];
+    let mut completado = 0;
+    for entrada in &entradas {
+        let ruta_remota = unir_ruta(&raiz_remota, &entrada.relativa);
+        let resultado = if entrada.es_dir {
+            crear_directorio_si_falta(id, &ruta_remota).map(|()| None)
+        } else {
+            let (dir_remoto, _) = ruta_remota.rsplit_once('/').unwrap_or_default();
+            let existentes = match listados.entry(dir_remoto.to_string()) {
+                Entry::Occupied(listado) => Ok(listado.into_mut()),
+                Entry::Vacant(hueco) => listar(id, dir_remoto).map(|l| hueco.insert(l)),
+            };
+            existentes.and_then(|existentes| {
+                subir_sin_listar(
+                    id,
+                    &entrada.ruta,
+                    dir_remoto,
+                    existentes,
+                    politica,
+                    &mut |restante, tamano| {
+                        progreso(
+                            restante_agregado(total, completado, restante, tamano),
+                            total,
+                        )
+                    },
+                )
+                .map(Some)
+            })
+        };
+
+        match resultado {
+            Err(error) if error.interrumpe() => return Err(error),
+            // Los directorios solo aparecen en el resumen si fallan
+            Ok(None) => {}
+            Ok(Some((ruta, resultado))) => {
+                completado += entrada.tamano as usize;
+                resultados.push(FileResult {
+                    path: ruta,
+                    error: None,
+                    outcome: Some(resultado),
+                });
+            }
+            Err(error) => {
+                if !entrada.es_dir {
+                    completado += entrada.tamano as usize;
+                }
+                resultados.push(FileResult {
+                    path: ruta_remota,
+                    error: Some(error),
+                    outcome: None,
+                });
+            }
+        }
+    }
+
+    Ok(resultados)
+}
+
+/// Envia un archivo de sistema operativo a la calculadora
+pub fn subir_os(id: DeviceId, src: &Path, progreso: Progreso) -> Result<(), DLinkError> {
+    let buffer = leer_local(src)?;
+    let total = buffer.len();
+
+    con_transporte_y_progreso(
+        id,
+        move |t, avance| t.send_os(&buffer, avance),
+        total,
+        progreso,
+    )
+}
+
+/// Elimina un archivo
+pub fn eliminar_archivo(id: DeviceId, path: &str) -> Result<(), DLinkError> {
+    let ruta = path.to_string();
+    con_transporte(id, move |t| t.delete_file(&ruta)).map_err(|e| e.con_ruta(path))
+}
+
+/// Elimina un directorio vacio
+pub fn eliminar_directorio(id: DeviceId, path: &str) -> Result<(), DLinkError> {
+    let ruta = path.to_string();
+    con_transporte(id, move |t| t.delete_dir(&ruta)).map_err(|e| e.con_ruta(path))
+}
+
+/// Elimina el directorio `path` con todo su contenido, de abajo hacia arriba:
+/// primero los archivos y luego cada directorio ya vacio. El progreso se
+/// reporta en elementos eliminados, no en bytes. Con `continuar_con_errores` un
+/// elemento que falla no detiene el resto (aunque su directorio padre tampoco se
+/// podra eliminar); sin el, se detiene en el primer fallo. La cancelacion y la
+/// desconexion se devuelven como error.
+pub fn eliminar_recursivo(
+    id: DeviceId,
+    path: &str,
+    continuar_con_errores: bool,
+    progreso: Progreso,
+) -> Result<Vec<FileResult>, DLinkError> {
+    let raiz = path.trim_end_matches('/');
+    if raiz.is_empty() {
+        return Err(DLinkError::app(
+            ErrorCode::InvalidArgument,
+            "No se puede eliminar el directorio raiz",
+        )
+        .con_ruta(path));
+    }
+
+    let mut entradas = listar_arbol(id, raiz)?;
+    // El listado tiene los directorios antes que su contenido
+    entradas.reverse();
+    entradas.push(FileInfo {
+        path: raiz.to_string(),
+        is_dir: true,
+        date: 0,
+        size: 0,
+    });
+
+    let total = entradas.len();
+    let mut resultados = Vec::with_capacity(total);
+    for (indice, entrada) in entradas.iter().enumerate() {
+        if progreso(total - indice, total).is_break() {
+            return Err(DLinkError::from(TransportError::Cancelled)
+                .con_dispositivo(id)
+                .con_ruta(raiz));
+        }
+
+        let resultado = if entrada.is_dir {
+            eliminar_directorio(id, &entrada.path)
+        } else {
+            eliminar_archivo(id, &entrada.path)
+        };
+
+        match resultado {
+            Err(error) if error.es_desconexion() => return Err(error),
+            resultado => {
+                let fallo = resultado.is_err();
+                resultados.push(FileResult {
+                    path: entrada.path.clone(),
+                    error: resultado.err(),
+                    outcome: None,
+                });
+                if fallo && !continuar_con_errores {
+                    return Ok(resultados);
+                }
+            }
+        }
+    }
+    let _ = progreso(0, total);
+
+    Ok(resultados)
+}
+
+/// Crea un directorio
+pub fn crear_directorio(id: DeviceId, path: &str) -> Result<(), DLinkError> {
+    let ruta = path.to_string();
+    con_transporte(id, move |t| t.create_dir(&ruta)).map_err(|e| e.con_ruta(path))
+}
+
+/// Mueve un archivo o directorio
+pub fn mover(id: DeviceId, src: &str, dest: &str) -> Result<(), DLinkError> {
+    let (origen, destino) = (src.to_string(), dest.to_string());
+    con_transporte(id, move |t| t.move_file(&origen, &destino)).map_err(|e| e.con_ruta(src))
+}
+
+/// Copia un archivo
+pub fn copiar(id: DeviceId, src: &str, dest: &str) -> Result<(), DLinkError> {
+    let (origen, destino) = (src.to_string(), dest.to_string());
+    con_transporte(id, move |t| t.copy_file(&origen, &destino)).map_err(|e| e.con_ruta(src))
+}
@@ -0,0 +1,819 @@
+//! Cola de operaciones de cada calculadora.
+//! Cada dispositivo, identificado por su id estable, tiene una cola que el
+//! nucleo ejecuta en orden en un hilo propio, una accion a la vez. La cola se
+//! puede pausar, reordenar y reintentar, y cada cambio se emite como
+//! `Event::QueueChanged` para que todas las ventanas muestren el mismo estado.
+//! Si la calculadora se desconecta, la accion en curso vuelve a quedar
+//! pendiente y la cola sigue al abrirla de nuevo.
+//!
+//! Con `abrir_diario` las colas se guardan en un archivo tras cada cambio y se
+//! recuperan al volver a iniciar la aplicacion; como se guardan por id
+//! estable, cada una vuelve a su calculadora cuando esta se conecta.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::thread;
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::backup::{self, RestoreMode};
+use crate::error::{DLinkError, ErrorCode};
+use crate::events::{ejecutar_operacion, Event, EventSink};
+use crate::operations::{self, Operacion, OperationId};
+use crate::ops::{self, Progreso};
+use crate::repository;
+use crate::sync::{self, SyncPlan};
+use crate::types::{ConflictPolicy, DeviceId, FileResult, OperationDetails, OperationKind};
+
+/// Accion de la cola o de un lote. Las rutas `src` de las subidas y de
+/// `Restore`, y las de destino de descargas y respaldos, son del sistema de
+/// archivos; el resto, de la calculadora.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum QueueAction {
+    /// Descarga `path` en el directorio `dest` (por defecto, el de descargas);
+    /// `on_conflict` dice que hacer si ya existe (por defecto, `Ask`)
+    Download {
+        path: String,
+        size: u64,
+        #[serde(default)]
+        dest: Option<PathBuf>,
+        #[serde(default = "ConflictPolicy::descarga_por_defecto")]
+        on_conflict: ConflictPolicy,
+    },
+    /// Descarga el directorio `path` en `dest` (por defecto, el de descargas);
+    /// los archivos que ya existen se tratan como en `Download`
+    DownloadDir {
+        path: String,
+        #[serde(default)]
+        dest: Option<PathBuf>,
+        #[serde(default = "ConflictPolicy::descarga_por_defecto")]
+        on_conflict: ConflictPolicy,
+    },
+    /// Sube el archivo `src` al directorio `path`; `on_conflict` dice que
+    /// hacer si ya existe (por defecto, se sobrescribe)
+    Upload {
+        path: String,
+        src: PathBuf,
+        #[serde(default)]
+        on_conflict: ConflictPolicy,
+    },
+    /// Sube el directorio `src` dentro de `path`; sin `extensions`, solo los
+    /// `ops::EXTENSIONES_TRANSFERIBLES`
+    UploadDir {
+        path: String,
+        src: PathBuf,
+        #[serde(default)]
+        extensions: Option<Vec<String>>,
+        #[serde(default)]
+        on_conflict: ConflictPolicy,
+    },
+    UploadOs {
+        src: PathBuf,
+    },
+    /// Con `is_dir` elimina el directorio con su contenido, y se detiene en el
+    /// primer elemento que no se pueda eliminar
+    Delete {
+        path: String,
+        is_dir: bool,
+    },
+    CreateDir {
+        path: String,
+    },
+    Move {
+        src: String,
+        dest: String,
+    },
+    Copy {
+        src: String,
+        dest: String,
+    },
+    Sync {
+        plan: SyncPlan,
+    },
+    /// Respalda la calculadora en `dest`, con el formato de su extension
+    Backup {
+        dest: PathBuf,
+    },
+    Restore {
+        src: PathBuf,
+        mode: RestoreMode,
+    },
+    /// Toma una instantanea en `repo` (por defecto, el repositorio por defecto)
+    Snapshot {
+        #[serde(default)]
+        repo: Option<PathBuf>,
+    },
+    SnapshotRestore {
+        snapshot: String,
+        mode: RestoreMode,
+        #[serde(default)]
+        repo: Option<PathBuf>,
+    },
+}
+
+impl QueueAction {
+    /// Tipo de operacion de la accion
+    pub fn tipo(&self) -> OperationKind {
+        match self {
+            QueueAction::Download { .. } => OperationKind::Download,
+            QueueAction::DownloadDir { .. } => OperationKind::DownloadDir,
+            QueueAction::Upload { .. } => OperationKind::Upload,
+            QueueAction::UploadDir { .. } => OperationKind::UploadDir,
+            QueueAction::UploadOs { .. } => OperationKind::UploadOs,
+            QueueAction::Delete { .. } => OperationKind::Delete,
+            QueueAction::CreateDir { .. } => OperationKind::CreateDir,
+            QueueAction::Move { .. } => OperationKind::Move,
+            QueueAction::Copy { .. } => OperationKind::Copy,
+            QueueAction::Sync { .. } => OperationKind::Sync,
+            QueueAction::Backup { .. } => OperationKind::Backup,
+            QueueAction::Restore { .. } => OperationKind::Restore,
+            QueueAction::Snapshot { .. } => OperationKind::Snapshot,
+            QueueAction::SnapshotRestore { .. } => OperationKind::SnapshotRestore,
+        }
+    }
+
+    /// Indica si la accion se puede repetir sin riesgo si quedo a medias. Las
+    /// que cambian o borran lo que habia en la calculadora, o reinstalan su
+    /// sistema, no: repetirlas actuaria sobre un estado distinto.
+    fn repetible(&self) -> bool {
+        match self {
+            QueueAction::Download { .. }
+            | QueueAction::DownloadDir { .. }
+            | QueueAction::Upload { .. }
+            | QueueAction::UploadDir { .. }
+            | QueueAction::CreateDir { .. }
+            | QueueAction::Copy { .. }
+            | QueueAction::Backup { .. }
+            | QueueAction::Snapshot { .. } => true,
+            QueueAction::UploadOs { .. }
+            | QueueAction::Delete { .. }
+            | QueueAction::Move { .. }
+            | QueueAction::Sync { .. }
+            | QueueAction::Restore { .. }
+            | QueueAction::SnapshotRestore { .. } => false,
+        }
+    }
+
+    /// Escribe en `detalles` el tipo y las rutas de origen y destino de la
+    /// accion. Los destinos por defecto quedan sin indicar.
+    pub(crate) fn describir(&self, detalles: &mut OperationDetails) {
+        let texto = |ruta: &Path| Some(ruta.display().to_string());
+        let (src, dest) = match self {
+            QueueAction::Download { path, dest, .. }
+            | QueueAction::DownloadDir { path, dest, .. } => {
+                (Some(path.clone()), dest.as_deref().and_then(texto))
+            }
+            QueueAction::Upload { path, src, .. } | QueueAction::UploadDir { path, src, .. } => {
+                (texto(src), Some(path.clone()))
+            }
+            QueueAction::UploadOs { src } | QueueAction::Restore { src, .. } => (texto(src), None),
+            QueueAction::Delete { path, .. } => (Some(path.clone()), None),
+            QueueAction::CreateDir { path } => (None, Some(path.clone())),
+            QueueAction::Move { src, dest } | QueueAction::Copy { src, dest } => {
+                (Some(src.clone()), Some(dest.clone()))
+            }
+            QueueAction::Sync { plan } => plan.origen_destino(),
+            QueueAction::Backup { dest } => (None, texto(dest)),
+            QueueAction::Snapshot { repo } => (None, repo.as_deref().and_then(texto)),
+            QueueAction::SnapshotRestore { snapshot, .. } => (Some(snapshot.clone()), None),
+        };
+        detalles.kind = self.tipo();
+        detalles.src = src;
+        detalles.dest = dest;
+    }
+}
+
+/// Destinos de las acciones que no indican uno
+#[derive(Debug, Clone, Default)]
+pub struct ActionDefaults {
+    /// Directorio de las descargas
+    pub descargas: Option<PathBuf>,
+    /// Repositorio de instantaneas
+    pub repositorio: Option<PathBuf>,
+}
+
+/// Estado de un elemento de la cola. Los elementos que terminan bien o se
+/// cancelan salen de la cola.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum QueueItemStatus {
+    Pending,
+    Running,
+    Failed,
+}
+
+/// Elemento de la cola
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueItem {
+    pub id: u64,
+    pub action: QueueAction,
+    pub status: QueueItemStatus,
+    /// Error del ultimo intento si fallo
+    pub error: Option<DLinkError>,
+    /// Id de la operacion en curso, el de sus eventos de progreso y el que
+    /// acepta la cancelacion
+    pub operation_id: Option<OperationId>,
+}
+
+/// Cola de un dispositivo
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceQueue {
+    /// Id estable del dispositivo
+    pub device: String,
+    pub paused: bool,
+    /// En orden de ejecucion
+    pub items: Vec<QueueItem>,
+}
+
+/// Clave con la que el hilo que ejecuta una cola la busca. `renombrar` la
+/// cambia para que el hilo siga a su cola, y la accion en curso quede
+/// registrada con el id nuevo.
+type Ejecutor = Arc<Mutex<String>>;
+
+/// Cola con su estado de ejecucion
+#[derive(Default)]
+struct Cola {
+    estado: DeviceQueue,
+    /// Hilo que ejecuta la cola, si hay
+    ejecutor: Option<Ejecutor>,
+}
+
+impl Cola {
+    /// Indica si `ejecutor` es el hilo de esta cola
+    fn ejecuta(&self, ejecutor: &Ejecutor) -> bool {
+        self.ejecutor
+            .as_ref()
+            .is_some_and(|propio| Arc::ptr_eq(propio, ejecutor))
+    }
+}
+
+/// Lo que la cola necesita para ejecutar acciones
+struct Contexto {
+    eventos: Arc<dyn EventSink>,
+    destinos: ActionDefaults,
+}
+
+/// Version del formato del diario
+const VERSION_DIARIO: u32 = 1;
+
+/// Contenido del archivo del diario
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Diario {
+    version: u32,
+    /// Solo las colas con elementos o en pausa
+    queues: Vec<DeviceQueue>,
+}
+
+static SIGUIENTE_ID: AtomicU64 = AtomicU64::new(1);
+
+lazy_static::lazy_static! {
+    static ref COLAS: Mutex<HashMap<String, Cola>> = Mutex::new(HashMap::new());
+    static ref CONTEXTO: RwLock<Option<Arc<Contexto>>> = RwLock::new(None);
+    /// Archivo del diario, si se abrio uno
+    static ref DIARIO: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Indica donde se emiten los eventos de las colas y los destinos por defecto
+/// de sus acciones. Sin configurar, las colas guardan las acciones pero no las
+/// ejecutan.
+pub fn configurar(eventos: Arc<dyn EventSink>, destinos: ActionDefaults) {
+    *CONTEXTO.write().unwrap_or_else(|e| e.into_inner()) =
+        Some(Arc::new(Contexto { eventos, destinos }));
+}
+
+fn contexto() -> Option<Arc<Contexto>> {
+    CONTEXTO.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn bloquear() -> MutexGuard<'static, HashMap<String, Cola>> {
+    COLAS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Clave actual de la cola del hilo `ejecutor`; se consulta con las colas
+/// bloqueadas para que no cambie a mitad de un cambio
+fn clave(ejecutor: &Ejecutor) -> String {
+    ejecutor.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Carga las colas guardadas en el diario `ruta`, si existe, y desde entonces
+/// guarda en el todos los cambios. Las acciones que estaban en curso vuelven a
+/// quedar pendientes si se pueden repetir, y si no fallan como interrumpidas;
+/// cada cola se retoma cuando se abre su calculadora.
+///
+/// Un diario que no se puede leer se renombra a `<ruta>.invalido` para no
+/// perderlo y se empieza con las colas vacias; el error se devuelve igual.
+pub fn abrir_diario(ruta: &Path) -> Result<(), DLinkError> {
+    let error_local = |e: std::io::Error| DLinkError::from(e).con_ruta(ruta.display());
+    if let Some(padre) = ruta.parent() {
+        fs::create_dir_all(padre).map_err(error_local)?;
+    }
+    *DIARIO.write().unwrap_or_else(|e| e.into_inner()) = Some(ruta.to_path_buf());
+
+    let json = match fs::read(ruta) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(error_local(e)),
+    };
+    let diario = match serde_json::from_slice::<Diario>(&json) {
+        Ok(diario) if diario.version <= VERSION_DIARIO => diario,
+        Ok(diario) => {
+            return Err(diario_invalido(
+                ruta,
+                format!("version {} no soportada", diario.version),
+            ))
+        }
+        Err(e) => return Err(diario_invalido(ruta, e.to_string())),
+    };
+
+    let estados: Vec<DeviceQueue> = {
+        let mut colas = bloquear();
+        for guardada in diario.queues {
+            let cola = cola(&mut colas, &guardada.device);
+            cola.estado.paused |= guardada.paused;
+            for mut item in guardada.items {
+                if item.status == QueueItemStatus::Running {
+                    interrumpir(&mut item, "se cerro la aplicacion");
+                }
+                item.operation_id = None;
+                SIGUIENTE_ID.fetch_max(item.id + 1, Ordering::Relaxed);
+                cola.estado.items.push(item);
+            }
+        }
+        colas.values().map(|cola| cola.estado.clone()).collect()
+    };
+    for estado in estados {
+        notificar(estado);
+    }
+    Ok(())
+}
+
+/// Aparta el diario que no se pudo leer y devuelve el error
+fn diario_invalido(ruta: &Path, motivo: String) -> DLinkError {
+    let mut apartado = ruta.as_os_str().to_owned();
+    apartado.push(".invalido");
+    let _ = fs::rename(ruta, &apartado);
+    DLinkError::app(
+        ErrorCode::InvalidArgument,
+        format!("No se pudo leer el diario de la cola ({})", motivo),
+    )
+    .con_ruta(ruta.display())
+}
+
+/// Guarda las colas en el diario, si hay uno. Se llama con las colas
+/// bloqueadas para que el archivo siga el orden de los cambios.
+fn guardar(colas: &HashMap<String, Cola>) {
+    let Some(ruta) = DIARIO.read().unwrap_or_else(|e| e.into_inner()).clone() else {
+        return;
+    };
+    let mut queues: Vec<DeviceQueue> = colas
+        .values()
+        .filter(|cola| cola.estado.paused || !cola.estado.items.is_empty())
+        .map(|cola| cola.estado.clone())
+        .collect();
+    queues.sort_by(|a, b| a.device.cmp(&b.device));
+    let diario = Diario {
+        version: VERSION_DIARIO,
+        queues,
+    };
+
+    let resultado = serde_json::to_vec_pretty(&diario)
+        .map_err(DLinkError::interno)
+        .and_then(|json| ops::escribir_atomico(&ruta, &json));
+    if let Err(e) = resultado {
+        eprintln!("Error guardando el diario de la cola: {}", e);
+    }
+}
+
+/// Cola del dispositivo, creada vacia si no existia
+fn cola<'a>(colas: &'a mut HashMap<String, Cola>, dispositivo: &str) -> &'a mut Cola {
+    colas
+        .entry(dispositivo.to_string())
+        .or_insert_with(|| Cola {
+            estado: DeviceQueue {
+                device: dispositivo.to_string(),
+                ..DeviceQueue::default()
+            },
+            ejecutor: None,
+        })
+}
+
+fn notificar(estado: DeviceQueue) {
+    if let Some(contexto) = contexto() {
+        contexto.eventos.emitir(Event::QueueChanged(estado));
+    }
+}
+
+/// Aplica `cambio` a la cola del dispositivo y emite el estado resultante
+fn modificar<T>(
+    dispositivo: &str,
+    cambio: impl FnOnce(&mut DeviceQueue) -> Result<T, DLinkError>,
+) -> Result<T, DLinkError> {
+    let (resultado, estado) = {
+        let mut colas = bloquear();
+        let cola = cola(&mut colas, dispositivo);
+        let resultado = cambio(&mut cola.estado)?;
+        let estado = cola.estado.clone();
+        guardar(&colas);
+        (resultado, estado)
+    };
+    notificar(estado);
+    Ok(resultado)
+}
+
+fn elemento_no_encontrado(elemento: u64) -> DLinkError {
+    DLinkError::app(
+        ErrorCode::NotFound,
+        format!("No hay ningun elemento {} en la cola", elemento),
+    )
+}
+
+/// Agrega `accion` al final de la cola del dispositivo y devuelve el id del
+/// elemento
+pub fn encolar(dispositivo: &str, accion: QueueAction) -> Result<u64, DLinkError> {
+    let id = SIGUIENTE_ID.fetch_add(1, Ordering::Relaxed);
+    modificar(dispositivo, |cola| {
+        cola.items.push(QueueItem {
+            id,
+            action: accion,
+            status: QueueItemStatus::Pending,
+            error: None,
+            operation_id: None,
+        });
+        Ok(())
+    })?;
+    procesar(dispositivo);
+    Ok(id)
+}
+
+/// Estado de la cola del dispositivo
+pub fn obtener(dispositivo: &str) -> DeviceQueue {
+    cola(&mut bloquear(), dispositivo).estado.clone()
+}
+
+/// Estado de todas las colas
+pub fn listar() -> Vec<DeviceQueue> {
+    bloquear()
+        .values()
+        .map(|cola| cola.estado.clone())
+        .collect()
+}
+
+/// Deja de tomar acciones nuevas; la que esta en curso termina
+pub fn pausar(dispositivo: &str) -> Result<(), DLinkError> {
+    modificar(dispositivo, |cola| {
+        cola.paused = true;
+        Ok(())
+    })
+}
+
+/// Sigue ejecutando la cola pausada
+pub fn reanudar(dispositivo: &str) -> Result<(), DLinkError> {
+    modificar(dispositivo, |cola| {
+        cola.paused = false;
+        Ok(())
+    })?;
+    procesar(dispositivo);
+    Ok(())
+}
+
+/// Mueve el elemento `elemento` a la posicion `posicion` de la cola (al final
+/// si la supera)
+pub fn mover(dispositivo: &str, elemento: u64, posicion: usize) -> Result<(), DLinkError> {
+    modificar(dispositivo, |cola| {
+        let indice = cola
+            .items
+            .iter()
+            .position(|e| e.id == elemento)
+            .ok_or_else(|| elemento_no_encontrado(elemento))?;
+        let item = cola.items.remove(indice);
+        cola.items.insert(posicion.min(cola.items.len()), item);
+        Ok(())
+    })
+}
+
+/// Vuelve a dejar pendientes los elementos que fallaron
+pub fn reintentar_fallidos(dispositivo: &str) -> Result<(), DLinkError> {
+    modificar(dispositivo, |cola| {
+        for item in &mut cola.items {
+            if item.status == QueueItemStatus::Failed {
+                item.status = QueueItemStatus::Pending;
+                item.error = None;
+            }
+        }
+        Ok(())
+    })?;
+    procesar(dispositivo);
+    Ok(())
+}
+
+/// Quita de la cola todos los elementos salvo el que esta en curso
+pub fn vaciar(dispositivo: &str) -> Result<(), DLinkError> {
+    modificar(dispositivo, |cola| {
+        cola.items.retain(|e| e.status == QueueItemStatus::Running);
+        Ok(())
+    })
+}
+
+/// Quita el elemento de la cola o, si esta en curso, pide cancelarlo; sale de
+/// la cola cuando la operacion se detiene
+pub fn cancelar(dispositivo: &str, elemento: u64) -> Result<(), DLinkError> {
+    let operacion = modificar(dispositivo, |cola| {
+        let indice = cola
+            .items
+            .iter()
+            .position(|e| e.id == elemento)
+            .ok_or_else(|| elemento_no_encontrado(elemento))?;
+        if cola.items[indice].status == QueueItemStatus::Running {
+            Ok(cola.items[indice].operation_id)
+        } else {
+            cola.items.remove(indice);
+            Ok(None)
+        }
+    })?;
+    if let Some(operacion) = operacion {
+        operations::cancelar(operacion)?;
+    }
+    Ok(())
+}
+
+/// Pasa la cola de `anterior` a `nuevo` cuando cambia el id estable del
+/// dispositivo al abrirlo; si ya habia una cola con el id nuevo, las acciones
+/// se agregan al final. El hilo que ejecutaba la cola vieja la sigue con el id
+/// nuevo; si la nueva ya tenia uno, el de la vieja termina la accion en curso
+/// y se detiene.
+pub fn renombrar(anterior: &str, nuevo: &str) {
+    let estado = {
+        let mut colas = bloquear();
+        let Some(vieja) = colas.remove(anterior) else {
+            return;
+        };
+        if let Some(ejecutor) = &vieja.ejecutor {
+            *ejecutor.lock().unwrap_or_else(|e| e.into_inner()) = nuevo.to_string();
+        }
+        let cola = cola(&mut colas, nuevo);
+        cola.estado.items.extend(vieja.estado.items);
+        cola.estado.paused |= vieja.estado.paused;
+        if cola.ejecutor.is_none() {
+            cola.ejecutor = vieja.ejecutor;
+        }
+        let estado = cola.estado.clone();
+        guardar(&colas);
+        estado
+    };
+    notificar(estado);
+}
+
+/// Empieza a ejecutar la cola del dispositivo si tiene acciones pendientes y
+/// no estaba en curso. Se llama sola al encolar, reanudar o reintentar, y hay
+/// que llamarla al abrir el dispositivo para retomar lo pendiente.
+pub fn procesar(dispositivo: &str) {
+    let Some(contexto) = contexto() else {
+        return;
+    };
+    let ejecutor = {
+        let mut colas = bloquear();
+        let cola = cola(&mut colas, dispositivo);
+        if cola.ejecutor.is_some() || cola.estado.paused {
+            return;
+        }
+        let ejecutor = Arc::new(Mutex::new(dispositivo.to_string()));
+        cola.ejecutor = Some(ejecutor.clone());
+        ejecutor
+    };
+
+    thread::spawn(move || ejecutar_cola(&ejecutor, &contexto));
+}
+
+/// Deja pendiente un elemento que estaba en curso, o fallido con
+/// `ErrorCode::Interrupted` si su accion no se puede repetir sola
+fn interrumpir(item: &mut QueueItem, motivo: &str) {
+    if item.action.repetible() {
+        item.status = QueueItemStatus::Pending;
+    } else {
+        item.status = QueueItemStatus::Failed;
+        item.error = Some(DLinkError::app(
+            ErrorCode::Interrupted,
+            format!(
+                "La accion quedo a medias porque {}; reintentala si hace falta",
+                motivo
+            ),
+        ));
+    }
+}
+
+/// Errores tras los que la accion queda pendiente hasta que el dispositivo
+/// vuelva a estar disponible, o fallida si no se puede repetir
+fn sin_dispositivo(error: &DLinkError) -> bool {
+    error.es_desconexion()
+        || matches!(
+            error.code,
+            ErrorCode::DeviceNotFound | ErrorCode::DeviceClosed
+        )
+}
+
+/// Toma el siguiente elemento pendiente y lo marca en curso, o marca la cola
+/// como detenida si no hay nada que hacer. Devuelve `None` sin tocar la cola
+/// si al renombrarla paso a ejecutarla otro hilo.
+fn siguiente(ejecutor: &Ejecutor) -> Option<(DeviceId, u64, QueueAction, Operacion)> {
+    let (siguiente, estado) = {
+        let mut colas = bloquear();
+        let dispositivo = clave(ejecutor);
+        let cola = cola(&mut colas, &dispositivo);
+        if !cola.ejecuta(ejecutor) {
+            return None;
+        }
+        let id = match cola.estado.paused {
+            true => None,
+            false => ops::resolver(&dispositivo).ok(),
+        };
+        let item = id.and_then(|_| {
+            cola.estado
+                .items
+                .iter_mut()
+                .find(|e| e.status == QueueItemStatus::Pending)
+        });
+        match (id, item) {
+            (Some(id), Some(item)) => {
+                let operacion = Operacion::iniciar(item.action.tipo());
+                operacion.actualizar(|detalles| item.action.describir(detalles));
+                item.status = QueueItemStatus::Running;
+                item.error = None;
+                item.operation_id = Some(operacion.id());
+                let siguiente = (id, item.id, item.action.clone(), operacion);
+                let estado = cola.estado.clone();
+                guardar(&colas);
+                (Some(siguiente), estado)
+            }
+            _ => {
+                cola.ejecutor = None;
+                return None;
+            }
+        }
+    };
+    notificar(estado);
+    siguiente
+}
+
+/// Ejecuta los elementos pendientes hasta vaciar o pausar la cola, o hasta que
+/// el dispositivo deje de estar disponible
+fn ejecutar_cola(ejecutor: &Ejecutor, contexto: &Contexto) {
+    while let Some((id, elemento, accion, operacion)) = siguiente(ejecutor) {
+        let resultado = ejecutar_operacion(&*contexto.eventos, id, &operacion, |progreso| {
+            ejecutar_accion(id, &accion, &contexto.destinos, progreso)
+        });
+        drop(operacion);
+
+        let detener = matches!(&resultado, Err(error) if sin_dispositivo(error));
+        let estado = {
+            let mut colas = bloquear();
+            let cola = cola(&mut colas, &clave(ejecutor));
+            if let Some(indice) = cola.estado.items.iter().position(|e| e.id == elemento) {
+                let item = &mut cola.estado.items[indice];
+                item.operation_id = None;
+                match resultado {
+                    Ok(()) => {
+                        cola.estado.items.remove(indice);
+                    }
+                    Err(error) if error.code == ErrorCode::Cancelled => {
+                        cola.estado.items.remove(indice);
+                    }
+                    Err(_) if detener => interrumpir(item, "se perdio el dispositivo"),
+                    Err(error) => {
+                        item.status = QueueItemStatus::Failed;
+                        item.error = Some(error);
+                    }
+                }
+            }
+            if detener && cola.ejecuta(ejecutor) {
+                cola.ejecutor = None;
+            }
+            let estado = cola.estado.clone();
+            guardar(&colas);
+            estado
+        };
+        notificar(estado);
+        if detener {
+            return;
+        }
+    }
+}
+
+/// El primer archivo que fallo de una operacion sobre varios, como error
+fn primer_fallo(resultados: Vec<FileResult>) -> Result<(), DLinkError> {
+    match resultados.into_iter().find_map(|r| r.error) {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+/// La ruta indicada o, si no hay, la de por defecto
+fn elegir<'a>(
+    ruta: &'a Option<PathBuf>,
+    defecto: &'a Option<PathBuf>,
+    que: &str,
+) -> Result<&'a Path, DLinkError> {
+    ruta.as_deref().or(defecto.as_deref()).ok_or_else(|| {
+        DLinkError::app(
+            ErrorCode::InvalidArgument,
+            format!("La accion no indica {} y no hay uno por defecto", que),
+        )
+    })
+}
+
+/// Ejecuta una accion. En las que procesan varios archivos, el primero que
+/// falla se devuelve como error de la accion.
+pub(crate) fn ejecutar_accion(
+    id: DeviceId,
+    accion: &QueueAction,
+    destinos: &ActionDefaults,
+    progreso: Progreso,
+) -> Result<(), DLinkError> {
+    match accion {
+        QueueAction::Download {
+            path,
+            size,
+            dest,
+            on_conflict,
+        } => {
+            let dest = elegir(dest, &destinos.descargas, "destino")?;
+            ops::descargar(id, path, Some(*size), dest, *on_conflict, progreso).map(|_| ())
+        }
+        QueueAction::DownloadDir {
+            path,
+            dest,
+            on_conflict,
+        } => {
+            let dest = elegir(dest, &destinos.descargas, "destino")?;
+            primer_fallo(ops::descargar_directorio(
+                id,
+                path,
+                dest,
+                *on_conflict,
+                progreso,
+            )?)
+        }
+        QueueAction::Upload {
+            path,
+            src,
+            on_conflict,
+        } => ops::subir(id, src, path, *on_conflict, progreso).map(|_| ()),
+        QueueAction::UploadDir {
+            path,
+            src,
+            extensions,
+            on_conflict,
+        } => {
+            let extensiones: Vec<&str> = match extensions {
+                Some(extensiones) => extensiones.iter().map(String::as_str).collect(),
+                None => ops::EXTENSIONES_TRANSFERIBLES.to_vec(),
+            };
+            primer_fallo(ops::subir_directorio(
+                id,
+                src,
+                path,
+                &extensiones,
+                *on_conflict,
+                progreso,
+            )?)
+        }
+        QueueAction::UploadOs { src } => ops::subir_os(id, src, progreso),
+        QueueAction::Delete { path, is_dir: true } => {
+            primer_fallo(ops::eliminar_recursivo(id, path, false, progreso)?)
+        }
+        QueueAction::Delete {
+            path,
+            is_dir: false,
+        } => ops::eliminar_archivo(id, path),
+        QueueAction::CreateDir { path } => ops::crear_directorio(id, path),
+        QueueAction::Move { src, dest } => ops::mover(id, src, dest),
+        QueueAction::Copy { src, dest } => ops::copiar(id, src, dest),
+        QueueAction::Sync { plan } => primer_fallo(sync::ejecutar(id, plan, progreso)?),
+        QueueAction::Backup { dest } => primer_fallo(backup::respaldar(id, dest, None, progreso)?),
+        QueueAction::Restore { src, mode } => {
+            primer_fallo(backup::restaurar(id, src, *mode, progreso)?)
+        }
+        QueueAction::Snapshot { repo } => {
+            let repo = elegir(repo, &destinos.repositorio, "repositorio")?;
+            primer_fallo(repository::crear(id, repo, progreso)?.results)
+        }
+        QueueAction::SnapshotRestore {
+            snapshot,
+            mode,
+            repo,
+        } => {
+            let repo = elegir(repo, &destinos.repositorio, "repositorio")?;
+            primer_fallo(repository::restaurar(id, repo, snapshot, *mode, progreso)?)
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! Repositorio local de respaldos incrementales para varias calculadoras.
+//! El contenido de cada archivo se guarda una sola vez en `objects/`, con su
+//! hash SHA-256 como nombre, y cada instantanea es un json en `snapshots/` con
+//! el arbol de una calculadora y el hash de cada archivo. Las calculadoras con
+//! el mismo contenido comparten los objetos, y una instantanea nueva solo
+//! descarga los archivos que cambiaron desde la anterior del mismo dispositivo.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::backup::{Restauracion, RestoreMode};
+use crate::error::{DLinkError, ErrorCode};
+use crate::ops::{self, Progreso};
+use crate::transport::TransportError;
+use crate::types::{DeviceId, DeviceInfo, FileInfo, FileResult};
+
+/// Version del formato de las instantaneas
+pub const VERSION_INSTANTANEA: u32 = 1;
+
+/// Directorio del repositorio con el contenido de los archivos
+pub const DIRECTORIO_OBJETOS: &str = "objects";
+
+/// Directorio del repositorio con las instantaneas
+pub const DIRECTORIO_INSTANTANEAS: &str = "snapshots";
+
+/// Entrada del arbol de una instantanea
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotEntry {
+    pub path: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub date: u64,
+    /// SHA-256 del contenido en hexadecimal; `None` en los directorios
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// Estado de una calculadora en un momento dado
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub version: u32,
+    pub id: String,
+    /// Id estable del dispositivo respaldado
+    pub device: String,
+    /// Segundos desde la epoca Unix
+    pub created: u64,
+    pub info: DeviceInfo,
+    /// Directorios antes que su contenido. Los archivos que no se pudieron
+    /// leer no aparecen.
+    pub entries: Vec<SnapshotEntry>,
+}
+
+/// Resumen de una instantanea para listarlas
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSummary {
+    pub id: String,
+    pub device: String,
+    /// Nombre de la calculadora al momento de la instantanea
+    pub name: String,
+    pub created: u64,
+    pub files: usize,
+    pub size: u64,
+}
+
+impl From<&Snapshot> for SnapshotSummary {
+    fn from(instantanea: &Snapshot) -> Self {
+        let archivos = instantanea.entries.iter().filter(|e| !e.is_dir);
+        SnapshotSummary {
+            id: instantanea.id.clone(),
+            device: instantanea.device.clone(),
+            name: instantanea.info.name.clone(),
+            created: instantanea.created,
+            files: archivos.clone().count(),
+            size: archivos.map(|e| e.size).sum(),
+        }
+    }
+}
+
+/// Instantaneas de un repositorio (o sus resumenes), con el error de cada una
+/// que no se pudo leer
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotList<T = Snapshot> {
+    pub snapshots: Vec<T>,
+    /// Las instantaneas que se omitieron, cada error con su ruta
+    pub invalid: Vec<DLinkError>,
+}
+
+impl SnapshotList {
+    /// La misma lista con el resumen de cada instantanea
+    pub fn resumir(&self) -> SnapshotList<SnapshotSummary> {
+        SnapshotList {
+            snapshots: self.snapshots.iter().map(SnapshotSummary::from).collect(),
+            invalid: self.invalid.clone(),
+        }
+    }
+}
+
+/// Resultado de crear una instantanea
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotReport {
+    pub snapshot: SnapshotSummary,
+    /// Archivos descargados de la calculadora
+    pub downloaded: usize,
+    /// Archivos sin cambios tomados de la instantanea anterior
+    pub reused: usize,
+    /// Resultado de cada archivo descargado
+    pub results: Vec<FileResult>,
+}
+
+/// Diferencias entre dos instantaneas, con las entradas de la mas nueva salvo
+/// en `removed`
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotDiff {
+    pub added: Vec<SnapshotEntry>,
+    pub removed: Vec<SnapshotEntry>,
+    pub modified: Vec<SnapshotEntry>,
+}
+
+/// Error para los archivos del repositorio
+fn error_local(ruta: &Path) -> impl Fn(io::Error) -> DLinkError + '_ {
+    move |e| DLinkError::from(e).con_ruta(ruta.display())
+}
+
+/// Ruta del objeto con el hash dado; se reparten en subdirectorios por los dos
+/// primeros caracteres para no juntar miles de archivos en uno solo
+fn ruta_objeto(repo: &Path, hash: &str) -> PathBuf {
+    repo.join(DIRECTORIO_OBJETOS).join(&hash[..2]).join(hash)
+}
+
+/// Guarda `datos` en el repositorio si no estaban y devuelve su hash
+fn guardar_objeto(repo: &Path, datos: &[u8]) -> Result<String, DLinkError> {
+    let hash = format!("{:x}", Sha256::digest(datos));
+    let ruta = ruta_objeto(repo, &hash);
+    if !ruta.exists() {
+        if let Some(padre) = ruta.parent() {
+            fs::create_dir_all(padre).map_err(error_local(padre))?;
+        }
+        ops::escribir_atomico(&ruta, datos)?;
+    }
+    Ok(hash)
+}
+
+/// Los ids de instantanea son nombres de archivo; se rechaza cualquier otra cosa
+fn ruta_instantanea(repo: &Path, id_instantanea: &str) -> Result<PathBuf, DLinkError> {
+    if id_instantanea.is_empty()
+        || !id_instantanea
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(DLinkError::app(
+            ErrorCode::InvalidArgument,
+            format!("Id de instantanea no valido: {}", id_instantanea),
+        ));
+    }
+    Ok(repo
+        .join(DIRECTORIO_INSTANTANEAS)
+        .join(format!("{}.json", id_instantanea)))
+}
+
+/// Indica si `hash` es un SHA-256 como los que escribe `guardar_objeto`: 64
+/// caracteres hexadecimales en minusculas. Los hashes se usan como rutas, asi
+/// que no se acepta ningun otro.
+fn hash_valido(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Lee la instantanea `id_instantanea` del repositorio
+pub fn cargar(repo: &Path, id_instantanea: &str) -> Result<Snapshot, DLinkError> {
+    let ruta = ruta_instantanea(repo, id_instantanea)?;
+    let json = fs::read(&ruta).map_err(error_local(&ruta))?;
+    let instantanea: Snapshot = serde_json::from_slice(&json).map_err(|e| {
+        DLinkError::app(
+            ErrorCode::InvalidArgument,
+            format!("La instantanea no es valida: {}", e),
+        )
+        .con_ruta(ruta.display())
+    })?;
+
+    if let Some(entrada) = instantanea.entries.iter().find(|entrada| {
+        entrada
+            .hash
+            .as_deref()
+            .is_some_and(|hash| !hash_valido(hash))
+    }) {
+        return Err(DLinkError::app(
+            ErrorCode::InvalidArgument,
+            format!(
+                "La instantanea tiene un hash no valido para {}",
+                entrada.path
+            ),
+        )
+        .con_ruta(ruta.display()));
+    }
+    Ok(instantanea)
+}
+
+/// Instantaneas del repositorio, de la mas vieja a la mas nueva, solo las del
+/// dispositivo con id estable `dispositivo` si se indica. Un repositorio que
+/// todavia no existe no tiene instantaneas.
+///
+/// Las instantaneas que no se pueden leer no impiden listar las demas: quedan
+/// en `invalid` con su error.
+pub fn listar(repo: &Path, dispositivo: Option<&str>) -> Result<SnapshotList, DLinkError> {
+    let mut lista = SnapshotList {
+        snapshots: vec![],
+        invalid: vec![],
+    };
+    let directorio = repo.join(DIRECTORIO_INSTANTANEAS);
+    let lectura = match fs::read_dir(&directorio) {
+        Ok(lectura) => lectura,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(lista),
+        Err(e) => return Err(error_local(&directorio)(e)),
+    };
+
+    for entrada in lectura {
+        let nombre = entrada.map_err(error_local(&directorio))?.file_name();
+        let Some(id_instantanea) = nombre.to_str().and_then(|n| n.strip_suffix(".json")) else {
+            continue;
+        };
+        match cargar(repo, id_instantanea) {
+            Ok(instantanea) if dispositivo.is_none_or(|d| d == instantanea.device) => {
+                lista.snapshots.push(instantanea)
+            }
+            Ok(_) => {}
+            Err(error) => lista
+                .invalid
+                .push(error.con_ruta(directorio.join(&nombre).display())),
+        }
+    }
+    lista
+        .snapshots
+        .sort_by(|a, b| (a.created, &a.id).cmp(&(b.created, &b.id)));
+    Ok(lista)
+}
+
+/// Toma una instantanea de la calculadora `id` en el repositorio `repo`, que se
+/// crea si no existe.
+///
+/// Los archivos con la misma ruta, tamano y fecha que en la instantanea
+/// anterior del mismo dispositivo no se descargan; el resto se descarga, y su
+/// contenido solo se guarda si ningun otro archivo del repositorio lo tenia. El
+/// progreso es el agregado de lo que se descarga. Un archivo que falla no entra
+/// en la instantanea, y si la operacion se interrumpe no se guarda.
+pub fn crear(id: DeviceId, repo: &Path, progreso: Progreso) -> Result<SnapshotReport, DLinkError> {
+    let info = ops::actualizar_info(id)?;
+    let anteriores: HashMap<String, SnapshotEntry> = listar(repo, Some(&info.stable_id))?
+        .snapshots
+        .pop()
+        .map(|anterior| {
+            anterior
+                .entries
+                .into_iter()
+                .filter(|e| {
+                    e.hash
+                        .as_deref()
+                        .is_some_and(|h| ruta_objeto(repo, h).exists())
+                })
+                .map(|e| (e.path.clone(), e))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let sin_cambios = |entrada: &FileInfo| {
+        anteriores
+            .get(&entrada.path)
+            .filter(|anterior| anterior.size == entrada.size && anterior.date == entrada.date)
+            .and_then(|anterior| anterior.hash.clone())
+    };
+
+    let arbol = ops::listar_arbol(id, "/")?;
+    let total: u64 = arbol
+        .iter()
+        .filter(|e| !e.is_dir && sin_cambios(e).is_none())
+        .map(|e| e.size)
+        .sum();
+    let total = total as usize;
+
+    let mut entradas = Vec::with_capacity(arbol.len());
+    let mut resultados = vec![];
+    let mut reutilizados = 0;
+    let mut completado = 0;
+    for entrada in arbol {
+        let hash = if entrada.is_dir {
+            None
+        } else if let Some(hash) = sin_cambios(&entrada) {
+            reutilizados += 1;
+            Some(hash)
+        } else {
+            if progreso(total.saturating_sub(completado), total).is_break() {
+                return Err(DLinkError::from(TransportError::Cancelled).con_dispositivo(id));
+            }
+            let datos = ops::leer(id, &entrada.path, entrada.size, &mut |restante, tamano| {
+                progreso(
+                    ops::restante_agregado(total, completado, restante, tamano),
+                    total,
+                )
+            });
+            completado += entrada.size as usize;
+
+            match datos.and_then(|datos| guardar_objeto(repo, &datos)) {
+                Ok(hash) => {
+                    resultados.push(FileResult {
+                        path: entrada.path.clone(),
+                        error: None,
+                        outcome: None,
+                    });
+                    Some(hash)
+                }
+                Err(error) if error.interrumpe() => return Err(error),
+                Err(error) => {
+                    resultados.push(FileResult {
+                        path: entrada.path,
+                        error: Some(error),
+                        outcome: None,
+                    });
+                    continue;
+                }
+            }
+        };
+        entradas.push(SnapshotEntry {
+            path: entrada.path,
+            is_dir: entrada.is_dir,
+            size: entrada.size,
+            date: entrada.date,
+            hash,
+        });
+    }
+    let _ = progreso(0, total);
+
+    let creado = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duracion| duracion.as_secs());
+    // El id estable puede tener caracteres que no van en un nombre de archivo
+    let base: String = info
+        .stable_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let mut id_instantanea = format!("{}-{}", base, creado);
+    let mut repeticion = 1;
+    while ruta_instantanea(repo, &id_instantanea)?.exists() {
+        repeticion += 1;
+        id_instantanea = format!("{}-{}-{}", base, creado, repeticion);
+    }
+
+    let instantanea = Snapshot {
+        version: VERSION_INSTANTANEA,
+        id: id_instantanea,
+        device: info.stable_id.clone(),
+        created: creado,
+        info,
+        entries: entradas,
+    };
+    let ruta = ruta_instantanea(repo, &instantanea.id)?;
+    if let Some(padre) = ruta.parent() {
+        fs::create_dir_all(padre).map_err(error_local(padre))?;
+    }
+    let json = serde_json::to_vec_pretty(&instantanea).map_err(DLinkError::interno)?;
+    ops::escribir_atomico(&ruta, &json)?;
+
+    Ok(SnapshotReport {
+        snapshot: SnapshotSummary::from(&instantanea),
+        downloaded: resultados.len(),
+        reused: reutilizados,
+        results: resultados,
+    })
+}
+
+/// Compara la instantanea `desde` con la posterior `hasta`, que pueden ser de
+/// distintas calculadoras. Un archivo cambia si cambia su contenido, o si pasa
+/// a ser directorio o al reves; las fechas no cuentan.
+pub fn comparar(repo: &Path, desde: &str, hasta: &str) -> Result<SnapshotDiff, DLinkError> {
+    let antes: HashMap<String, SnapshotEntry> = cargar(repo, desde)?
+        .entries
+        .into_iter()
+        .map(|e| (e.path.clone(), e))
+        .collect();
+    let despues = cargar(repo, hasta)?.entries;
+
+    let mut diferencias = SnapshotDiff::default();
+    let presentes: BTreeSet<&str> = despues.iter().map(|e| e.path.as_str()).collect();
+    for entrada in &despues {
+        match antes.get(&entrada.path) {
+            None => diferencias.added.push(entrada.clone()),
+            Some(anterior)
+                if anterior.is_dir != entrada.is_dir || anterior.hash != entrada.hash =>
+            {
+                diferencias.modified.push(entrada.clone())
+            }
+            Some(_) => {}
+        }
+    }
+    let mut eliminadas: Vec<SnapshotEntry> = antes
+        .into_values()
+        .filter(|e| !presentes.contains(e.path.as_str()))
+        .collect();
+    eliminadas.sort_by(|a, b| a.path.cmp(&b.path));
+    diferencias.removed = eliminadas;
+
+    Ok(diferencias)
+}
+
+/// Restaura en la calculadora `id` la instantanea `id_instantanea`, que puede
+/// ser de otra calculadora, con los mismos modos y comprobaciones que
+/// `backup::restaurar`. Un archivo cuyo contenido falta en el repositorio queda
+/// como fallido.
+pub fn restaurar(
+    id: DeviceId,
+    repo: &Path,
+    id_instantanea: &str,
+    modo: RestoreMode,
+    progreso: Progreso,
+) -> Result<Vec<FileResult>, DLinkError> {
+    let instantanea = cargar(repo, id_instantanea)?;
+
+    let mut directorios = BTreeSet::new();
+    let mut archivos = BTreeMap::new();
+    let mut hashes = HashMap::new();
+    for entrada in instantanea.entries {
+        if entrada.is_dir {
+            directorios.insert(entrada.path);
+        } else if let Some(hash) = entrada.hash {
+            archivos.insert(entrada.path.clone(), entrada.size);
+            hashes.insert(entrada.path, hash);
+        }
+    }
+
+    let mut restauracion =
+        Restauracion::preparar(id, directorios, &mut archivos, modo, vec![], progreso)?;
+    for ruta in archivos.into_keys() {
+        restauracion.continuar(progreso)?;
+        let objeto = ruta_objeto(repo, &hashes[&ruta]);
+        match fs::read(&objeto) {
+            Ok(datos) => restauracion.subir(ruta, datos, progreso)?,
+            Err(e) => restauracion.registrar(ruta, Err(error_local(&objeto)(e)))?,
+        }
+    }
+
+    Ok(restauracion.terminar(progreso))
+}
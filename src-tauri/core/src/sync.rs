@@ -0,0 +1,537 @@
+//! Sincronizacion entre una carpeta local y una carpeta de la calculadora.
+//! Primero se calcula un `SyncPlan` comparando ambos arboles por nombre, tamano
+//! y fecha; la interfaz lo puede mostrar antes de ejecutarlo, y la ejecucion
+//! recorre las acciones con las operaciones de `ops`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{DLinkError, ErrorCode};
+use crate::ops::{self, Progreso};
+use crate::transport::TransportError;
+use crate::types::{ConflictPolicy, DeviceId, FileResult};
+
+/// Diferencia maxima, en segundos, entre dos fechas que se consideran iguales
+const TOLERANCIA_FECHA: u64 = 2;
+
+/// Sentido de la sincronizacion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncMode {
+    /// La carpeta de la calculadora queda igual que la local
+    MirrorToDevice,
+    /// La carpeta local queda igual que la de la calculadora
+    MirrorToLocal,
+    /// Se copian los archivos nuevos o modificados en ambos sentidos; no se
+    /// elimina nada
+    TwoWay,
+}
+
+/// Accion de un plan de sincronizacion. Las rutas `remote` son de la
+/// calculadora y las `local` del sistema de archivos.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SyncAction {
+    Upload {
+        local: PathBuf,
+        remote: String,
+        size: u64,
+    },
+    /// `date` es la del archivo en la calculadora, que la copia local conserva
+    Download {
+        remote: String,
+        local: PathBuf,
+        size: u64,
+        #[serde(default)]
+        date: u64,
+    },
+    CreateRemoteDir {
+        remote: String,
+    },
+    CreateLocalDir {
+        local: PathBuf,
+    },
+    /// Los directorios se eliminan con todo su contenido
+    DeleteRemote {
+        remote: String,
+        dir: bool,
+    },
+    /// Los directorios se eliminan con todo su contenido
+    DeleteLocal {
+        local: PathBuf,
+        dir: bool,
+    },
+}
+
+impl SyncAction {
+    /// Bytes que transfiere la accion
+    pub(crate) fn bytes(&self) -> u64 {
+        match self {
+            SyncAction::Upload { size, .. } | SyncAction::Download { size, .. } => *size,
+            _ => 0,
+        }
+    }
+
+    /// Ruta con la que la accion aparece en el resumen
+    fn ruta(&self) -> String {
+        match self {
+            SyncAction::Upload { remote, .. }
+            | SyncAction::Download { remote, .. }
+            | SyncAction::CreateRemoteDir { remote }
+            | SyncAction::DeleteRemote { remote, .. } => remote.clone(),
+            SyncAction::CreateLocalDir { local } | SyncAction::DeleteLocal { local, .. } => {
+                local.display().to_string()
+            }
+        }
+    }
+}
+
+/// Plan de sincronizacion. Las acciones estan en orden de ejecucion:
+/// eliminaciones, creacion de directorios y transferencias.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncPlan {
+    pub mode: SyncMode,
+    pub local: PathBuf,
+    pub remote: String,
+    pub actions: Vec<SyncAction>,
+    /// Rutas relativas que existen en ambos lados con distinto tipo (archivo y
+    /// directorio) y que el modo bidireccional no resuelve
+    pub conflicts: Vec<String>,
+}
+
+impl SyncPlan {
+    /// Carpetas de origen y de destino segun el modo; en el bidireccional, la
+    /// local y la de la calculadora
+    pub fn origen_destino(&self) -> (Option<String>, Option<String>) {
+        let local = Some(self.local.display().to_string());
+        let remota = Some(self.remote.clone());
+        match self.mode {
+            SyncMode::MirrorToLocal => (remota, local),
+            SyncMode::MirrorToDevice | SyncMode::TwoWay => (local, remota),
+        }
+    }
+
+    /// Comprueba que cada accion quede dentro de las carpetas del plan. El plan
+    /// llega de la interfaz, asi que no se ejecuta una accion que apunte fuera
+    /// (con `..`, por ejemplo); las eliminaciones tampoco pueden apuntar a la
+    /// carpeta misma. Las transferencias conservan el nombre del archivo, asi
+    /// que el local y el remoto tienen que coincidir.
+    pub fn validar(&self) -> Result<(), DLinkError> {
+        let raiz_remota = self.remote.trim_end_matches('/');
+        for accion in &self.actions {
+            let valida = match accion {
+                SyncAction::Upload { local, remote, .. }
+                | SyncAction::Download { remote, local, .. } => {
+                    dentro_local(&self.local, local, false)
+                        && dentro_remota(raiz_remota, remote, false)
+                        && local.file_name().and_then(|n| n.to_str()) == remote.rsplit('/').next()
+                }
+                SyncAction::CreateRemoteDir { remote } => dentro_remota(raiz_remota, remote, true),
+                SyncAction::CreateLocalDir { local } => dentro_local(&self.local, local, true),
+                SyncAction::DeleteRemote { remote, .. } => {
+                    dentro_remota(raiz_remota, remote, false)
+                }
+                SyncAction::DeleteLocal { local, .. } => dentro_local(&self.local, local, false),
+            };
+            if !valida {
+                return Err(DLinkError::app(
+                    ErrorCode::InvalidArgument,
+                    "La accion esta fuera de las carpetas sincronizadas",
+                )
+                .con_ruta(accion.ruta()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Indica si `ruta` esta dentro de la carpeta local `raiz`, sin `..` ni otros
+/// componentes que la saquen de ella. Con `incluida`, la raiz misma tambien vale.
+fn dentro_local(raiz: &Path, ruta: &Path, incluida: bool) -> bool {
+    match ruta.strip_prefix(raiz) {
+        Ok(resto) => {
+            (incluida || resto.components().next().is_some())
+                && resto
+                    .components()
+                    .all(|parte| matches!(parte, Component::Normal(_)))
+        }
+        Err(_) => false,
+    }
+}
+
+/// Indica si `ruta` esta dentro de la carpeta `raiz` de la calculadora, sin
+/// `..`, `.` ni nombres vacios. Con `incluida`, la raiz misma tambien vale.
+fn dentro_remota(raiz: &str, ruta: &str, incluida: bool) -> bool {
+    if ruta == raiz || ruta == raiz_remota_o_barra(raiz) {
+        return incluida;
+    }
+    match ruta
+        .strip_prefix(raiz)
+        .and_then(|resto| resto.strip_prefix('/'))
+    {
+        Some(resto) => resto
+            .split('/')
+            .all(|parte| !parte.is_empty() && parte != "." && parte != ".."),
+        None => false,
+    }
+}
+
+/// Archivo o directorio de uno de los lados, por ruta relativa a la raiz
+struct Entrada {
+    es_dir: bool,
+    tamano: u64,
+    fecha: u64,
+}
+
+/// Lado que gana cuando un archivo existe en ambos y difiere
+enum Ganador {
+    Local,
+    Remoto,
+}
+
+/// Compara la carpeta `local` con la carpeta `remote` de la calculadora y
+/// calcula las acciones para sincronizarlas segun `modo`. Del lado local solo
+/// se consideran los archivos con alguna de las `extensiones` (todos si esta
+/// vacia); si una de las carpetas no existe se trata como vacia y se crea.
+///
+/// La calculadora pone la fecha actual a los archivos que recibe, asi que en
+/// los espejos un archivo solo se vuelve a copiar si cambio el tamano o si el
+/// origen es mas nuevo que el destino. En el modo bidireccional gana el mas
+/// nuevo de los dos, y dos archivos del mismo tamano y la misma fecha se
+/// consideran iguales; `ejecutar` deja la copia local con la fecha de la
+/// calculadora para que no se vuelvan a copiar.
+pub fn planificar(
+    id: DeviceId,
+    local: &Path,
+    remote: &str,
+    modo: SyncMode,
+    extensiones: &[&str],
+) -> Result<SyncPlan, DLinkError> {
+    let raiz_remota = remote.trim_end_matches('/');
+
+    let (remotas, existe_remota) = match ops::listar_arbol(id, raiz_remota_o_barra(raiz_remota)) {
+        Ok(entradas) => (entradas, true),
+        Err(error) if error.code == ErrorCode::NotFound => (vec![], false),
+        Err(error) => return Err(error),
+    };
+    let remotas: BTreeMap<String, Entrada> = remotas
+        .into_iter()
+        .map(|entrada| {
+            let relativa = entrada.path[raiz_remota.len()..]
+                .trim_start_matches('/')
+                .to_string();
+            let datos = Entrada {
+                es_dir: entrada.is_dir,
+                tamano: entrada.size,
+                fecha: entrada.date,
+            };
+            (relativa, datos)
+        })
+        .collect();
+
+    let existe_local = local.exists();
+    if existe_local && !local.is_dir() {
+        return Err(DLinkError::app(
+            ErrorCode::InvalidArgument,
+            "La ruta local no es un directorio",
+        )
+        .con_ruta(local.display()));
+    }
+    let locales: BTreeMap<String, Entrada> = if existe_local {
+        ops::listar_arbol_local(local, extensiones)?
+            .into_iter()
+            .map(|entrada| {
+                let datos = Entrada {
+                    es_dir: entrada.es_dir,
+                    tamano: entrada.tamano,
+                    fecha: entrada.fecha,
+                };
+                (entrada.relativa, datos)
+            })
+            .collect()
+    } else {
+        BTreeMap::new()
+    };
+
+    let ruta_remota = |relativa: &str| ops::unir_ruta(raiz_remota, relativa);
+    let ruta_local = |relativa: &str| {
+        relativa
+            .split('/')
+            .fold(local.to_path_buf(), |acumulada, parte| {
+                acumulada.join(parte)
+            })
+    };
+    let sube = modo != SyncMode::MirrorToLocal;
+    let baja = modo != SyncMode::MirrorToDevice;
+
+    let mut eliminaciones = vec![];
+    let mut directorios = vec![];
+    let mut transferencias = vec![];
+    let mut conflictos = vec![];
+
+    if sube && !existe_remota {
+        directorios.push(SyncAction::CreateRemoteDir {
+            remote: raiz_remota_o_barra(raiz_remota).to_string(),
+        });
+    }
+    if baja && !existe_local {
+        directorios.push(SyncAction::CreateLocalDir {
+            local: local.to_path_buf(),
+        });
+    }
+
+    // El orden alfabetico deja cada directorio antes que su contenido
+    let rutas: BTreeSet<&String> = locales.keys().chain(remotas.keys()).collect();
+    // Directorios que se eliminan o se omiten enteros
+    let mut omitidos: Vec<&str> = vec![];
+
+    for relativa in rutas {
+        if omitidos.iter().any(|dir| {
+            relativa
+                .strip_prefix(dir)
+                .is_some_and(|resto| resto.starts_with('/'))
+        }) {
+            continue;
+        }
+
+        let subir = |transferencias: &mut Vec<_>, directorios: &mut Vec<_>, l: &Entrada| {
+            if l.es_dir {
+                directorios.push(SyncAction::CreateRemoteDir {
+                    remote: ruta_remota(relativa),
+                });
+            } else {
+                transferencias.push(SyncAction::Upload {
+                    local: ruta_local(relativa),
+                    remote: ruta_remota(relativa),
+                    size: l.tamano,
+                });
+            }
+        };
+        let bajar = |transferencias: &mut Vec<_>, directorios: &mut Vec<_>, r: &Entrada| {
+            if r.es_dir {
+                directorios.push(SyncAction::CreateLocalDir {
+                    local: ruta_local(relativa),
+                });
+            } else {
+                transferencias.push(SyncAction::Download {
+                    remote: ruta_remota(relativa),
+                    local: ruta_local(relativa),
+                    size: r.tamano,
+                    date: r.fecha,
+                });
+            }
+        };
+
+        match (locales.get(relativa), remotas.get(relativa)) {
+            (Some(l), None) if sube => subir(&mut transferencias, &mut directorios, l),
+            (Some(l), None) => {
+                eliminaciones.push(SyncAction::DeleteLocal {
+                    local: ruta_local(relativa),
+                    dir: l.es_dir,
+                });
+                if l.es_dir {
+                    omitidos.push(relativa);
+                }
+            }
+            (None, Some(r)) if baja => bajar(&mut transferencias, &mut directorios, r),
+            (None, Some(r)) => {
+                eliminaciones.push(SyncAction::DeleteRemote {
+                    remote: ruta_remota(relativa),
+                    dir: r.es_dir,
+                });
+                if r.es_dir {
+                    omitidos.push(relativa);
+                }
+            }
+            (Some(l), Some(r)) if l.es_dir && r.es_dir => {}
+            (Some(l), Some(r)) if !l.es_dir && !r.es_dir => {
+                let ganador = match modo {
+                    SyncMode::MirrorToDevice => (l.tamano != r.tamano
+                        || l.fecha > r.fecha + TOLERANCIA_FECHA)
+                        .then_some(Ganador::Local),
+                    SyncMode::MirrorToLocal => (l.tamano != r.tamano
+                        || r.fecha > l.fecha + TOLERANCIA_FECHA)
+                        .then_some(Ganador::Remoto),
+                    SyncMode::TwoWay if l.fecha > r.fecha + TOLERANCIA_FECHA => {
+                        Some(Ganador::Local)
+                    }
+                    SyncMode::TwoWay if r.fecha > l.fecha + TOLERANCIA_FECHA => {
+                        Some(Ganador::Remoto)
+                    }
+                    // Con fechas iguales solo se nota un cambio de tamano
+                    SyncMode::TwoWay => (l.tamano != r.tamano).then_some(Ganador::Local),
+                };
+                match ganador {
+                    Some(Ganador::Local) => subir(&mut transferencias, &mut directorios, l),
+                    Some(Ganador::Remoto) => bajar(&mut transferencias, &mut directorios, r),
+                    None => {}
+                }
+            }
+            // Archivo en un lado y directorio en el otro
+            (Some(l), Some(r)) => match modo {
+                SyncMode::MirrorToDevice => {
+                    eliminaciones.push(SyncAction::DeleteRemote {
+                        remote: ruta_remota(relativa),
+                        dir: r.es_dir,
+                    });
+                    if r.es_dir {
+                        omitidos.push(relativa);
+                    }
+                    subir(&mut transferencias, &mut directorios, l);
+                }
+                SyncMode::MirrorToLocal => {
+                    eliminaciones.push(SyncAction::DeleteLocal {
+                        local: ruta_local(relativa),
+                        dir: l.es_dir,
+                    });
+                    if l.es_dir {
+                        omitidos.push(relativa);
+                    }
+                    bajar(&mut transferencias, &mut directorios, r);
+                }
+                SyncMode::TwoWay => {
+                    conflictos.push(relativa.clone());
+                    omitidos.push(relativa);
+                }
+            },
+            (None, None) => {}
+        }
+    }
+
+    let mut acciones = eliminaciones;
+    acciones.append(&mut directorios);
+    acciones.append(&mut transferencias);
+
+    Ok(SyncPlan {
+        mode: modo,
+        local: local.to_path_buf(),
+        remote: remote.to_string(),
+        actions: acciones,
+        conflicts: conflictos,
+    })
+}
+
+/// Pone `fecha` como fecha de modificacion del archivo local `ruta`
+fn fechar(ruta: &Path, fecha: SystemTime) -> Result<(), DLinkError> {
+    fs::File::options()
+        .write(true)
+        .open(ruta)
+        .and_then(|archivo| archivo.set_modified(fecha))
+        .map_err(|e| DLinkError::from(e).con_ruta(ruta.display()))
+}
+
+/// La raiz de la calculadora se lista como `/`
+fn raiz_remota_o_barra(raiz: &str) -> &str {
+    if raiz.is_empty() {
+        "/"
+    } else {
+        raiz
+    }
+}
+
+/// Ejecuta las acciones de `plan` en orden, con el progreso agregado de los
+/// bytes transferidos. Si alguna accion queda fuera de las carpetas del plan no
+/// se ejecuta ninguna (ver `SyncPlan::validar`).
+pub fn ejecutar(
+    id: DeviceId,
+    plan: &SyncPlan,
+    progreso: Progreso,
+) -> Result<Vec<FileResult>, DLinkError> {
+    plan.validar()?;
+
+    let total: u64 = plan.actions.iter().map(SyncAction::bytes).sum();
+    let total = total as usize;
+
+    let mut resultados = Vec::with_capacity(plan.actions.len());
+    let mut completado = 0;
+    for accion in &plan.actions {
+        if progreso(total.saturating_sub(completado), total).is_break() {
+            return Err(DLinkError::from(TransportError::Cancelled).con_dispositivo(id));
+        }
+
+        // Progreso de una transferencia sumado a lo ya completado
+        let mut avance = |restante, tamano| {
+            progreso(
+                ops::restante_agregado(total, completado, restante, tamano),
+                total,
+            )
+        };
+        let resultado = match accion {
+            SyncAction::Upload { local, remote, .. } => {
+                let (dir_remoto, _) = remote.rsplit_once('/').unwrap_or_default();
+                // El plan ya decidio que archivos reemplazar
+                ops::subir(
+                    id,
+                    local,
+                    dir_remoto,
+                    ConflictPolicy::Overwrite,
+                    &mut avance,
+                )
+                // La copia de la calculadora tiene la fecha de ahora
+                .and_then(|_| fechar(local, SystemTime::now()))
+            }
+            SyncAction::Download {
+                remote,
+                local,
+                size,
+                date,
+            } => {
+                let dir_local = local.parent().unwrap_or(&plan.local);
+                ops::descargar(
+                    id,
+                    remote,
+                    Some(*size),
+                    dir_local,
+                    ConflictPolicy::Overwrite,
+                    &mut avance,
+                )
+                .and_then(|_| match date {
+                    0 => Ok(()),
+                    date => fechar(local, UNIX_EPOCH + Duration::from_secs(*date)),
+                })
+            }
+            SyncAction::CreateRemoteDir { remote } => ops::crear_directorio(id, remote),
+            SyncAction::CreateLocalDir { local } => {
+                fs::create_dir_all(local).map_err(|e| DLinkError::from(e).con_ruta(local.display()))
+            }
+            SyncAction::DeleteRemote { remote, dir: false } => ops::eliminar_archivo(id, remote),
+            SyncAction::DeleteRemote { remote, dir: true } => {
+                // Se detiene en el primer fallo, que es el ultimo resultado
+                ops::eliminar_recursivo(id, remote, false, &mut |_, _| avance(0, 0)).and_then(
+                    |resultados| match resultados.into_iter().find_map(|r| r.error) {
+                        Some(error) => Err(error),
+                        None => Ok(()),
+                    },
+                )
+            }
+            SyncAction::DeleteLocal { local, dir } => {
+                let resultado = if *dir {
+                    fs::remove_dir_all(local)
+                } else {
+                    fs::remove_file(local)
+                };
+                resultado.map_err(|e| DLinkError::from(e).con_ruta(local.display()))
+            }
+        };
+
+        match resultado {
+            Err(error) if error.interrumpe() => return Err(error),
+            resultado => {
+                completado += accion.bytes() as usize;
+                resultados.push(FileResult {
+                    path: accion.ruta(),
+                    error: resultado.err(),
+                    outcome: None,
+                });
+            }
+        }
+    }
+    let _ = progreso(0, total);
+
+    Ok(resultados)
+}
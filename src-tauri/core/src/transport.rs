@@ -0,0 +1,323 @@
+//! Abstraccion del canal de comunicacion con la calculadora.
+//! Los comandos hablan con un `CalculatorTransport` en lugar de usar
+//! directamente `libnspire::Handle`, lo que permite sustituir el hardware
+//! por otra implementacion (por ejemplo, en pruebas sin calculadora).
+
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use libnspire::dir::EntryType;
+use rusb::GlobalContext;
+
+use crate::convertir_info;
+use crate::types::{DeviceInfo, FileInfo};
+
+/// Error devuelto por un transporte
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportError {
+    /// La calculadora se desconecto
+    NoDevice,
+    /// La calculadora esta ocupada con otra operacion
+    Busy,
+    /// La calculadora no respondio a tiempo
+    Timeout,
+    /// No queda espacio en la calculadora
+    NoSpace,
+    /// La ruta no existe
+    NotFound,
+    /// La ruta ya existe
+    Exists,
+    /// El callback de progreso pidio cancelar la operacion
+    Cancelled,
+    /// El archivo, de los bytes indicados, supera `LECTURA_MAXIMA`
+    TooLarge(u64),
+    /// Fallo al escribir en el destino local los datos recibidos
+    Local(io::ErrorKind, String),
+    /// Cualquier otro error del protocolo
+    Other(String),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::NoDevice => write!(f, "Dispositivo desconectado"),
+            TransportError::Busy => write!(f, "Dispositivo ocupado"),
+            TransportError::Timeout => write!(f, "Tiempo de espera agotado"),
+            TransportError::NoSpace => write!(f, "Sin espacio en el dispositivo"),
+            TransportError::NotFound => write!(f, "La ruta no existe"),
+            TransportError::Exists => write!(f, "La ruta ya existe"),
+            TransportError::Cancelled => write!(f, "Operacion cancelada"),
+            TransportError::TooLarge(size) => write!(
+                f,
+                "El archivo ocupa {} bytes y se leen como maximo {} en memoria",
+                size, LECTURA_MAXIMA
+            ),
+            TransportError::Local(_, mensaje) => write!(f, "{}", mensaje),
+            TransportError::Other(mensaje) => write!(f, "{}", mensaje),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<io::Error> for TransportError {
+    fn from(error: io::Error) -> Self {
+        TransportError::Local(error.kind(), error.to_string())
+    }
+}
+
+impl From<libnspire::Error> for TransportError {
+    fn from(error: libnspire::Error) -> Self {
+        match error {
+            libnspire::Error::NoDevice => TransportError::NoDevice,
+            libnspire::Error::Busy => TransportError::Busy,
+            libnspire::Error::Timeout => TransportError::Timeout,
+            libnspire::Error::Exists => TransportError::Exists,
+            libnspire::Error::NonExist => TransportError::NotFound,
+            otro => TransportError::Other(otro.to_string()),
+        }
+    }
+}
+
+/// Tamano maximo de un archivo que `read_to_vec` lee en memoria. Es mayor que
+/// la memoria de cualquier calculadora; solo protege de un tamano de listado
+/// invalido.
+pub const LECTURA_MAXIMA: u64 = 1024 * 1024 * 1024;
+
+/// Tamano de un archivo a leer en memoria, rechazado si supera `LECTURA_MAXIMA`
+fn tamano_en_memoria(size: u64) -> Result<usize, TransportError> {
+    match usize::try_from(size) {
+        Ok(tamano) if size <= LECTURA_MAXIMA => Ok(tamano),
+        _ => Err(TransportError::TooLarge(size)),
+    }
+}
+
+/// Ruta unica junto a `path` con la que se escribe un archivo antes de
+/// reemplazarlo, o se aparta el original mientras tanto:
+/// `/Clase/~4242-1-tarea.tns` para `/Clase/tarea.tns`. Conserva la extension.
+fn ruta_temporal(path: &str) -> String {
+    static CONTADOR: AtomicU64 = AtomicU64::new(0);
+
+    let unico = format!(
+        "~{}-{}-",
+        std::process::id(),
+        CONTADOR.fetch_add(1, Ordering::Relaxed)
+    );
+    match path.rsplit_once('/') {
+        Some((dir, nombre)) => format!("{}/{}{}", dir, unico, nombre),
+        None => format!("{}{}", unico, path),
+    }
+}
+
+/// Operaciones que los comandos necesitan de una calculadora.
+///
+/// Los callbacks de progreso reciben los bytes restantes, igual que en libnspire,
+/// y devuelven `ControlFlow::Break` para cancelar. Un transporte cancelado
+/// devuelve `TransportError::Cancelled` y no deja archivos a medio escribir.
+pub trait CalculatorTransport: Send {
+    /// Lee la informacion del sistema
+    fn info(&self) -> Result<DeviceInfo, TransportError>;
+
+    /// Lista el contenido de un directorio
+    fn list_dir(&self, path: &str) -> Result<Vec<FileInfo>, TransportError>;
+
+    /// Lee `size` bytes de `path` y los escribe en `destino`. Cada transporte
+    /// decide cuando: el emulador los entrega a medida que llegan, pero
+    /// libnspire solo lee archivos completos, asi que con la calculadora real
+    /// el archivo entero pasa por memoria y se escribe al terminar, con el
+    /// limite de `read_to_vec`.
+    fn read_file(
+        &self,
+        path: &str,
+        size: u64,
+        destino: &mut dyn Write,
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError>;
+
+    /// Lee `size` bytes de `path` en memoria. Los transportes que ya leen en
+    /// un buffer propio lo devuelven sin copiarlo. Como el buffer se reserva
+    /// segun `size`, los archivos mayores que `LECTURA_MAXIMA` se rechazan con
+    /// `TransportError::TooLarge` sin leerlos.
+    fn read_to_vec(
+        &self,
+        path: &str,
+        size: u64,
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<Vec<u8>, TransportError> {
+        let mut datos = Vec::with_capacity(tamano_en_memoria(size)?);
+        self.read_file(path, size, &mut datos, progreso)?;
+        Ok(datos)
+    }
+
+    /// Escribe `datos` en `path`, reemplazando el archivo si existe. Si la
+    /// escritura falla o se cancela, el archivo anterior queda intacto.
+    fn write_file(
+        &self,
+        path: &str,
+        datos: &[u8],
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError>;
+
+    /// Envia una imagen de sistema operativo. Una imagen enviada se instala
+    /// igualmente, asi que el envio no se cancela una vez iniciado: lo que
+    /// devuelve `progreso` se ignora.
+    fn send_os(
+        &self,
+        datos: &[u8],
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError>;
+
+    /// Elimina un archivo
+    fn delete_file(&self, path: &str) -> Result<(), TransportError>;
+
+    /// Elimina un directorio vacio
+    fn delete_dir(&self, path: &str) -> Result<(), TransportError>;
+
+    /// Crea un directorio
+    fn create_dir(&self, path: &str) -> Result<(), TransportError>;
+
+    /// Mueve o renombra un archivo o directorio
+    fn move_file(&self, src: &str, dest: &str) -> Result<(), TransportError>;
+
+    /// Copia un archivo
+    fn copy_file(&self, src: &str, dest: &str) -> Result<(), TransportError>;
+}
+
+/// Implementacion sobre una calculadora real conectada por USB
+impl CalculatorTransport for libnspire::Handle<GlobalContext> {
+    fn info(&self) -> Result<DeviceInfo, TransportError> {
+        let info = libnspire::Handle::info(self)?;
+        Ok(convertir_info(&info))
+    }
+
+    fn list_dir(&self, path: &str) -> Result<Vec<FileInfo>, TransportError> {
+        let directorio = libnspire::Handle::list_dir(self, path)?;
+
+        Ok(directorio
+            .iter()
+            .map(|archivo| FileInfo {
+                path: archivo.name().to_string_lossy().to_string(),
+                is_dir: archivo.entry_type() == EntryType::Directory,
+                date: archivo.date(),
+                size: archivo.size(),
+            })
+            .collect())
+    }
+
+    fn read_file(
+        &self,
+        path: &str,
+        size: u64,
+        destino: &mut dyn Write,
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError> {
+        // libnspire solo lee archivos completos en memoria: se escribe al terminar
+        let buffer = self.read_to_vec(path, size, progreso)?;
+        destino.write_all(&buffer)?;
+        Ok(())
+    }
+
+    fn read_to_vec(
+        &self,
+        path: &str,
+        size: u64,
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<Vec<u8>, TransportError> {
+        let mut buffer = vec![0u8; tamano_en_memoria(size)?];
+        let mut cancelado = false;
+        libnspire::Handle::read_file(self, path, &mut buffer, &mut |restante| {
+            cancelado = cancelado || progreso(restante).is_break();
+        })?;
+
+        if cancelado {
+            return Err(TransportError::Cancelled);
+        }
+        Ok(buffer)
+    }
+
+    fn write_file(
+        &self,
+        path: &str,
+        datos: &[u8],
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError> {
+        // Se escribe con un nombre temporal y el original solo se reemplaza al
+        // terminar, para no perderlo si la escritura falla o se cancela
+        let temporal = ruta_temporal(path);
+        let mut cancelado = false;
+        let resultado = libnspire::Handle::write_file(self, &temporal, datos, &mut |restante| {
+            cancelado = cancelado || progreso(restante).is_break();
+        });
+
+        // libnspire no permite interrumpir la escritura: al cancelar se borra lo escrito
+        if resultado.is_err() || cancelado {
+            let _ = libnspire::Handle::delete_file(self, &temporal);
+            resultado?;
+            return Err(TransportError::Cancelled);
+        }
+
+        let resultado = match libnspire::Handle::move_file(self, &temporal, path) {
+            // La calculadora no renombra sobre un archivo existente: el original
+            // se aparta y solo se borra cuando el nuevo ya esta en su lugar
+            Err(libnspire::Error::Exists) => {
+                let apartado = ruta_temporal(path);
+                libnspire::Handle::move_file(self, path, &apartado).and_then(|()| {
+                    match libnspire::Handle::move_file(self, &temporal, path) {
+                        Ok(()) => {
+                            let _ = libnspire::Handle::delete_file(self, &apartado);
+                            Ok(())
+                        }
+                        Err(error) => {
+                            let _ = libnspire::Handle::move_file(self, &apartado, path);
+                            Err(error)
+                        }
+                    }
+                })
+            }
+            resultado => resultado,
+        };
+        if resultado.is_err() {
+            let _ = libnspire::Handle::delete_file(self, &temporal);
+        }
+        resultado?;
+        Ok(())
+    }
+
+    fn send_os(
+        &self,
+        datos: &[u8],
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<(), TransportError> {
+        libnspire::Handle::send_os(self, datos, &mut |restante| {
+            let _ = progreso(restante);
+        })?;
+        Ok(())
+    }
+
+    fn delete_file(&self, path: &str) -> Result<(), TransportError> {
+        libnspire::Handle::delete_file(self, path)?;
+        Ok(())
+    }
+
+    fn delete_dir(&self, path: &str) -> Result<(), TransportError> {
+        libnspire::Handle::delete_dir(self, path)?;
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(), TransportError> {
+        libnspire::Handle::create_dir(self, path)?;
+        Ok(())
+    }
+
+    fn move_file(&self, src: &str, dest: &str) -> Result<(), TransportError> {
+        libnspire::Handle::move_file(self, src, dest)?;
+        Ok(())
+    }
+
+    fn copy_file(&self, src: &str, dest: &str) -> Result<(), TransportError> {
+        libnspire::Handle::copy_file(self, src, dest)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! Estructuras de datos compartidas con las interfaces.
+//! Se serializan en camelCase, el formato que espera el frontend.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::DLinkError;
+use crate::operations::OperationId;
+
+/// Identificador unico de dispositivo USB
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceId {
+    pub bus_number: u8,
+    pub address: u8,
+}
+
+/// Payload enviado al frontend cuando se detecta un dispositivo
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AddDevicePayload {
+    #[serde(flatten)]
+    pub id: DeviceId,
+    /// Identificador estable, con el que los comandos direccionan el dispositivo
+    pub stable_id: String,
+    pub name: String,
+    pub is_cx_ii: bool,
+    pub needs_drivers: bool,
+}
+
+/// Tipo de operacion larga; los nombres son los de las acciones de la cola
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum OperationKind {
+    Download,
+    DownloadDir,
+    Upload,
+    UploadDir,
+    UploadOs,
+    Delete,
+    CreateDir,
+    Move,
+    Copy,
+    Sync,
+    Backup,
+    Restore,
+    Snapshot,
+    SnapshotRestore,
+    Batch,
+}
+
+/// Que hace una operacion larga. Lo llevan todos sus eventos, para que la
+/// interfaz no tenga que adivinar a que operacion pertenecen.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationDetails {
+    /// Id de la operacion, el que acepta la cancelacion
+    pub operation_id: OperationId,
+    pub kind: OperationKind,
+    /// Ruta de origen, de la calculadora o del sistema segun el tipo
+    pub src: Option<String>,
+    /// Ruta de destino, de la calculadora o del sistema segun el tipo
+    pub dest: Option<String>,
+    /// Dentro de un lote, posicion de la accion en curso; `kind`, `src` y
+    /// `dest` son entonces los de esa accion
+    pub batch_index: Option<usize>,
+    /// Cantidad de acciones del lote
+    pub batch_count: Option<usize>,
+}
+
+/// Inicio, fin o fallo de una operacion larga
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationEvent {
+    #[serde(flatten)]
+    pub id: DeviceId,
+    #[serde(flatten)]
+    pub operation: OperationDetails,
+    /// Solo en los fallos; la cancelacion es un fallo con codigo `cancelled`
+    pub error: Option<DLinkError>,
+}
+
+/// Actualizacion de progreso para operaciones largas
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressUpdate {
+    #[serde(flatten)]
+    pub id: DeviceId,
+    /// Operacion a la que pertenece, con su id para poder cancelarla
+    #[serde(flatten)]
+    pub operation: OperationDetails,
+    pub remaining: usize,
+    pub total: usize,
+    /// Velocidad promediada en los ultimos segundos, en unidades de `total`
+    /// (bytes en las transferencias) por segundo; 0 hasta tener una muestra
+    pub rate: f64,
+    /// Tiempo desde el inicio de la operacion
+    pub elapsed_ms: u64,
+    /// Tiempo restante estimado con `rate`; `None` mientras no se conoce
+    pub eta_ms: Option<u64>,
+}
+
+/// Estado de la cola de trabajo de un dispositivo abierto
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkerStatus {
+    /// Hay una operacion en curso
+    pub busy: bool,
+    /// Operaciones esperando turno
+    pub queued: usize,
+    /// Operacion del trabajo en curso
+    pub running: Option<OperationId>,
+    /// Operacion de cada trabajo que espera turno, en orden: el indice es la
+    /// posicion en la cola. `None` para los trabajos que no son de una
+    /// operacion registrada, como listar un directorio.
+    pub waiting: Vec<Option<OperationId>>,
+}
+
+/// Informacion de un archivo/directorio
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileInfo {
+    pub path: String,
+    pub is_dir: bool,
+    pub date: u64,
+    pub size: u64,
+}
+
+/// Resultado de un archivo dentro de una operacion sobre varios
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileResult {
+    pub path: String,
+    /// `None` si el archivo se proceso sin errores
+    pub error: Option<DLinkError>,
+    /// Solo en las subidas y descargas: que se hizo con el archivo segun la
+    /// politica de conflictos. `None` si fallo.
+    pub outcome: Option<TransferOutcome>,
+}
+
+/// Que hacer al transferir un archivo cuyo nombre ya existe en el directorio
+/// de destino: el de la calculadora al subir o el local al descargar
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictPolicy {
+    /// Se reemplaza el archivo existente
+    #[default]
+    Overwrite,
+    /// No se transfiere; el archivo existente no se toca
+    Skip,
+    /// Se transfiere con un sufijo numerado (`nombre_1.tns`, `nombre_2.tns`...)
+    KeepBoth,
+    /// No se transfiere y se devuelve `ErrorCode::AlreadyExists`, para que la
+    /// interfaz pregunte y lo vuelva a intentar con otra politica
+    Ask,
+}
+
+impl ConflictPolicy {
+    /// Politica de las descargas que no indican otra. Al subir se reemplaza el
+    /// archivo de la calculadora (`Default`), pero un archivo local solo se
+    /// sobrescribe si se pide.
+    pub fn descarga_por_defecto() -> Self {
+        ConflictPolicy::Ask
+    }
+}
+
+/// Lo que se hizo con un archivo transferido
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferOutcome {
+    /// No existia y se creo
+    Created,
+    /// Existia y se reemplazo
+    Overwritten,
+    /// Existia y se dejo como estaba
+    Skipped,
+    /// Existia y se transfirio con otro nombre
+    Renamed,
+}
+
+/// Informacion de memoria del dispositivo
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryInfo {
+    pub free: u64,
+    pub total: u64,
+}
+
+/// Informacion completa del dispositivo abierto
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInfo {
+    /// Identificador estable; lo completa `ops`, los transportes lo dejan vacio
+    pub stable_id: String,
+    pub name: String,
+    pub free_storage: u64,
+    pub total_storage: u64,
+    pub free_ram: u64,
+    pub total_ram: u64,
+    pub battery: u8,
+    pub is_charging: bool,
+    pub os_version: String,
+    pub boot1_version: String,
+    pub boot2_version: String,
+    pub hw_type: u8,
+    pub clock_speed: u8,
+    pub lcd_width: u16,
+    pub lcd_height: u16,
+    pub lcd_bpp: u8,
+    pub lcd_sample_mode: u8,
+}
@@ -0,0 +1,230 @@
+//! Deteccion de calculadoras TI-Nspire en el bus USB.
+//! Basado en n-link: enumeracion, lectura del nombre y monitor hotplug.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use libnspire::{PID, PID_CX2, VID};
+use rusb::{GlobalContext, Hotplug, UsbContext};
+
+use crate::error::DLinkError;
+use crate::events::{Event, EventSink};
+use crate::types::{AddDevicePayload, DeviceId};
+use crate::{derivar_id_estable, Device, DeviceSource, DeviceState, DEVICES};
+
+/// Intenta agregar un dispositivo USB a la lista.
+/// Retorna el ID y los datos del dispositivo si es valido.
+pub fn agregar_dispositivo(
+    dispositivo: Arc<rusb::Device<GlobalContext>>,
+) -> rusb::Result<((u8, u8), Device)> {
+    let descriptor = dispositivo.device_descriptor()?;
+
+    // Verificar que sea un dispositivo TI-Nspire
+    if !(descriptor.vendor_id() == VID && matches!(descriptor.product_id(), PID | PID_CX2)) {
+        return Err(rusb::Error::Other);
+    }
+
+    // Intentar abrir el dispositivo para leer el nombre y el numero de serie
+    let (nombre, serie, necesita_drivers) = match dispositivo.open() {
+        Ok(handle) => {
+            let idiomas = handle.read_languages(Duration::from_millis(100))?;
+            let (nombre_producto, serie) = if !idiomas.is_empty() {
+                let nombre = handle.read_product_string(
+                    idiomas[0],
+                    &descriptor,
+                    Duration::from_millis(100),
+                )?;
+                let serie = descriptor
+                    .serial_number_string_index()
+                    .and_then(|_| {
+                        handle
+                            .read_serial_number_string(
+                                idiomas[0],
+                                &descriptor,
+                                Duration::from_millis(100),
+                            )
+                            .ok()
+                    })
+                    .map(|serie| serie.trim().to_string())
+                    .filter(|serie| !serie.is_empty());
+                (nombre, serie)
+            } else {
+                (obtener_nombre_por_pid(descriptor.product_id()), None)
+            };
+            (nombre_producto, serie, false)
+        }
+        // En Windows sin drivers, no podemos abrir pero detectamos el dispositivo
+        Err(rusb::Error::NotSupported) | Err(rusb::Error::Access) => {
+            (obtener_nombre_por_pid(descriptor.product_id()), None, true)
+        }
+        Err(error) => return Err(error),
+    };
+
+    let es_cx_ii = descriptor.product_id() == PID_CX2;
+    let id_estable = derivar_id_estable(
+        descriptor.product_id(),
+        serie.as_deref(),
+        None,
+        &ubicacion(&dispositivo),
+    );
+
+    Ok((
+        (dispositivo.bus_number(), dispositivo.address()),
+        Device {
+            nombre,
+            id_estable,
+            id_producto: descriptor.product_id(),
+            serie,
+            origen: DeviceSource::Usb(dispositivo),
+            estado: DeviceState::Closed,
+            necesita_drivers,
+            es_cx_ii,
+        },
+    ))
+}
+
+/// Ubicacion fisica del dispositivo: bus y cadena de puertos desde el controlador
+fn ubicacion(dispositivo: &rusb::Device<GlobalContext>) -> String {
+    let puertos = dispositivo
+        .port_numbers()
+        .unwrap_or_default()
+        .iter()
+        .map(|puerto| puerto.to_string())
+        .collect::<Vec<_>>()
+        .join(".");
+    format!("{}-{}", dispositivo.bus_number(), puertos)
+}
+
+/// Obtiene el nombre del dispositivo basado en el Product ID
+fn obtener_nombre_por_pid(pid: u16) -> String {
+    if pid == PID_CX2 {
+        "TI-Nspire CX II".to_string()
+    } else {
+        "TI-Nspire".to_string()
+    }
+}
+
+/// Sincroniza `DEVICES` con los dispositivos presentes en el bus USB.
+/// Retorna los dispositivos nuevos y los identificadores de los que desaparecieron.
+pub fn sincronizar_dispositivos() -> Result<(Vec<AddDevicePayload>, Vec<DeviceId>), DLinkError> {
+    let dispositivos_usb: Vec<_> = rusb::devices()?.iter().collect();
+    let mut mapa = DEVICES.write().map_err(DLinkError::interno)?;
+
+    // Eliminar dispositivos desconectados (los emulados no aparecen en el bus)
+    let claves_a_eliminar: Vec<_> = mapa
+        .iter()
+        .filter(|(_, d)| matches!(d.origen, DeviceSource::Usb(_)))
+        .map(|(k, _)| *k)
+        .filter(|k| {
+            dispositivos_usb
+                .iter()
+                .all(|d| d.bus_number() != k.0 || d.address() != k.1)
+        })
+        .collect();
+
+    let mut eliminados = Vec::with_capacity(claves_a_eliminar.len());
+    for clave in claves_a_eliminar {
+        mapa.remove(&clave);
+        eliminados.push(DeviceId {
+            bus_number: clave.0,
+            address: clave.1,
+        });
+    }
+
+    // Filtrar dispositivos que ya conocemos
+    let nuevos_dispositivos: Vec<_> = dispositivos_usb
+        .into_iter()
+        .filter(|d| !mapa.contains_key(&(d.bus_number(), d.address())))
+        .collect();
+
+    // Agregar nuevos dispositivos
+    let agregados: Vec<AddDevicePayload> = nuevos_dispositivos
+        .into_iter()
+        .filter_map(|dev| agregar_dispositivo(Arc::new(dev)).ok())
+        .map(|(id, datos)| {
+            let payload = datos.payload(id);
+            mapa.insert(id, datos);
+            payload
+        })
+        .collect();
+
+    Ok((agregados, eliminados))
+}
+
+/// Monitor de conexion/desconexion USB (Hotplug)
+struct MonitorDispositivos {
+    eventos: Arc<dyn EventSink>,
+}
+
+impl Hotplug<GlobalContext> for MonitorDispositivos {
+    fn device_arrived(&mut self, dispositivo: rusb::Device<GlobalContext>) {
+        let eventos = self.eventos.clone();
+        let dispositivo = Arc::new(dispositivo);
+
+        // Procesar en hilo separado para no bloquear el callback
+        std::thread::spawn(move || {
+            // Reintentar si el dispositivo esta ocupado
+            for _ in 0..10 {
+                match agregar_dispositivo(dispositivo.clone()) {
+                    Ok((id, datos)) => {
+                        let payload = datos.payload(id);
+
+                        if let Ok(mut mapa) = DEVICES.write() {
+                            mapa.insert(id, datos);
+                        }
+
+                        eventos.emitir(Event::AddDevice(payload));
+                        return;
+                    }
+                    Err(rusb::Error::Busy) => {
+                        std::thread::sleep(Duration::from_millis(250));
+                    }
+                    Err(e) => {
+                        eprintln!("Error agregando dispositivo: {}", e);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    fn device_left(&mut self, dispositivo: rusb::Device<GlobalContext>) {
+        let clave = (dispositivo.bus_number(), dispositivo.address());
+
+        if let Ok(mut mapa) = DEVICES.write() {
+            if mapa.remove(&clave).is_some() {
+                self.eventos.emitir(Event::RemoveDevice(DeviceId {
+                    bus_number: clave.0,
+                    address: clave.1,
+                }));
+            }
+        }
+    }
+}
+
+/// Registra el monitor hotplug USB y lanza el hilo que procesa sus eventos.
+/// Retorna `false` si el sistema no soporta hotplug y hay que enumerar manualmente.
+pub fn iniciar_monitor(eventos: Arc<dyn EventSink>) -> bool {
+    if !rusb::has_hotplug() {
+        return false;
+    }
+
+    let monitor = MonitorDispositivos { eventos };
+
+    match GlobalContext::default().register_callback(Some(VID), None, None, Box::new(monitor)) {
+        Ok(_registration) => {
+            // Iniciar hilo de procesamiento de eventos USB
+            std::thread::spawn(|| loop {
+                if let Err(e) = GlobalContext::default().handle_events(None) {
+                    eprintln!("Error procesando eventos USB: {}", e);
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+            });
+        }
+        Err(e) => {
+            eprintln!("No se pudo registrar hotplug USB: {}", e);
+        }
+    }
+
+    true
+}
@@ -0,0 +1,210 @@
+//! Hilo de trabajo por dispositivo abierto.
+//! El hilo es el unico dueno del transporte y atiende los trabajos en orden de
+//! llegada. Quien envia un trabajo espera la respuesta por un canal, de modo que
+//! una operacion larga no bloquea a las demas mas alla de su turno en la cola y
+//! un panico dentro de una operacion no deja el dispositivo inutilizable.
+
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::{DLinkError, ErrorCode};
+use crate::operations::{self, OperationId};
+use crate::transport::{CalculatorTransport, TransportError};
+use crate::types::{DeviceId, WorkerStatus};
+
+/// Tiempo maximo sin noticias de un trabajo en curso (ni progreso ni resultado).
+/// La espera en cola no cuenta: una subida de sistema operativo puede tardar minutos.
+pub const TIEMPO_LIMITE_INACTIVIDAD: Duration = Duration::from_secs(60);
+
+/// Cada cuanto se consulta el callback de progreso mientras el trabajo espera turno
+const INTERVALO_SONDEO: Duration = Duration::from_millis(250);
+
+/// Trabajo que el hilo ejecuta con el transporte
+type Trabajo = Box<dyn FnOnce(&dyn CalculatorTransport) + Send>;
+
+/// Mensajes del hilo de trabajo hacia quien espera el resultado
+enum Mensaje<T> {
+    Iniciado,
+    Progreso(usize),
+    Terminado(Result<T, TransportError>),
+}
+
+/// Estado observable del hilo de trabajo: la operacion de cada trabajo, en
+/// curso y en espera, en el orden de la cola
+#[derive(Default)]
+struct Turnos {
+    /// `Some` mientras hay un trabajo en curso
+    en_curso: Option<Option<OperationId>>,
+    en_cola: VecDeque<Option<OperationId>>,
+}
+
+/// Hilo dedicado a un dispositivo abierto.
+/// Al soltar el ultimo `DeviceWorker` se cierra la cola y el hilo termina
+/// despues de atender los trabajos pendientes.
+pub struct DeviceWorker {
+    id: DeviceId,
+    cola: Sender<Trabajo>,
+    turnos: Arc<Mutex<Turnos>>,
+}
+
+/// Bloquea los turnos; un panico con el lock tomado no los invalida
+fn bloquear(turnos: &Mutex<Turnos>) -> MutexGuard<'_, Turnos> {
+    turnos.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl DeviceWorker {
+    /// Lanza el hilo de trabajo, que pasa a ser el dueno de `transporte`
+    pub fn iniciar(
+        id: DeviceId,
+        transporte: Box<dyn CalculatorTransport>,
+    ) -> Result<Self, DLinkError> {
+        let (cola, recibir) = mpsc::channel::<Trabajo>();
+        let turnos = Arc::new(Mutex::new(Turnos::default()));
+
+        let estado = turnos.clone();
+        thread::Builder::new()
+            .name(format!("d-link-{}-{}", id.bus_number, id.address))
+            .spawn(move || {
+                for trabajo in recibir {
+                    {
+                        let mut turnos = bloquear(&estado);
+                        let operacion = turnos.en_cola.pop_front().flatten();
+                        turnos.en_curso = Some(operacion);
+                    }
+                    trabajo(&*transporte);
+                    bloquear(&estado).en_curso = None;
+                }
+            })
+            .map_err(|e| DLinkError::from(e).con_dispositivo(id))?;
+
+        Ok(Self { id, cola, turnos })
+    }
+
+    /// Indica si hay una operacion en curso y que operaciones esperan turno,
+    /// en orden
+    pub fn estado(&self) -> WorkerStatus {
+        let turnos = bloquear(&self.turnos);
+        WorkerStatus {
+            busy: turnos.en_curso.is_some(),
+            queued: turnos.en_cola.len(),
+            running: turnos.en_curso.flatten(),
+            waiting: turnos.en_cola.iter().copied().collect(),
+        }
+    }
+
+    /// Ejecuta `operacion` en el hilo del dispositivo y espera su resultado.
+    ///
+    /// `progreso` se invoca en el hilo que llama, con los bytes restantes que
+    /// reporta el transporte; mientras el trabajo espera turno se consulta con
+    /// `restante_inicial`. Si devuelve `ControlFlow::Break` el trabajo se descarta
+    /// antes de empezar o el transporte lo corta en el siguiente bloque, y se
+    /// devuelve `ErrorCode::Cancelled`.
+    ///
+    /// Si el trabajo deja de dar noticias durante `TIEMPO_LIMITE_INACTIVIDAD` se
+    /// devuelve `ErrorCode::Timeout`; el resultado que llegue despues se descarta.
+    pub fn ejecutar<T: Send + 'static>(
+        &self,
+        operacion: impl FnOnce(
+                &dyn CalculatorTransport,
+                &mut dyn FnMut(usize) -> ControlFlow<()>,
+            ) -> Result<T, TransportError>
+            + Send
+            + 'static,
+        restante_inicial: usize,
+        progreso: &mut dyn FnMut(usize) -> ControlFlow<()>,
+    ) -> Result<T, DLinkError> {
+        let (responder, respuestas) = mpsc::channel::<Mensaje<T>>();
+        let cancelado = Arc::new(AtomicBool::new(false));
+
+        let marca = cancelado.clone();
+        let trabajo: Trabajo = Box::new(move |transporte| {
+            // Si quien espera ya se rindio no tiene sentido ocupar la calculadora
+            if responder.send(Mensaje::Iniciado).is_err() {
+                return;
+            }
+
+            let resultado = catch_unwind(AssertUnwindSafe(|| {
+                operacion(transporte, &mut |restante| {
+                    let _ = responder.send(Mensaje::Progreso(restante));
+                    if marca.load(Ordering::SeqCst) {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                })
+            }))
+            .unwrap_or_else(|_| {
+                Err(TransportError::Other(
+                    "La operacion termino de forma inesperada".to_string(),
+                ))
+            });
+            let _ = responder.send(Mensaje::Terminado(resultado));
+        });
+
+        // El turno se anota con la cola bloqueada para que quede en el mismo
+        // orden que el trabajo en el canal
+        {
+            let mut turnos = bloquear(&self.turnos);
+            if self.cola.send(trabajo).is_err() {
+                return Err(self.error_hilo_detenido());
+            }
+            turnos.en_cola.push_back(operations::en_curso());
+        }
+
+        // Esperar turno sin limite de tiempo, pero atendiendo la cancelacion.
+        // Al volver se suelta `respuestas` y el hilo descarta el trabajo.
+        loop {
+            match respuestas.recv_timeout(INTERVALO_SONDEO) {
+                Ok(Mensaje::Iniciado) => break,
+                Err(RecvTimeoutError::Timeout) => {
+                    if progreso(restante_inicial).is_break() {
+                        return Err(
+                            DLinkError::from(TransportError::Cancelled).con_dispositivo(self.id)
+                        );
+                    }
+                }
+                Ok(_) | Err(RecvTimeoutError::Disconnected) => {
+                    return Err(self.error_hilo_detenido())
+                }
+            }
+        }
+
+        // Una vez iniciado, vigilar la inactividad. Tras pedir la cancelacion se
+        // espera el resultado para que el transporte termine de limpiar.
+        loop {
+            match respuestas.recv_timeout(TIEMPO_LIMITE_INACTIVIDAD) {
+                Ok(Mensaje::Progreso(restante)) => {
+                    if progreso(restante).is_break() {
+                        cancelado.store(true, Ordering::SeqCst);
+                    }
+                }
+                Ok(Mensaje::Terminado(resultado)) => {
+                    return resultado.map_err(|e| DLinkError::from(e).con_dispositivo(self.id))
+                }
+                Ok(Mensaje::Iniciado) => {}
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(DLinkError::app(
+                        ErrorCode::Timeout,
+                        "La calculadora dejo de responder",
+                    )
+                    .con_dispositivo(self.id))
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(self.error_hilo_detenido()),
+            }
+        }
+    }
+
+    fn error_hilo_detenido(&self) -> DLinkError {
+        DLinkError::app(
+            ErrorCode::Internal,
+            "El hilo de trabajo del dispositivo se detuvo",
+        )
+        .con_dispositivo(self.id)
+    }
+}
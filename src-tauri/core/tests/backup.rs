@@ -0,0 +1,53 @@
+//! Restauracion de respaldos sobre una calculadora emulada
+
+use std::fs::{self, File};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use d_link_core::backup::{self, RestoreMode};
+use d_link_core::emulator::{registrar_emulado, EmulatedDevice};
+use d_link_core::error::ErrorCode;
+use d_link_core::ops;
+
+#[test]
+fn restaura_solo_la_primera_entrada_con_cada_nombre() {
+    let dispositivo = Arc::new(EmulatedDevice::con_contenido_de_ejemplo());
+    let id = registrar_emulado(dispositivo.clone(), "Respaldo".to_string())
+        .unwrap()
+        .id;
+    ops::abrir(id).unwrap();
+
+    // Un tar con dos entradas con el mismo nombre y distinto tamano
+    let src = std::env::temp_dir().join(format!("d-link-repetidas-{}.tar", std::process::id()));
+    let mut tar = tar::Builder::new(File::create(&src).unwrap());
+    for datos in [&b"primera"[..], &b"la segunda es mas larga"[..]] {
+        let mut cabecera = tar::Header::new_gnu();
+        cabecera.set_size(datos.len() as u64);
+        cabecera.set_mode(0o644);
+        cabecera.set_cksum();
+        tar.append_data(&mut cabecera, "files/Repetido/tarea.tns", datos)
+            .unwrap();
+    }
+    tar.finish().unwrap();
+    drop(tar);
+
+    let mut avances = vec![];
+    let resultados = backup::restaurar(id, &src, RestoreMode::Merge, &mut |restante, total| {
+        avances.push((restante, total));
+        ControlFlow::Continue(())
+    })
+    .unwrap();
+    fs::remove_file(&src).unwrap();
+
+    let error = resultados
+        .iter()
+        .find_map(|r| r.error.as_ref())
+        .expect("la entrada repetida aparece como fallo");
+    assert_eq!(error.code, ErrorCode::InvalidArgument);
+    assert_eq!(
+        dispositivo.contenido("/Repetido/tarea.tns").as_deref(),
+        Some(&b"primera"[..])
+    );
+    assert!(avances.iter().all(|&(restante, total)| restante <= total));
+    assert_eq!(avances.last(), Some(&(0, b"primera".len())));
+}
@@ -0,0 +1,56 @@
+//! Utilidades compartidas por las pruebas de integracion
+
+// Cada prueba usa solo algunas
+#![allow(dead_code)]
+
+use std::fs;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use d_link_core::emulator::{registrar_emulado, EmulatedDevice};
+use d_link_core::ops;
+use d_link_core::types::DeviceId;
+
+/// Progreso que nunca cancela
+pub fn continuar(_: usize, _: usize) -> ControlFlow<()> {
+    ControlFlow::Continue(())
+}
+
+/// Registra y abre una calculadora emulada con el contenido de ejemplo
+pub fn abrir_emulado(nombre: &str) -> (Arc<EmulatedDevice>, DeviceId) {
+    let dispositivo = Arc::new(EmulatedDevice::con_contenido_de_ejemplo());
+    let id = registrar_emulado(dispositivo.clone(), nombre.to_string())
+        .unwrap()
+        .id;
+    ops::abrir(id).unwrap();
+    (dispositivo, id)
+}
+
+/// Directorio local vacio para una prueba; se borra al soltarlo
+pub struct DirectorioLocal(pub PathBuf);
+
+impl DirectorioLocal {
+    pub fn nuevo(nombre: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("d-link-{}-{}", nombre, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        DirectorioLocal(dir)
+    }
+
+    /// Archivos que contiene, por nombre
+    pub fn nombres(&self) -> Vec<String> {
+        let mut nombres: Vec<String> = fs::read_dir(&self.0)
+            .unwrap()
+            .map(|entrada| entrada.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        nombres.sort();
+        nombres
+    }
+}
+
+impl Drop for DirectorioLocal {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,104 @@
+//! Politicas de conflicto al transferir sobre archivos que ya existen
+
+mod common;
+
+use std::fs;
+
+use d_link_core::error::ErrorCode;
+use d_link_core::ops;
+use d_link_core::queue::QueueAction;
+use d_link_core::types::{ConflictPolicy, TransferOutcome};
+
+use common::{abrir_emulado, continuar, DirectorioLocal};
+
+#[test]
+fn las_descargas_no_sobrescriben_salvo_que_se_pida() {
+    let (_, id) = abrir_emulado("Descargas");
+    let dest = DirectorioLocal::nuevo("descargas");
+    let local = dest.0.join("Welcome.tns");
+    fs::write(&local, b"local").unwrap();
+
+    // Sin politica, la cola y los comandos usan la de las descargas
+    let accion: QueueAction =
+        serde_json::from_str(r#"{"type":"download","path":"/Examples/Welcome.tns","size":1}"#)
+            .unwrap();
+    let QueueAction::Download { on_conflict, .. } = accion else {
+        panic!("no es una descarga");
+    };
+    assert_eq!(on_conflict, ConflictPolicy::descarga_por_defecto());
+
+    let descargar = |politica| {
+        ops::descargar(
+            id,
+            "/Examples/Welcome.tns",
+            None,
+            &dest.0,
+            politica,
+            &mut continuar,
+        )
+    };
+
+    let error = descargar(on_conflict).unwrap_err();
+    assert_eq!(error.code, ErrorCode::AlreadyExists);
+    assert_eq!(fs::read(&local).unwrap(), b"local");
+
+    let (_, resultado) = descargar(ConflictPolicy::Skip).unwrap();
+    assert_eq!(resultado, TransferOutcome::Skipped);
+    assert_eq!(fs::read(&local).unwrap(), b"local");
+
+    let (ruta, resultado) = descargar(ConflictPolicy::KeepBoth).unwrap();
+    assert_eq!(resultado, TransferOutcome::Renamed);
+    assert_eq!(ruta, dest.0.join("Welcome_1.tns"));
+    assert_eq!(fs::read(&local).unwrap(), b"local");
+
+    let (ruta, resultado) = descargar(ConflictPolicy::Overwrite).unwrap();
+    assert_eq!(resultado, TransferOutcome::Overwritten);
+    assert_eq!(ruta, local);
+    assert_eq!(fs::read(&local).unwrap(), vec![0x2a; 12 * 1024]);
+    assert_eq!(dest.nombres(), ["Welcome.tns", "Welcome_1.tns"]);
+}
+
+#[test]
+fn las_subidas_aplican_la_politica_pedida() {
+    let (dispositivo, id) = abrir_emulado("Subidas");
+    let src = DirectorioLocal::nuevo("subidas");
+    let local = src.0.join("numtheory.tns");
+    fs::write(&local, b"nuevo").unwrap();
+    let anterior = dispositivo.contenido("/MyLib/numtheory.tns").unwrap();
+
+    let subir = |politica| ops::subir(id, &local, "/MyLib", politica, &mut continuar);
+
+    let error = subir(ConflictPolicy::Ask).unwrap_err();
+    assert_eq!(error.code, ErrorCode::AlreadyExists);
+    assert_eq!(
+        dispositivo.contenido("/MyLib/numtheory.tns"),
+        Some(anterior.clone())
+    );
+
+    let (_, resultado) = subir(ConflictPolicy::Skip).unwrap();
+    assert_eq!(resultado, TransferOutcome::Skipped);
+    assert_eq!(
+        dispositivo.contenido("/MyLib/numtheory.tns"),
+        Some(anterior.clone())
+    );
+
+    let (ruta, resultado) = subir(ConflictPolicy::KeepBoth).unwrap();
+    assert_eq!(resultado, TransferOutcome::Renamed);
+    assert_eq!(ruta, "/MyLib/numtheory_1.tns");
+    assert_eq!(dispositivo.contenido(&ruta), Some(b"nuevo".to_vec()));
+    assert_eq!(
+        dispositivo.contenido("/MyLib/numtheory.tns"),
+        Some(anterior)
+    );
+
+    let (ruta, resultado) = subir(ConflictPolicy::Overwrite).unwrap();
+    assert_eq!(resultado, TransferOutcome::Overwritten);
+    assert_eq!(ruta, "/MyLib/numtheory.tns");
+    assert_eq!(dispositivo.contenido(&ruta), Some(b"nuevo".to_vec()));
+
+    // Un archivo que no existia se crea con cualquier politica
+    let (ruta, resultado) =
+        ops::subir(id, &local, "/Examples", ConflictPolicy::Ask, &mut continuar).unwrap();
+    assert_eq!(resultado, TransferOutcome::Created);
+    assert_eq!(dispositivo.contenido(&ruta), Some(b"nuevo".to_vec()));
+}
@@ -0,0 +1,44 @@
+//! Ids estables de las calculadoras emuladas
+
+use std::sync::Arc;
+
+use d_link_core::emulator::{info_por_defecto, registrar_emulado_sin_serie, EmulatedDevice};
+use d_link_core::{desambiguar_id_estable, ops};
+
+#[test]
+fn sin_serie_dos_calculadoras_con_el_mismo_nombre_tienen_ids_distintos() {
+    let mut info = info_por_defecto();
+    info.name = "Aula 3".to_string();
+
+    let primera = registrar_emulado_sin_serie(
+        Arc::new(EmulatedDevice::new(info.clone())),
+        "Primera".to_string(),
+    )
+    .unwrap();
+    let segunda =
+        registrar_emulado_sin_serie(Arc::new(EmulatedDevice::new(info)), "Segunda".to_string())
+            .unwrap();
+
+    let info_primera = ops::abrir(primera.id).unwrap();
+    let info_segunda = ops::abrir(segunda.id).unwrap();
+
+    assert!(info_primera.stable_id.contains("Aula 3"));
+    assert_ne!(info_primera.stable_id, info_segunda.stable_id);
+    assert_eq!(ops::resolver(&info_primera.stable_id).unwrap(), primera.id);
+    assert_eq!(ops::resolver(&info_segunda.stable_id).unwrap(), segunda.id);
+}
+
+#[test]
+fn desambiguar_usa_el_primer_sufijo_libre() {
+    let ocupados = ["0e08-nombre-A", "0e08-nombre-A-2"];
+    let ocupado = |id: &str| ocupados.contains(&id);
+
+    assert_eq!(
+        desambiguar_id_estable("0e08-nombre-B".to_string(), ocupado),
+        "0e08-nombre-B"
+    );
+    assert_eq!(
+        desambiguar_id_estable("0e08-nombre-A".to_string(), ocupado),
+        "0e08-nombre-A-3"
+    );
+}
@@ -0,0 +1,176 @@
+//! Operaciones de `ops` sobre una calculadora emulada: transferencias, fallos
+//! inyectados y cancelacion
+
+mod common;
+
+use std::fs;
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use d_link_core::emulator::InjectedFailure;
+use d_link_core::error::ErrorCode;
+use d_link_core::ops;
+use d_link_core::types::{ConflictPolicy, TransferOutcome};
+
+use common::{abrir_emulado, continuar, DirectorioLocal};
+
+/// Archivo de ejemplo de 256 KiB, muchos bloques del emulador
+const GRANDE: &str = "/Clase/Algebra/Tarea2.tns";
+
+#[test]
+fn subir_listar_y_descargar_devuelve_el_mismo_contenido() {
+    let (dispositivo, id) = abrir_emulado("Ida y vuelta");
+    let local = DirectorioLocal::nuevo("ida");
+    let datos: Vec<u8> = (0..20_000u32).map(|n| (n % 251) as u8).collect();
+    fs::write(local.0.join("datos.tns"), &datos).unwrap();
+
+    let (ruta, resultado) = ops::subir(
+        id,
+        &local.0.join("datos.tns"),
+        "/Clase",
+        ConflictPolicy::Ask,
+        &mut continuar,
+    )
+    .unwrap();
+    assert_eq!(
+        (ruta.as_str(), resultado),
+        ("/Clase/datos.tns", TransferOutcome::Created)
+    );
+    assert_eq!(
+        dispositivo.contenido("/Clase/datos.tns"),
+        Some(datos.clone())
+    );
+
+    let listado = ops::listar(id, "/Clase").unwrap();
+    let entrada = listado.iter().find(|e| e.path == "datos.tns").unwrap();
+    assert!(!entrada.is_dir);
+    assert_eq!(entrada.size, datos.len() as u64);
+
+    let vuelta = DirectorioLocal::nuevo("vuelta");
+    let mut restantes = vec![];
+    let (ruta, resultado) = ops::descargar(
+        id,
+        "/Clase/datos.tns",
+        Some(entrada.size),
+        &vuelta.0,
+        ConflictPolicy::Ask,
+        &mut |restante, total| {
+            restantes.push((restante, total));
+            ControlFlow::Continue(())
+        },
+    )
+    .unwrap();
+    assert_eq!(resultado, TransferOutcome::Created);
+    assert_eq!(fs::read(ruta).unwrap(), datos);
+    assert_eq!(restantes.last(), Some(&(0, datos.len())));
+}
+
+#[test]
+fn ocupado_falla_solo_la_operacion_siguiente() {
+    let (dispositivo, id) = abrir_emulado("Ocupado");
+    dispositivo.programar_fallo(InjectedFailure::Busy);
+
+    assert_eq!(ops::listar(id, "/").unwrap_err().code, ErrorCode::Busy);
+    assert!(ops::listar(id, "/").is_ok());
+}
+
+#[test]
+fn sin_espacio_no_crea_el_archivo() {
+    let (dispositivo, id) = abrir_emulado("Sin espacio");
+    let local = DirectorioLocal::nuevo("sin-espacio");
+    fs::write(local.0.join("nuevo.tns"), b"contenido").unwrap();
+    dispositivo.programar_fallo(InjectedFailure::OutOfSpace);
+
+    let error = ops::subir(
+        id,
+        &local.0.join("nuevo.tns"),
+        "/Clase",
+        ConflictPolicy::Ask,
+        &mut continuar,
+    )
+    .unwrap_err();
+    assert_eq!(error.code, ErrorCode::NoSpace);
+    assert_eq!(dispositivo.contenido("/Clase/nuevo.tns"), None);
+}
+
+#[test]
+fn una_desconexion_a_mitad_de_descarga_no_deja_archivos_y_quita_el_dispositivo() {
+    let (dispositivo, id) = abrir_emulado("Desconexion");
+    let local = DirectorioLocal::nuevo("desconexion");
+    dispositivo.programar_fallo(InjectedFailure::NoDevice {
+        after_bytes: 64 * 1024,
+    });
+
+    let error = ops::descargar(
+        id,
+        GRANDE,
+        None,
+        &local.0,
+        ConflictPolicy::Ask,
+        &mut continuar,
+    )
+    .unwrap_err();
+    assert_eq!(error.code, ErrorCode::Disconnected);
+    assert!(local.nombres().is_empty());
+
+    // El dispositivo desconectado deja de estar registrado
+    assert_eq!(ops::abrir(id).unwrap_err().code, ErrorCode::DeviceNotFound);
+}
+
+#[test]
+fn cancelar_a_mitad_de_descarga_no_deja_archivos() {
+    let (dispositivo, id) = abrir_emulado("Cancelar descarga");
+    // El hilo del dispositivo ve la cancelacion en el bloque siguiente
+    dispositivo.establecer_retardo(Duration::from_millis(2));
+    let local = DirectorioLocal::nuevo("cancelar-descarga");
+
+    let mut restante_al_cancelar = None;
+    let error = ops::descargar(
+        id,
+        GRANDE,
+        None,
+        &local.0,
+        ConflictPolicy::Ask,
+        &mut |restante, total| {
+            if restante < total {
+                restante_al_cancelar.get_or_insert(restante);
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(error.code, ErrorCode::Cancelled);
+    assert!(restante_al_cancelar.is_some_and(|restante| restante > 0));
+    assert!(local.nombres().is_empty());
+    // La cancelacion no cierra el dispositivo
+    assert!(ops::listar(id, "/").is_ok());
+}
+
+#[test]
+fn cancelar_a_mitad_de_subida_conserva_el_archivo_anterior() {
+    let (dispositivo, id) = abrir_emulado("Cancelar subida");
+    dispositivo.establecer_retardo(Duration::from_millis(2));
+    let local = DirectorioLocal::nuevo("cancelar-subida");
+    fs::write(local.0.join("Tarea2.tns"), vec![0x55; 256 * 1024]).unwrap();
+
+    let error = ops::subir(
+        id,
+        &local.0.join("Tarea2.tns"),
+        "/Clase/Algebra",
+        ConflictPolicy::Overwrite,
+        &mut |restante, total| {
+            if restante < total {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        },
+    )
+    .unwrap_err();
+
+    assert_eq!(error.code, ErrorCode::Cancelled);
+    assert_eq!(dispositivo.contenido(GRANDE), Some(vec![0x2a; 256 * 1024]));
+}
@@ -0,0 +1,127 @@
+//! Ejecucion de la cola de acciones de un dispositivo
+
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use d_link_core::error::ErrorCode;
+use d_link_core::events::SinEventos;
+use d_link_core::queue::{self, ActionDefaults, QueueAction, QueueItemStatus};
+use d_link_core::types::ConflictPolicy;
+use d_link_core::DEVICES;
+
+use common::{abrir_emulado, DirectorioLocal};
+
+/// Espera hasta que se cumpla `condicion`
+fn hasta(condicion: impl Fn() -> bool) {
+    let limite = Instant::now() + Duration::from_secs(10);
+    while !condicion() {
+        assert!(
+            Instant::now() < limite,
+            "la cola no llego al estado esperado"
+        );
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn descarga(path: &str, size: u64) -> QueueAction {
+    QueueAction::Download {
+        path: path.to_string(),
+        size,
+        dest: None,
+        on_conflict: ConflictPolicy::Overwrite,
+    }
+}
+
+#[test]
+fn una_cola_renombrada_mientras_se_ejecuta_se_vacia() {
+    let descargas = DirectorioLocal::nuevo("cola-renombrada");
+    queue::configurar(
+        Arc::new(SinEventos),
+        ActionDefaults {
+            descargas: Some(descargas.0.clone()),
+            ..ActionDefaults::default()
+        },
+    );
+    let (dispositivo, id) = abrir_emulado("Renombrada");
+    // Cada bloque tarda, para renombrar con la primera descarga en curso
+    dispositivo.establecer_retardo(Duration::from_millis(2));
+    let clave = (id.bus_number, id.address);
+    let anterior = DEVICES.read().unwrap()[&clave].id_estable.clone();
+    let nuevo = format!("{}-nuevo", anterior);
+
+    queue::encolar(&anterior, descarga("/Clase/Algebra/Tarea2.tns", 256 * 1024)).unwrap();
+    queue::encolar(&anterior, descarga("/Examples/Welcome.tns", 12 * 1024)).unwrap();
+    hasta(|| {
+        queue::obtener(&anterior)
+            .items
+            .first()
+            .is_some_and(|item| item.status == QueueItemStatus::Running)
+    });
+
+    // Como al abrir una calculadora cuyo id estable cambia
+    DEVICES.write().unwrap().get_mut(&clave).unwrap().id_estable = nuevo.clone();
+    queue::renombrar(&anterior, &nuevo);
+    queue::procesar(&nuevo);
+
+    hasta(|| queue::obtener(&nuevo).items.is_empty());
+    assert_eq!(descargas.nombres(), ["Tarea2.tns", "Welcome.tns"]);
+    assert!(queue::obtener(&anterior).items.is_empty());
+}
+
+#[test]
+fn al_abrir_el_diario_solo_se_repiten_las_acciones_repetibles() {
+    let dir = DirectorioLocal::nuevo("diario-interrumpido");
+    let diario = dir.0.join("queue.json");
+    let en_curso = |id: u64, accion: &str| {
+        format!(
+            r#"{{"id":{},"action":{},"status":"running","error":null,"operationId":7}}"#,
+            id, accion
+        )
+    };
+    let items = [
+        en_curso(
+            901,
+            r#"{"type":"download","path":"/Examples/Welcome.tns","size":1}"#,
+        ),
+        en_curso(902, r#"{"type":"uploadOs","src":"/tmp/os.tco"}"#),
+        en_curso(903, r#"{"type":"move","src":"/a.tns","dest":"/b.tns"}"#),
+    ];
+    fs::write(
+        &diario,
+        format!(
+            r#"{{"version":1,"queues":[{{"device":"DIARIO-INTERRUMPIDO","paused":false,"items":[{}]}}]}}"#,
+            items.join(",")
+        ),
+    )
+    .unwrap();
+
+    queue::abrir_diario(&diario).unwrap();
+
+    let cola = queue::obtener("DIARIO-INTERRUMPIDO");
+    let estados: Vec<_> = cola
+        .items
+        .iter()
+        .map(|item| (item.id, item.status, item.error.as_ref().map(|e| e.code)))
+        .collect();
+    assert_eq!(
+        estados,
+        [
+            (901, QueueItemStatus::Pending, None),
+            (902, QueueItemStatus::Failed, Some(ErrorCode::Interrupted)),
+            (903, QueueItemStatus::Failed, Some(ErrorCode::Interrupted)),
+        ]
+    );
+    assert!(cola.items.iter().all(|item| item.operation_id.is_none()));
+
+    // Se repiten solo a pedido
+    queue::reintentar_fallidos("DIARIO-INTERRUMPIDO").unwrap();
+    assert!(queue::obtener("DIARIO-INTERRUMPIDO")
+        .items
+        .iter()
+        .all(|item| item.status == QueueItemStatus::Pending));
+    queue::vaciar("DIARIO-INTERRUMPIDO").unwrap();
+}
@@ -0,0 +1,80 @@
+//! Instantaneas del repositorio sobre una calculadora emulada
+
+use std::fs;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use d_link_core::emulator::{registrar_emulado, EmulatedDevice};
+use d_link_core::error::ErrorCode;
+use d_link_core::ops;
+use d_link_core::repository::{self, DIRECTORIO_INSTANTANEAS};
+
+#[test]
+fn rechaza_instantaneas_con_hashes_no_validos() {
+    let dispositivo = Arc::new(EmulatedDevice::con_contenido_de_ejemplo());
+    let id = registrar_emulado(dispositivo, "Repositorio".to_string())
+        .unwrap()
+        .id;
+    ops::abrir(id).unwrap();
+
+    let repo = std::env::temp_dir().join(format!("d-link-repo-{}", std::process::id()));
+    let informe = repository::crear(id, &repo, &mut |_, _| ControlFlow::Continue(())).unwrap();
+    let id_instantanea = informe.snapshot.id;
+    assert!(repository::cargar(&repo, &id_instantanea).is_ok());
+
+    let ruta = repo
+        .join(DIRECTORIO_INSTANTANEAS)
+        .join(format!("{}.json", id_instantanea));
+    let original: serde_json::Value = serde_json::from_slice(&fs::read(&ruta).unwrap()).unwrap();
+
+    for hash in [
+        "../../../../etc/passwd",
+        "a",
+        "ABCDEF",
+        &"A".repeat(64),
+        &"g".repeat(64),
+    ] {
+        let mut alterada = original.clone();
+        let entrada = alterada["entries"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .find(|entrada| entrada.get("hash").is_some())
+            .unwrap();
+        entrada["hash"] = hash.into();
+        fs::write(&ruta, serde_json::to_vec(&alterada).unwrap()).unwrap();
+
+        let error = repository::cargar(&repo, &id_instantanea).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidArgument, "{}", hash);
+    }
+    fs::remove_dir_all(&repo).unwrap();
+}
+
+#[test]
+fn una_instantanea_danada_no_impide_listar_las_demas() {
+    let dispositivo = Arc::new(EmulatedDevice::con_contenido_de_ejemplo());
+    let id = registrar_emulado(dispositivo, "Repositorio danado".to_string())
+        .unwrap()
+        .id;
+    ops::abrir(id).unwrap();
+
+    let repo = std::env::temp_dir().join(format!("d-link-repo-danado-{}", std::process::id()));
+    let informe = repository::crear(id, &repo, &mut |_, _| ControlFlow::Continue(())).unwrap();
+    let danada = repo.join(DIRECTORIO_INSTANTANEAS).join("danada.json");
+    fs::write(&danada, b"{ no es json").unwrap();
+
+    let lista = repository::listar(&repo, None).unwrap();
+    let ids: Vec<&str> = lista.snapshots.iter().map(|s| s.id.as_str()).collect();
+    assert_eq!(ids, [informe.snapshot.id.as_str()]);
+    assert_eq!(lista.invalid.len(), 1);
+    assert_eq!(lista.invalid[0].code, ErrorCode::InvalidArgument);
+    assert_eq!(
+        lista.invalid[0].path.as_deref(),
+        Some(danada.display().to_string().as_str())
+    );
+
+    // La siguiente instantanea sigue reutilizando la anterior
+    let informe = repository::crear(id, &repo, &mut |_, _| ControlFlow::Continue(())).unwrap();
+    assert_eq!(informe.downloaded, 0);
+    fs::remove_dir_all(&repo).unwrap();
+}
@@ -0,0 +1,201 @@
+//! Validacion de los planes de sincronizacion que llegan de la interfaz y
+//! comparacion de las carpetas
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use d_link_core::emulator::{registrar_emulado, EmulatedDevice};
+use d_link_core::error::ErrorCode;
+use d_link_core::ops;
+use d_link_core::sync::{self, SyncAction, SyncMode, SyncPlan};
+
+use common::{continuar, DirectorioLocal};
+
+fn plan(acciones: Vec<SyncAction>) -> SyncPlan {
+    SyncPlan {
+        mode: SyncMode::MirrorToLocal,
+        local: PathBuf::from("/tmp/sync"),
+        remote: "/Clase".to_string(),
+        actions: acciones,
+        conflicts: vec![],
+    }
+}
+
+#[test]
+fn acepta_acciones_dentro_de_las_carpetas() {
+    let plan = plan(vec![
+        SyncAction::CreateRemoteDir {
+            remote: "/Clase".to_string(),
+        },
+        SyncAction::CreateLocalDir {
+            local: PathBuf::from("/tmp/sync"),
+        },
+        SyncAction::Download {
+            remote: "/Clase/temas/tarea.tns".to_string(),
+            local: PathBuf::from("/tmp/sync/temas/tarea.tns"),
+            size: 10,
+            date: 0,
+        },
+        SyncAction::DeleteLocal {
+            local: PathBuf::from("/tmp/sync/viejo"),
+            dir: true,
+        },
+    ]);
+    assert!(plan.validar().is_ok());
+}
+
+#[test]
+fn rechaza_acciones_fuera_de_las_carpetas() {
+    let fuera = [
+        SyncAction::DeleteLocal {
+            local: PathBuf::from("/home"),
+            dir: true,
+        },
+        SyncAction::DeleteLocal {
+            local: PathBuf::from("/tmp/sync/../otra"),
+            dir: true,
+        },
+        // La carpeta misma no se elimina
+        SyncAction::DeleteLocal {
+            local: PathBuf::from("/tmp/sync"),
+            dir: true,
+        },
+        SyncAction::DeleteRemote {
+            remote: "/Otra/tarea.tns".to_string(),
+            dir: false,
+        },
+        SyncAction::DeleteRemote {
+            remote: "/Clase/../Otra".to_string(),
+            dir: true,
+        },
+        // Comparte prefijo pero es otra carpeta
+        SyncAction::DeleteRemote {
+            remote: "/Clase2".to_string(),
+            dir: true,
+        },
+        SyncAction::Upload {
+            local: PathBuf::from("/etc/passwd"),
+            remote: "/Clase/passwd".to_string(),
+            size: 1,
+        },
+        // Se subiria con el nombre local, no con el del plan
+        SyncAction::Upload {
+            local: PathBuf::from("/tmp/sync/otro.tns"),
+            remote: "/Clase/tarea.tns".to_string(),
+            size: 1,
+        },
+    ];
+
+    for accion in fuera {
+        let error = plan(vec![accion.clone()]).validar().unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidArgument, "{:?}", accion);
+    }
+}
+
+#[test]
+fn no_ejecuta_un_plan_con_acciones_fuera() {
+    let dispositivo = Arc::new(EmulatedDevice::con_contenido_de_ejemplo());
+    let id = registrar_emulado(dispositivo, "Sync".to_string())
+        .unwrap()
+        .id;
+    ops::abrir(id).unwrap();
+
+    let raiz = std::env::temp_dir().join(format!("d-link-sync-{}", std::process::id()));
+    let dentro = raiz.join("dentro");
+    let viejo = dentro.join("viejo");
+    let fuera = raiz.join("fuera");
+    fs::create_dir_all(&viejo).unwrap();
+    fs::create_dir_all(&fuera).unwrap();
+
+    // La primera accion es valida, pero no se ejecuta porque la segunda no lo es
+    let plan = SyncPlan {
+        mode: SyncMode::MirrorToLocal,
+        local: dentro.clone(),
+        remote: "/".to_string(),
+        actions: vec![
+            SyncAction::DeleteLocal {
+                local: viejo.clone(),
+                dir: true,
+            },
+            SyncAction::DeleteLocal {
+                local: fuera.clone(),
+                dir: true,
+            },
+        ],
+        conflicts: vec![],
+    };
+    let error =
+        sync::ejecutar(id, &plan, &mut |_, _| std::ops::ControlFlow::Continue(())).unwrap_err();
+
+    assert_eq!(error.code, ErrorCode::InvalidArgument);
+    assert!(viejo.exists() && fuera.exists());
+    fs::remove_dir_all(&raiz).unwrap();
+}
+
+/// Tipo y ruta remota de cada accion del plan
+fn resumen(plan: &SyncPlan) -> Vec<(&'static str, String)> {
+    plan.actions
+        .iter()
+        .map(|accion| match accion {
+            SyncAction::Upload { remote, .. } => ("subir", remote.clone()),
+            SyncAction::Download { remote, .. } => ("bajar", remote.clone()),
+            otra => panic!("accion inesperada: {:?}", otra),
+        })
+        .collect()
+}
+
+#[test]
+fn el_modo_bidireccional_copia_ediciones_del_mismo_tamano() {
+    let hace_una_hora = SystemTime::now() - Duration::from_secs(3600);
+    let segundos = |fecha: SystemTime| fecha.duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    let dispositivo = Arc::new(EmulatedDevice::con_contenido_de_ejemplo());
+    // Tarea1 no cambio en la calculadora desde hace una hora; Tarea2 se edito ahora
+    dispositivo.agregar_archivo(
+        "/Clase/Algebra/Tarea1.tns",
+        vec![0x2a; 48 * 1024],
+        segundos(hace_una_hora),
+    );
+    let id = registrar_emulado(dispositivo.clone(), "Sync bidireccional".to_string())
+        .unwrap()
+        .id;
+    ops::abrir(id).unwrap();
+
+    // Tarea1 se edito ahora en local; Tarea2 tiene el contenido de hace una hora
+    let local = DirectorioLocal::nuevo("sync-bidireccional");
+    fs::write(local.0.join("Tarea1.tns"), vec![0x55; 48 * 1024]).unwrap();
+    fs::write(local.0.join("Tarea2.tns"), vec![0x55; 256 * 1024]).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(local.0.join("Tarea2.tns"))
+        .unwrap()
+        .set_modified(hace_una_hora)
+        .unwrap();
+
+    let plan = sync::planificar(id, &local.0, "/Clase/Algebra", SyncMode::TwoWay, &[]).unwrap();
+    assert_eq!(
+        resumen(&plan),
+        [
+            ("subir", "/Clase/Algebra/Tarea1.tns".to_string()),
+            ("bajar", "/Clase/Algebra/Tarea2.tns".to_string()),
+        ]
+    );
+
+    sync::ejecutar(id, &plan, &mut continuar).unwrap();
+    assert_eq!(
+        dispositivo.contenido("/Clase/Algebra/Tarea1.tns"),
+        Some(vec![0x55; 48 * 1024])
+    );
+    assert_eq!(
+        fs::read(local.0.join("Tarea2.tns")).unwrap(),
+        vec![0x2a; 256 * 1024]
+    );
+
+    // Despues de sincronizar las dos carpetas quedan iguales
+    let plan = sync::planificar(id, &local.0, "/Clase/Algebra", SyncMode::TwoWay, &[]).unwrap();
+    assert!(plan.actions.is_empty(), "{:?}", plan.actions);
+}
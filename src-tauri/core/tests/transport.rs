@@ -0,0 +1,153 @@
+//! Contrato de `CalculatorTransport` sobre el emulador y conversion de sus
+//! errores a `DLinkError`
+
+use std::io;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use d_link_core::emulator::EmulatedDevice;
+use d_link_core::error::{DLinkError, ErrorCode, ErrorSource};
+use d_link_core::transport::{CalculatorTransport, TransportError};
+
+/// Archivo de ejemplo de varios bloques
+const ARCHIVO: &str = "/Clase/Algebra/Tarea1.tns";
+const TAMANO: usize = 48 * 1024;
+
+fn emulado() -> Arc<EmulatedDevice> {
+    Arc::new(EmulatedDevice::con_contenido_de_ejemplo())
+}
+
+/// Progreso que pide cancelar en la llamada numero `corte` y anota cuantas hubo
+fn cancelar_en(corte: usize, llamadas: &mut usize) -> impl FnMut(usize) -> ControlFlow<()> + '_ {
+    move |_| {
+        *llamadas += 1;
+        if *llamadas >= corte {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    }
+}
+
+#[test]
+fn el_progreso_informa_los_bytes_restantes() {
+    let transporte = emulado();
+    let mut restantes = vec![];
+    let mut datos = vec![];
+    transporte
+        .read_file(ARCHIVO, TAMANO as u64, &mut datos, &mut |restante| {
+            restantes.push(restante);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+
+    assert_eq!(datos.len(), TAMANO);
+    assert!(restantes.windows(2).all(|par| par[0] > par[1]));
+    assert_eq!(restantes.last(), Some(&0));
+}
+
+#[test]
+fn break_cancela_la_lectura() {
+    let transporte = emulado();
+    let mut llamadas = 0;
+    let mut datos = vec![];
+    let resultado = transporte.read_file(
+        ARCHIVO,
+        TAMANO as u64,
+        &mut datos,
+        &mut cancelar_en(2, &mut llamadas),
+    );
+
+    assert_eq!(resultado, Err(TransportError::Cancelled));
+    // No se sigue leyendo despues del Break
+    assert_eq!(llamadas, 2);
+    assert!(datos.len() < TAMANO);
+
+    let mut llamadas = 0;
+    let resultado =
+        transporte.read_to_vec(ARCHIVO, TAMANO as u64, &mut cancelar_en(1, &mut llamadas));
+    assert_eq!(resultado, Err(TransportError::Cancelled));
+}
+
+#[test]
+fn read_to_vec_rechaza_tamanos_que_no_caben_en_memoria() {
+    let transporte = emulado();
+    let resultado = transporte.read_to_vec(ARCHIVO, u64::MAX, &mut |_| ControlFlow::Continue(()));
+    assert_eq!(resultado, Err(TransportError::TooLarge(u64::MAX)));
+}
+
+#[test]
+fn break_cancela_la_escritura_sin_tocar_el_archivo_anterior() {
+    let transporte = emulado();
+    let nuevo = vec![0x55; TAMANO];
+
+    let mut llamadas = 0;
+    let resultado = transporte.write_file(ARCHIVO, &nuevo, &mut cancelar_en(3, &mut llamadas));
+    assert_eq!(resultado, Err(TransportError::Cancelled));
+    assert_eq!(llamadas, 3);
+    assert_eq!(transporte.contenido(ARCHIVO), Some(vec![0x2a; TAMANO]));
+
+    let mut llamadas = 0;
+    let resultado = transporte.write_file(
+        "/Clase/nuevo.tns",
+        &nuevo,
+        &mut cancelar_en(1, &mut llamadas),
+    );
+    assert_eq!(resultado, Err(TransportError::Cancelled));
+    assert_eq!(transporte.contenido("/Clase/nuevo.tns"), None);
+}
+
+#[test]
+fn el_envio_del_sistema_operativo_ignora_break() {
+    let transporte = emulado();
+    let mut llamadas = 0;
+    let resultado = transporte.send_os(&[0; 3 * 4096], &mut cancelar_en(1, &mut llamadas));
+
+    assert_eq!(resultado, Ok(()));
+    assert_eq!(llamadas, 3);
+}
+
+#[test]
+fn los_errores_del_transporte_conservan_su_codigo() {
+    let casos = [
+        (TransportError::NoDevice, ErrorCode::Disconnected),
+        (TransportError::Busy, ErrorCode::Busy),
+        (TransportError::Timeout, ErrorCode::Timeout),
+        (TransportError::NoSpace, ErrorCode::NoSpace),
+        (TransportError::NotFound, ErrorCode::NotFound),
+        (TransportError::Exists, ErrorCode::AlreadyExists),
+        (TransportError::Cancelled, ErrorCode::Cancelled),
+        (TransportError::TooLarge(u64::MAX), ErrorCode::FileTooLarge),
+        (
+            TransportError::Other("respuesta invalida".to_string()),
+            ErrorCode::Protocol,
+        ),
+    ];
+    for (error, codigo) in casos {
+        let mensaje = error.to_string();
+        let convertido = DLinkError::from(error);
+        assert_eq!(convertido.code, codigo);
+        assert_eq!(convertido.source, ErrorSource::Libnspire);
+        assert_eq!(convertido.message, mensaje);
+    }
+
+    assert!(DLinkError::from(TransportError::NoDevice).es_desconexion());
+    assert!(DLinkError::from(TransportError::NoDevice).interrumpe());
+    assert!(DLinkError::from(TransportError::Cancelled).interrumpe());
+    assert!(!DLinkError::from(TransportError::Busy).interrumpe());
+}
+
+#[test]
+fn los_fallos_locales_se_informan_como_errores_del_sistema_de_archivos() {
+    let casos = [
+        (io::ErrorKind::StorageFull, ErrorCode::NoSpace),
+        (io::ErrorKind::PermissionDenied, ErrorCode::PermissionDenied),
+        (io::ErrorKind::Other, ErrorCode::Io),
+    ];
+    for (tipo, codigo) in casos {
+        let error = TransportError::from(io::Error::new(tipo, "disco"));
+        let convertido = DLinkError::from(error);
+        assert_eq!(convertido.code, codigo);
+        assert_eq!(convertido.source, ErrorSource::Io);
+    }
+}
@@ -0,0 +1,78 @@
+//! Cola del hilo de trabajo de un dispositivo
+
+use std::ops::ControlFlow;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use d_link_core::emulator::EmulatedDevice;
+use d_link_core::operations::Operacion;
+use d_link_core::types::{DeviceId, OperationKind};
+use d_link_core::worker::DeviceWorker;
+
+#[test]
+fn el_estado_dice_la_posicion_de_cada_operacion() {
+    let id = DeviceId {
+        bus_number: 254,
+        address: 1,
+    };
+    let worker = DeviceWorker::iniciar(
+        id,
+        Box::new(Arc::new(EmulatedDevice::con_contenido_de_ejemplo())),
+    )
+    .unwrap();
+
+    let descarga = Operacion::iniciar(OperationKind::Download);
+    let subida = Operacion::iniciar(OperationKind::Upload);
+    // El primer trabajo ocupa el hilo hasta que la prueba lo suelte
+    let (soltar, esperar) = mpsc::channel::<()>();
+
+    thread::scope(|hilos| {
+        let worker = &worker;
+        hilos.spawn(|| {
+            descarga.ejecutar(|| {
+                worker.ejecutar(
+                    move |_, _| {
+                        let _ = esperar.recv();
+                        Ok(())
+                    },
+                    0,
+                    &mut |_| ControlFlow::Continue(()),
+                )
+            })
+        });
+
+        let hasta = |condicion: &dyn Fn() -> bool| {
+            let limite = Instant::now() + Duration::from_secs(5);
+            while !condicion() {
+                assert!(
+                    Instant::now() < limite,
+                    "la cola no llego al estado esperado"
+                );
+                thread::sleep(Duration::from_millis(5));
+            }
+        };
+        hasta(&|| worker.estado().busy);
+
+        hilos.spawn(|| {
+            subida
+                .ejecutar(|| worker.ejecutar(|_, _| Ok(()), 0, &mut |_| ControlFlow::Continue(())))
+        });
+        hasta(&|| worker.estado().queued == 1);
+        // Sin operacion registrada, como listar un directorio
+        hilos.spawn(|| worker.ejecutar(|_, _| Ok(()), 0, &mut |_| ControlFlow::Continue(())));
+        hasta(&|| worker.estado().queued == 2);
+
+        let estado = worker.estado();
+        assert_eq!(estado.running, Some(descarga.id()));
+        assert_eq!(estado.waiting, vec![Some(subida.id()), None]);
+
+        soltar.send(()).unwrap();
+        hasta(&|| !worker.estado().busy && worker.estado().queued == 0);
+    });
+
+    let estado = worker.estado();
+    assert_eq!(estado.running, None);
+    assert!(estado.waiting.is_empty());
+}
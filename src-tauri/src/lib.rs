@@ -1,550 +1,665 @@
 //! Biblioteca principal de D-Link para Tauri 2.x.
-//! Implementa la comunicacion USB con calculadoras TI-Nspire.
+//! Expone como comandos las operaciones de `d_link_core` y reenvia sus eventos
+//! al frontend.
 //!
 //! Basado en n-link, con mejoras de arquitectura y compatibilidad multiplataforma.
 
-#![allow(clippy::type_complexity)]
-
-use std::fs::File;
-use std::io::{BufWriter, Read, Write};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex, RwLock};
-use std::time::Duration;
-
-use hashbrown::HashMap;
-use libnspire::dir::EntryType;
-use libnspire::{PID_CX2, VID};
-use rusb::{GlobalContext, Hotplug, UsbContext};
-use serde::Serialize;
-use tauri::{AppHandle, Emitter};
+use std::sync::Arc;
+
+use d_link_core::backup::{self, BackupFormat, RestoreMode};
+use d_link_core::batch::{self, BatchResult};
+use d_link_core::error::{DLinkError, ErrorCode};
+use d_link_core::events::{ejecutar_operacion, notificar_desconexion, Event, EventSink};
+use d_link_core::operations::{self, Operacion, OperationId};
+use d_link_core::ops;
+use d_link_core::queue::{self, ActionDefaults, DeviceQueue, QueueAction};
+use d_link_core::repository::{self, SnapshotDiff, SnapshotList, SnapshotReport, SnapshotSummary};
+use d_link_core::sync::{self, SyncMode, SyncPlan};
+use d_link_core::types::{
+    ConflictPolicy, DeviceId, DeviceInfo, FileInfo, FileResult, OperationKind, WorkerStatus,
+};
+use tauri::{AppHandle, Emitter, Manager};
 
 pub mod cmd;
 
-use cmd::{agregar_dispositivo, AddDevicePayload, DeviceId, DeviceInfo, FileInfo, ProgressUpdate};
-
-// Estado global de dispositivos conectados
-lazy_static::lazy_static! {
-    pub static ref DEVICES: RwLock<HashMap<(u8, u8), Device>> = RwLock::new(HashMap::new());
-}
-
-/// Convierte un Info de libnspire a nuestro DeviceInfo
-fn convertir_info(info: &libnspire::info::Info) -> DeviceInfo {
-    use libnspire::info::{Battery, HardwareType};
-
-    // El enum Battery no representa porcentaje real, solo estados
-    // Powered = conectado a corriente, Ok = bateria bien, Low = bateria baja
-    let battery_percent = match info.battery {
-        Battery::Powered => 100,
-        Battery::Ok => 80,
-        Battery::Low => 20,
-        Battery::Unknown(_) => 50, // Valor desconocido, usar 50% por defecto
-    };
-
-    let hw_type_num = match info.hw_type {
-        HardwareType::Cas => 0,
-        HardwareType::NonCas => 1,
-        HardwareType::CasCx => 2,
-        HardwareType::NonCasCx => 3,
-        HardwareType::Unknown(v) => v,
-    };
-
-    DeviceInfo {
-        name: info.name.clone(),
-        free_storage: info.free_storage,
-        total_storage: info.total_storage,
-        free_ram: info.free_ram,
-        total_ram: info.total_ram,
-        battery: battery_percent,
-        is_charging: info.is_charging,
-        os_version: info.version.to_string(),
-        boot1_version: info.boot1_version.to_string(),
-        boot2_version: info.boot2_version.to_string(),
-        hw_type: hw_type_num,
-        clock_speed: info.clock_speed,
-        lcd_width: info.lcd.width,
-        lcd_height: info.lcd.height,
-        lcd_bpp: info.lcd.bpp,
-        lcd_sample_mode: info.lcd.sample_mode,
+/// Reenvia los eventos del nucleo al frontend
+struct EmisorTauri(AppHandle);
+
+impl EventSink for EmisorTauri {
+    fn emitir(&self, evento: Event) {
+        let nombre = evento.nombre();
+        let _ = match evento {
+            Event::AddDevice(payload) => self.0.emit(nombre, payload),
+            Event::RemoveDevice(id) => self.0.emit(nombre, id),
+            Event::Progress(progreso) => self.0.emit(nombre, progreso),
+            Event::QueueChanged(cola) => self.0.emit(nombre, cola),
+            Event::OperationStarted(evento)
+            | Event::OperationFinished(evento)
+            | Event::OperationFailed(evento) => self.0.emit(nombre, evento),
+        };
     }
 }
 
-/// Estado de conexion del dispositivo
-pub enum DeviceState {
-    Open(
-        Arc<Mutex<libnspire::Handle<GlobalContext>>>,
-        libnspire::info::Info,
-    ),
-    Closed,
-}
-
-/// Datos de un dispositivo TI-Nspire
-pub struct Device {
-    pub nombre: String,
-    pub dispositivo: Arc<rusb::Device<GlobalContext>>,
-    pub estado: DeviceState,
-    pub necesita_drivers: bool,
-    pub es_cx_ii: bool,
-}
-
-/// Error serializable para enviar al frontend
-#[derive(Debug, Serialize)]
-pub struct SerializedError(String);
-
-impl<T: std::fmt::Display> From<T> for SerializedError {
-    fn from(error: T) -> Self {
-        SerializedError(error.to_string())
-    }
-}
-
-/// Monitor de conexion/desconexion USB (Hotplug)
-struct MonitorDispositivos {
-    app_handle: AppHandle,
-}
-
-impl Hotplug<GlobalContext> for MonitorDispositivos {
-    fn device_arrived(&mut self, dispositivo: rusb::Device<GlobalContext>) {
-        let handle = self.app_handle.clone();
-        let es_cx_ii = dispositivo
-            .device_descriptor()
-            .map(|d| d.product_id() == PID_CX2)
-            .unwrap_or(false);
-        let dispositivo = Arc::new(dispositivo);
-
-        // Procesar en hilo separado para no bloquear el callback
-        std::thread::spawn(move || {
-            // Reintentar si el dispositivo esta ocupado
-            for _ in 0..10 {
-                match agregar_dispositivo(dispositivo.clone()) {
-                    Ok((id, datos)) => {
-                        let nombre = datos.nombre.clone();
-                        let necesita_drivers = datos.necesita_drivers;
-
-                        if let Ok(mut mapa) = DEVICES.write() {
-                            mapa.insert(id, datos);
-                        }
-
-                        let _ = handle.emit(
-                            "addDevice",
-                            AddDevicePayload {
-                                id: DeviceId {
-                                    bus_number: id.0,
-                                    address: id.1,
-                                },
-                                name: nombre,
-                                is_cx_ii: es_cx_ii,
-                                needs_drivers: necesita_drivers,
-                            },
-                        );
-                        return;
-                    }
-                    Err(rusb::Error::Busy) => {
-                        std::thread::sleep(Duration::from_millis(250));
-                    }
-                    Err(e) => {
-                        eprintln!("Error agregando dispositivo: {}", e);
-                        return;
-                    }
-                }
-            }
-        });
-    }
-
-    fn device_left(&mut self, dispositivo: rusb::Device<GlobalContext>) {
-        let clave = (dispositivo.bus_number(), dispositivo.address());
-
-        if let Ok(mut mapa) = DEVICES.write() {
-            if mapa.remove(&clave).is_some() {
-                let _ = self.app_handle.emit(
-                    "removeDevice",
-                    DeviceId {
-                        bus_number: clave.0,
-                        address: clave.1,
-                    },
-                );
-            }
-        }
-    }
-}
-
-/// Obtiene el handle de un dispositivo abierto
-fn obtener_dispositivo_abierto(
-    id: &DeviceId,
-) -> Result<Arc<Mutex<libnspire::Handle<GlobalContext>>>, anyhow::Error> {
-    let mapa = DEVICES.read().map_err(|e| anyhow::anyhow!("{}", e))?;
-
-    if let Some(dispositivo) = mapa.get(&(id.bus_number, id.address)) {
-        match &dispositivo.estado {
-            DeviceState::Open(handle, _) => Ok(handle.clone()),
-            DeviceState::Closed => anyhow::bail!("Dispositivo cerrado"),
-        }
-    } else {
-        anyhow::bail!("Dispositivo no encontrado")
-    }
-}
-
-/// Envuelve errores y maneja desconexiones automaticamente
-fn envolver_error<T>(
-    resultado: Result<T, libnspire::Error>,
+/// Si una operacion fallo porque el dispositivo se desconecto,
+/// avisa a la interfaz con `removeDevice` antes de devolver el error.
+fn notificar_error<T>(
+    resultado: Result<T, DLinkError>,
     id: DeviceId,
-    app_handle: &AppHandle,
-) -> Result<T, libnspire::Error> {
-    if let Err(libnspire::Error::NoDevice) = resultado {
-        if let Ok(mut mapa) = DEVICES.write() {
-            mapa.remove(&(id.bus_number, id.address));
-        }
-        let _ = app_handle.emit("removeDevice", id);
-    }
+    emisor: &EmisorTauri,
+) -> Result<T, DLinkError> {
+    notificar_desconexion(&resultado, id, emisor);
     resultado
 }
 
-/// Crea un callback para reportar progreso de operaciones
-/// Optimizado: reporta solo cuando hay un cambio significativo (>=1% o al finalizar)
-fn crear_reporter_progreso(
-    app_handle: &AppHandle,
-    id: DeviceId,
-    total: usize,
-) -> impl FnMut(usize) + '_ {
-    let umbral = (total / 100).max(1); // 1% del total, minimo 1 byte
-    let mut ultimo_reportado = total;
-
-    move |restante| {
-        let diferencia = ultimo_reportado.saturating_sub(restante);
-
-        // Reportar si hay cambio >= 1% o si es el final
-        if diferencia >= umbral || restante == 0 {
-            ultimo_reportado = restante;
-            let _ = app_handle.emit(
-                "progress",
-                ProgressUpdate {
-                    id,
-                    remaining: restante,
-                    total,
-                },
-            );
-        }
-    }
+/// Ejecuta trabajo bloqueante (USB, disco) en un hilo aparte para que el
+/// comando no retenga el runtime mientras dura la transferencia
+async fn en_segundo_plano<T: Send + 'static>(
+    trabajo: impl FnOnce() -> Result<T, DLinkError> + Send + 'static,
+) -> Result<T, DLinkError> {
+    tauri::async_runtime::spawn_blocking(trabajo)
+        .await
+        .map_err(DLinkError::interno)?
 }
 
 // ============================================================================
 // COMANDOS TAURI
 // ============================================================================
 
-/// Abre un dispositivo para comunicacion
+/// Abre un dispositivo para comunicacion y retoma su cola de operaciones
 #[tauri::command]
-fn open_device(bus_number: u8, address: u8) -> Result<DeviceInfo, SerializedError> {
-    let dispositivo_usb = {
-        let mapa = DEVICES.read().map_err(|e| e.to_string())?;
-        let dispositivo = mapa
-            .get(&(bus_number, address))
-            .ok_or("Dispositivo no encontrado")?;
-
-        if !matches!(dispositivo.estado, DeviceState::Closed) {
-            return Err("El dispositivo ya esta abierto".into());
+async fn open_device(device_id: String) -> Result<DeviceInfo, DLinkError> {
+    en_segundo_plano(move || {
+        let info = ops::abrir(ops::resolver(&device_id)?)?;
+        // Sin numero de serie USB el id estable se completa al abrir
+        if info.stable_id != device_id {
+            queue::renombrar(&device_id, &info.stable_id);
         }
-
-        dispositivo.dispositivo.clone()
-    };
-
-    // Abrir conexion con la calculadora
-    let handle = libnspire::Handle::new(dispositivo_usb.open()?)?;
-    let info = handle.info()?;
-
-    // Guardar el handle abierto
-    {
-        let mut mapa = DEVICES.write().map_err(|e| e.to_string())?;
-        let dispositivo = mapa
-            .get_mut(&(bus_number, address))
-            .ok_or("Dispositivo perdido durante apertura")?;
-
-        dispositivo.estado = DeviceState::Open(Arc::new(Mutex::new(handle)), info.clone());
-    }
-
-    // Convertir info de libnspire a nuestro formato
-    Ok(convertir_info(&info))
+        queue::procesar(&info.stable_id);
+        Ok(info)
+    })
+    .await
 }
 
 /// Cierra la conexion con un dispositivo
 #[tauri::command]
-fn close_device(bus_number: u8, address: u8) -> Result<(), SerializedError> {
-    let mut mapa = DEVICES.write().map_err(|e| e.to_string())?;
-    let dispositivo = mapa
-        .get_mut(&(bus_number, address))
-        .ok_or("Dispositivo no encontrado")?;
-
-    dispositivo.estado = DeviceState::Closed;
-    Ok(())
+fn close_device(device_id: String) -> Result<(), DLinkError> {
+    ops::cerrar(ops::resolver(&device_id)?)
 }
 
 /// Actualiza la informacion del dispositivo
 #[tauri::command]
-fn update_device(
-    bus_number: u8,
-    address: u8,
-    app_handle: AppHandle,
-) -> Result<DeviceInfo, SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
-
-    let info = envolver_error(handle.info(), id, &app_handle)?;
+async fn update_device(device_id: String, app_handle: AppHandle) -> Result<DeviceInfo, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        notificar_error(ops::actualizar_info(id), id, &EmisorTauri(app_handle))
+    })
+    .await
+}
 
-    Ok(convertir_info(&info))
+/// Indica si el dispositivo esta ocupado y que operaciones esperan turno, en
+/// orden, para que la interfaz muestre la posicion de cada una
+#[tauri::command]
+fn device_status(device_id: String) -> Result<WorkerStatus, DLinkError> {
+    ops::estado(ops::resolver(&device_id)?)
 }
 
 /// Lista el contenido de un directorio
 #[tauri::command]
-fn list_dir(
-    bus_number: u8,
-    address: u8,
+async fn list_dir(
+    device_id: String,
     path: String,
     app_handle: AppHandle,
-) -> Result<Vec<FileInfo>, SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
-
-    let directorio = envolver_error(handle.list_dir(&path), id, &app_handle)?;
-
-    Ok(directorio
-        .iter()
-        .map(|archivo| FileInfo {
-            path: archivo.name().to_string_lossy().to_string(),
-            is_dir: archivo.entry_type() == EntryType::Directory,
-            date: archivo.date(),
-            size: archivo.size(),
-        })
-        .collect())
+) -> Result<Vec<FileInfo>, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        notificar_error(ops::listar(id, &path), id, &EmisorTauri(app_handle))
+    })
+    .await
 }
 
-/// Descarga un archivo de la calculadora
+/// Descarga un archivo de la calculadora.
+/// `size` es el tamano del listado que vio el frontend; si ya no coincide falla con `file_changed`.
+/// `on_conflict` dice que hacer si el archivo ya existe en `dest` (por defecto,
+/// `ask`: falla con `already_exists` y no lo toca); el resultado trae la ruta
+/// local con la que quedo.
 #[tauri::command]
-fn download_file(
-    bus_number: u8,
-    address: u8,
+async fn download_file(
+    device_id: String,
     src: String,
     size: u64,
     dest: String,
+    on_conflict: Option<ConflictPolicy>,
     app_handle: AppHandle,
-) -> Result<(), SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
-    let ruta_destino = PathBuf::from(dest);
-
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
-
-    let mut buffer = vec![0u8; size as usize];
-    envolver_error(
-        handle.read_file(
-            &src,
-            &mut buffer,
-            &mut crear_reporter_progreso(&app_handle, id, size as usize),
-        ),
-        id,
-        &app_handle,
-    )?;
-
-    // Guardar en disco con buffer para mejor rendimiento
-    if let Some(nombre) = src.split('/').last() {
-        let archivo = File::create(ruta_destino.join(nombre))?;
-        let mut escritor = BufWriter::with_capacity(64 * 1024, archivo); // 64KB buffer
-        escritor.write_all(&buffer)?;
-        escritor.flush()?;
-    }
+) -> Result<FileResult, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::Download)
+            .con_origen(&src)
+            .con_destino(&dest);
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            ops::descargar(
+                id,
+                &src,
+                Some(size),
+                &PathBuf::from(dest),
+                on_conflict.unwrap_or_else(ConflictPolicy::descarga_por_defecto),
+                progreso,
+            )
+        })
+        .map(|(path, outcome)| FileResult {
+            path: path.display().to_string(),
+            error: None,
+            outcome: Some(outcome),
+        })
+    })
+    .await
+}
 
-    Ok(())
+/// Descarga un directorio completo de la calculadora.
+/// Devuelve el resultado de cada archivo; los que ya existen en `dest` se
+/// tratan segun `on_conflict`, como en `download_file`.
+#[tauri::command]
+async fn download_dir(
+    device_id: String,
+    src: String,
+    dest: String,
+    on_conflict: Option<ConflictPolicy>,
+    app_handle: AppHandle,
+) -> Result<Vec<FileResult>, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::DownloadDir)
+            .con_origen(&src)
+            .con_destino(&dest);
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            ops::descargar_directorio(
+                id,
+                &src,
+                &PathBuf::from(dest),
+                on_conflict.unwrap_or_else(ConflictPolicy::descarga_por_defecto),
+                progreso,
+            )
+        })
+    })
+    .await
 }
 
-/// Sube un archivo a la calculadora
+/// Sube un archivo a la calculadora. `on_conflict` dice que hacer si ya existe
+/// (por defecto, se sobrescribe); el resultado indica que se hizo y la ruta con
+/// la que quedo.
 #[tauri::command]
-fn upload_file(
-    bus_number: u8,
-    address: u8,
+async fn upload_file(
+    device_id: String,
     src: String,
     dest: String,
+    on_conflict: Option<ConflictPolicy>,
     app_handle: AppHandle,
-) -> Result<(), SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
-    let ruta_origen = PathBuf::from(&src);
+) -> Result<FileResult, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::Upload)
+            .con_origen(&src)
+            .con_destino(&dest);
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            ops::subir(
+                id,
+                &PathBuf::from(src),
+                &dest,
+                on_conflict.unwrap_or_default(),
+                progreso,
+            )
+        })
+        .map(|(path, outcome)| FileResult {
+            path,
+            error: None,
+            outcome: Some(outcome),
+        })
+    })
+    .await
+}
 
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
+/// Sube un directorio local completo a la calculadora.
+/// Sin `extensions` solo se suben los documentos que la calculadora puede
+/// abrir; con una lista vacia se suben todos los archivos. Los archivos que ya
+/// existen se tratan segun `on_conflict`, como en `upload_file`.
+#[tauri::command]
+async fn upload_dir(
+    device_id: String,
+    src: String,
+    dest: String,
+    extensions: Option<Vec<String>>,
+    on_conflict: Option<ConflictPolicy>,
+    app_handle: AppHandle,
+) -> Result<Vec<FileResult>, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::UploadDir)
+            .con_origen(&src)
+            .con_destino(&dest);
+        let extensiones: Vec<&str> = match &extensions {
+            Some(extensiones) => extensiones.iter().map(String::as_str).collect(),
+            None => ops::EXTENSIONES_TRANSFERIBLES.to_vec(),
+        };
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            ops::subir_directorio(
+                id,
+                &PathBuf::from(src),
+                &dest,
+                &extensiones,
+                on_conflict.unwrap_or_default(),
+                progreso,
+            )
+        })
+    })
+    .await
+}
 
-    let mut buffer = vec![];
-    File::open(&ruta_origen)?.read_to_end(&mut buffer)?;
+/// Calcula, sin ejecutarlo, el plan para sincronizar una carpeta local con una
+/// de la calculadora. `extensions` filtra los archivos locales como en `upload_dir`.
+#[tauri::command]
+async fn sync_plan(
+    device_id: String,
+    local: String,
+    remote: String,
+    mode: SyncMode,
+    extensions: Option<Vec<String>>,
+    app_handle: AppHandle,
+) -> Result<SyncPlan, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let extensiones: Vec<&str> = match &extensions {
+            Some(extensiones) => extensiones.iter().map(String::as_str).collect(),
+            None => ops::EXTENSIONES_TRANSFERIBLES.to_vec(),
+        };
+        let resultado = sync::planificar(id, &PathBuf::from(local), &remote, mode, &extensiones);
+        notificar_error(resultado, id, &EmisorTauri(app_handle))
+    })
+    .await
+}
 
-    let nombre_archivo = ruta_origen
-        .file_name()
-        .ok_or("No se pudo obtener el nombre del archivo")?
-        .to_string_lossy()
-        .to_string();
+/// Ejecuta un plan de sincronizacion calculado con `sync_plan`
+#[tauri::command]
+async fn sync_execute(
+    device_id: String,
+    plan: SyncPlan,
+    app_handle: AppHandle,
+) -> Result<Vec<FileResult>, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::Sync);
+        operacion.actualizar(|detalles| (detalles.src, detalles.dest) = plan.origen_destino());
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            sync::ejecutar(id, &plan, progreso)
+        })
+    })
+    .await
+}
 
-    let ruta_completa = format!("{}/{}", dest, nombre_archivo);
+/// Respalda toda la calculadora en el archivo `dest` (zip, tar o tar.zst).
+/// Sin `format` se deduce de la extension de `dest`.
+#[tauri::command]
+async fn backup_device(
+    device_id: String,
+    dest: String,
+    format: Option<BackupFormat>,
+    app_handle: AppHandle,
+) -> Result<Vec<FileResult>, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::Backup).con_destino(&dest);
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            backup::respaldar(id, &PathBuf::from(dest), format, progreso)
+        })
+    })
+    .await
+}
 
-    envolver_error(
-        handle.write_file(
-            &ruta_completa,
-            &buffer,
-            &mut crear_reporter_progreso(&app_handle, id, buffer.len()),
-        ),
-        id,
-        &app_handle,
-    )?;
+/// Restaura en la calculadora el respaldo `src`, que puede venir de otra
+#[tauri::command]
+async fn restore_backup(
+    device_id: String,
+    src: String,
+    mode: RestoreMode,
+    app_handle: AppHandle,
+) -> Result<Vec<FileResult>, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::Restore).con_origen(&src);
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            backup::restaurar(id, &PathBuf::from(src), mode, progreso)
+        })
+    })
+    .await
+}
 
-    Ok(())
+/// Ejecuta en orden una lista de acciones, las mismas que las de la cola. El
+/// `batchId` del resultado es el de los eventos de progreso del
+/// lote y el que acepta `cancel_operation`; las descargas sin destino van a la
+/// carpeta de descargas.
+#[tauri::command]
+async fn run_batch(
+    device_id: String,
+    actions: Vec<QueueAction>,
+    app_handle: AppHandle,
+) -> Result<BatchResult, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let destinos = destinos_por_defecto();
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::Batch);
+        operacion.actualizar(|detalles| detalles.batch_count = Some(actions.len()));
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            batch::ejecutar(id, &actions, &destinos, &operacion, &emisor, progreso).map(|results| {
+                BatchResult {
+                    batch_id: operacion.id(),
+                    results,
+                }
+            })
+        })
+    })
+    .await
 }
 
-/// Sube un archivo de sistema operativo
+/// Directorio, dentro de las descargas, del repositorio de instantaneas por defecto
+const REPOSITORIO_POR_DEFECTO: &str = "D-Link instantaneas";
+
+/// Ruta del repositorio de instantaneas: la indicada o la de por defecto
+fn ruta_repositorio(repo: Option<String>) -> Result<PathBuf, DLinkError> {
+    match repo {
+        Some(repo) => Ok(PathBuf::from(repo)),
+        None => Ok(PathBuf::from(get_download_path()?).join(REPOSITORIO_POR_DEFECTO)),
+    }
+}
+
+/// Toma una instantanea incremental de la calculadora en el repositorio `repo`
+/// (por defecto, uno en la carpeta de descargas)
 #[tauri::command]
-fn upload_os(
-    bus_number: u8,
-    address: u8,
-    src: String,
+async fn snapshot_create(
+    device_id: String,
+    repo: Option<String>,
     app_handle: AppHandle,
-) -> Result<(), SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
+) -> Result<SnapshotReport, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let repo = ruta_repositorio(repo)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::Snapshot).con_destino(repo.display());
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            repository::crear(id, &repo, progreso)
+        })
+    })
+    .await
+}
 
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
+/// Lista las instantaneas del repositorio, solo las de `device` (id estable) si se indica
+#[tauri::command]
+async fn snapshot_list(
+    repo: Option<String>,
+    device: Option<String>,
+) -> Result<SnapshotList<SnapshotSummary>, DLinkError> {
+    en_segundo_plano(move || {
+        let lista = repository::listar(&ruta_repositorio(repo)?, device.as_deref())?;
+        Ok(lista.resumir())
+    })
+    .await
+}
 
-    let mut buffer = vec![];
-    File::open(&src)?.read_to_end(&mut buffer)?;
+/// Compara dos instantaneas del repositorio
+#[tauri::command]
+async fn snapshot_diff(
+    repo: Option<String>,
+    from: String,
+    to: String,
+) -> Result<SnapshotDiff, DLinkError> {
+    en_segundo_plano(move || repository::comparar(&ruta_repositorio(repo)?, &from, &to)).await
+}
 
-    envolver_error(
-        handle.send_os(
-            &buffer,
-            &mut crear_reporter_progreso(&app_handle, id, buffer.len()),
-        ),
-        id,
-        &app_handle,
-    )?;
+/// Restaura en la calculadora una instantanea del repositorio, que puede ser de otra
+#[tauri::command]
+async fn snapshot_restore(
+    device_id: String,
+    repo: Option<String>,
+    snapshot: String,
+    mode: RestoreMode,
+    app_handle: AppHandle,
+) -> Result<Vec<FileResult>, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let repo = ruta_repositorio(repo)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::SnapshotRestore).con_origen(&snapshot);
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            repository::restaurar(id, &repo, &snapshot, mode, progreso)
+        })
+    })
+    .await
+}
 
-    Ok(())
+/// Sube un archivo de sistema operativo
+#[tauri::command]
+async fn upload_os(
+    device_id: String,
+    src: String,
+    app_handle: AppHandle,
+) -> Result<(), DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::UploadOs).con_origen(&src);
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            ops::subir_os(id, &PathBuf::from(src), progreso)
+        })
+    })
+    .await
 }
 
 /// Elimina un archivo
 #[tauri::command]
-fn delete_file(
-    bus_number: u8,
-    address: u8,
+async fn delete_file(
+    device_id: String,
     path: String,
     app_handle: AppHandle,
-) -> Result<(), SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
-
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
-
-    envolver_error(handle.delete_file(&path), id, &app_handle)?;
-    Ok(())
+) -> Result<(), DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        notificar_error(
+            ops::eliminar_archivo(id, &path),
+            id,
+            &EmisorTauri(app_handle),
+        )
+    })
+    .await
 }
 
 /// Elimina un directorio
 #[tauri::command]
-fn delete_dir(
-    bus_number: u8,
-    address: u8,
+async fn delete_dir(
+    device_id: String,
     path: String,
     app_handle: AppHandle,
-) -> Result<(), SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
-
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
+) -> Result<(), DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        notificar_error(
+            ops::eliminar_directorio(id, &path),
+            id,
+            &EmisorTauri(app_handle),
+        )
+    })
+    .await
+}
 
-    envolver_error(handle.delete_dir(&path), id, &app_handle)?;
-    Ok(())
+/// Elimina un directorio con todo su contenido.
+/// Con `continue_on_error` sigue con el resto de elementos cuando uno falla.
+#[tauri::command]
+async fn delete_dir_recursive(
+    device_id: String,
+    path: String,
+    continue_on_error: bool,
+    app_handle: AppHandle,
+) -> Result<Vec<FileResult>, DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        let emisor = EmisorTauri(app_handle);
+        let operacion = Operacion::iniciar(OperationKind::Delete).con_origen(&path);
+        ejecutar_operacion(&emisor, id, &operacion, |progreso| {
+            ops::eliminar_recursivo(id, &path, continue_on_error, progreso)
+        })
+    })
+    .await
 }
 
 /// Crea un directorio
 #[tauri::command]
-fn create_dir(
-    bus_number: u8,
-    address: u8,
+async fn create_dir(
+    device_id: String,
     path: String,
     app_handle: AppHandle,
-) -> Result<(), SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
-
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
-
-    envolver_error(handle.create_dir(&path), id, &app_handle)?;
-    Ok(())
+) -> Result<(), DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        notificar_error(
+            ops::crear_directorio(id, &path),
+            id,
+            &EmisorTauri(app_handle),
+        )
+    })
+    .await
 }
 
 /// Mueve un archivo o directorio
 #[tauri::command]
-fn move_file(
-    bus_number: u8,
-    address: u8,
+async fn move_file(
+    device_id: String,
     src: String,
     dest: String,
     app_handle: AppHandle,
-) -> Result<(), SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
-
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
-
-    envolver_error(handle.move_file(&src, &dest), id, &app_handle)?;
-    Ok(())
+) -> Result<(), DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        notificar_error(ops::mover(id, &src, &dest), id, &EmisorTauri(app_handle))
+    })
+    .await
 }
 
 /// Copia un archivo o directorio
 #[tauri::command]
-fn copy_file(
-    bus_number: u8,
-    address: u8,
+async fn copy_file(
+    device_id: String,
     src: String,
     dest: String,
     app_handle: AppHandle,
-) -> Result<(), SerializedError> {
-    let id = DeviceId {
-        bus_number,
-        address,
-    };
+) -> Result<(), DLinkError> {
+    en_segundo_plano(move || {
+        let id = ops::resolver(&device_id)?;
+        notificar_error(ops::copiar(id, &src, &dest), id, &EmisorTauri(app_handle))
+    })
+    .await
+}
 
-    let handle = obtener_dispositivo_abierto(&id)?;
-    let handle = handle.lock().map_err(|e| e.to_string())?;
+/// Cambia el tamano maximo de archivo que se permite descargar
+#[tauri::command]
+fn set_download_limit(bytes: u64) {
+    ops::establecer_limite_descarga(bytes);
+}
 
-    envolver_error(handle.copy_file(&src, &dest), id, &app_handle)?;
-    Ok(())
+/// Pide cancelar una transferencia en curso o en espera.
+/// Devuelve `false` si la operacion ya habia terminado.
+#[tauri::command]
+fn cancel_operation(operation_id: OperationId) -> Result<bool, DLinkError> {
+    operations::cancelar(operation_id)
 }
 
 /// Obtiene la ruta de descargas del sistema
 #[tauri::command]
-fn get_download_path() -> Result<String, SerializedError> {
+fn get_download_path() -> Result<String, DLinkError> {
     dirs::download_dir()
         .map(|p| p.to_string_lossy().to_string())
-        .ok_or_else(|| "No se pudo obtener el directorio de descargas".into())
+        .ok_or_else(|| {
+            DLinkError::app(
+                ErrorCode::NotFound,
+                "No se pudo obtener el directorio de descargas",
+            )
+        })
+}
+
+/// Archivo, dentro del directorio de datos de la aplicacion, del diario de la cola
+const DIARIO_COLA: &str = "queue.json";
+
+/// Destinos de las acciones de la cola y de los lotes que no indican uno
+fn destinos_por_defecto() -> ActionDefaults {
+    ActionDefaults {
+        descargas: get_download_path().ok().map(PathBuf::from),
+        repositorio: ruta_repositorio(None).ok(),
+    }
+}
+
+// ============================================================================
+// COLA DE OPERACIONES
+// ============================================================================
+
+/// Agrega una accion al final de la cola del dispositivo y devuelve el id del
+/// elemento. La cola se ejecuta sola mientras el dispositivo este abierto.
+#[tauri::command]
+fn queue_enqueue(device_id: String, action: QueueAction) -> Result<u64, DLinkError> {
+    queue::encolar(&device_id, action)
+}
+
+/// Estado de la cola de un dispositivo
+#[tauri::command]
+fn queue_get(device_id: String) -> DeviceQueue {
+    queue::obtener(&device_id)
+}
+
+/// Estado de las colas de todos los dispositivos
+#[tauri::command]
+fn queue_list() -> Vec<DeviceQueue> {
+    queue::listar()
+}
+
+/// Deja de tomar acciones de la cola; la que esta en curso termina
+#[tauri::command]
+fn queue_pause(device_id: String) -> Result<(), DLinkError> {
+    queue::pausar(&device_id)
+}
+
+/// Sigue ejecutando una cola pausada
+#[tauri::command]
+fn queue_resume(device_id: String) -> Result<(), DLinkError> {
+    queue::reanudar(&device_id)
+}
+
+/// Mueve un elemento de la cola a la posicion `index`
+#[tauri::command]
+fn queue_reorder(device_id: String, item_id: u64, index: usize) -> Result<(), DLinkError> {
+    queue::mover(&device_id, item_id, index)
+}
+
+/// Vuelve a encolar los elementos que fallaron
+#[tauri::command]
+fn queue_retry_failed(device_id: String) -> Result<(), DLinkError> {
+    queue::reintentar_fallidos(&device_id)
+}
+
+/// Quita de la cola todo salvo la accion en curso
+#[tauri::command]
+fn queue_clear(device_id: String) -> Result<(), DLinkError> {
+    queue::vaciar(&device_id)
+}
+
+/// Quita un elemento de la cola, o pide cancelarlo si esta en curso
+#[tauri::command]
+fn queue_cancel(device_id: String, item_id: u64) -> Result<(), DLinkError> {
+    queue::cancelar(&device_id, item_id)
 }
 
 // ============================================================================
@@ -559,56 +674,69 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .setup(move |app| {
-            // Registrar hotplug USB si esta disponible
-            if !hotplug_registrado.swap(true, Ordering::SeqCst) {
-                let app_handle = app.handle().clone();
-
-                if rusb::has_hotplug() {
-                    let monitor = MonitorDispositivos {
-                        app_handle: app_handle.clone(),
-                    };
-
-                    match GlobalContext::default().register_callback(
-                        Some(VID),
-                        None,
-                        None,
-                        Box::new(monitor),
-                    ) {
-                        Ok(_registration) => {
-                            // Iniciar hilo de procesamiento de eventos USB
-                            std::thread::spawn(|| loop {
-                                if let Err(e) = GlobalContext::default().handle_events(None) {
-                                    eprintln!("Error procesando eventos USB: {}", e);
-                                    std::thread::sleep(Duration::from_secs(1));
-                                }
-                            });
-                        }
-                        Err(e) => {
-                            eprintln!("No se pudo registrar hotplug USB: {}", e);
-                        }
+            let emisor = Arc::new(EmisorTauri(app.handle().clone()));
+            queue::configurar(emisor.clone(), destinos_por_defecto());
+
+            // Recuperar las operaciones que quedaron pendientes al cerrar
+            match app.path().app_data_dir() {
+                Ok(datos) => {
+                    if let Err(e) = queue::abrir_diario(&datos.join(DIARIO_COLA)) {
+                        eprintln!("Error abriendo el diario de la cola: {}", e);
                     }
-                } else {
-                    println!("Sistema sin soporte hotplug USB - se requiere enumeracion manual");
                 }
+                Err(e) => eprintln!("Sin directorio de datos para el diario de la cola: {}", e),
+            }
+
+            // Registrar hotplug USB si esta disponible
+            if !hotplug_registrado.swap(true, Ordering::SeqCst)
+                && !d_link_core::usb::iniciar_monitor(emisor)
+            {
+                println!("Sistema sin soporte hotplug USB - se requiere enumeracion manual");
             }
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             cmd::enumerate,
+            #[cfg(debug_assertions)]
+            cmd::add_emulated_device,
             open_device,
             close_device,
             update_device,
+            device_status,
             list_dir,
             download_file,
+            download_dir,
             upload_file,
+            upload_dir,
             upload_os,
+            sync_plan,
+            sync_execute,
+            backup_device,
+            restore_backup,
+            run_batch,
+            snapshot_create,
+            snapshot_list,
+            snapshot_diff,
+            snapshot_restore,
             delete_file,
             delete_dir,
+            delete_dir_recursive,
             create_dir,
             move_file,
             copy_file,
+            set_download_limit,
+            cancel_operation,
             get_download_path,
+            queue_enqueue,
+            queue_get,
+            queue_list,
+            queue_pause,
+            queue_resume,
+            queue_reorder,
+            queue_retry_failed,
+            queue_clear,
+            queue_cancel,
         ])
         .run(tauri::generate_context!())
         .expect("Error iniciando aplicacion D-Link");
@@ -2,7 +2,7 @@
 
 fn main() {
     #[cfg(target_os = "linux")]
-    { 
+    {
         unsafe {
             std::env::set_var("GTK_THEME", std::env::var("GTK_THEME").unwrap_or_default());
         }
@@ -5,3 +5,6 @@ export { default as FileBrowser } from "./FileBrowser.vue";
 export { default as DeviceInfo } from "./DeviceInfo.vue";
 export { default as DeviceSelector } from "./DeviceSelector.vue";
 export { default as OperationQueue } from "./OperationQueue.vue";
+export { default as SyncDialog } from "./SyncDialog.vue";
+export { default as RestoreDialog } from "./RestoreDialog.vue";
+export { default as SnapshotsDialog } from "./SnapshotsDialog.vue";
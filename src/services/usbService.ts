@@ -3,13 +3,30 @@
  * Encapsula todas las llamadas al backend Rust.
  */
 import { invoke } from "@tauri-apps/api/core";
-import { open as openDialog } from "@tauri-apps/plugin-dialog";
-import type { DeviceId, DeviceInfo, FileInfo } from "@/types";
+import { open as openDialog, save as saveDialog } from "@tauri-apps/plugin-dialog";
+import type {
+  BatchAction,
+  BatchResult,
+  ConflictPolicy,
+  DeviceInfo,
+  DeviceQueue,
+  FileInfo,
+  FileResult,
+  QueueAction,
+  RestoreMode,
+  SnapshotDiff,
+  SnapshotList,
+  SnapshotReport,
+  SyncMode,
+  SyncPlan,
+  WorkerStatus,
+} from "@/types";
 
 /** Datos de dispositivo agregado desde el backend */
 interface AddDevicePayload {
   busNumber: number;
   address: number;
+  stableId: string;
   name: string;
   isCxIi: boolean;
   needsDrivers: boolean;
@@ -26,70 +43,240 @@ class TauriUsbService {
     return await invoke<AddDevicePayload[]>("enumerate");
   }
 
+  /**
+   * Agrega una calculadora emulada en memoria (pruebas y demos sin USB).
+   * Solo existe en las compilaciones de desarrollo del backend.
+   */
+  async addEmulatedDevice(name?: string): Promise<AddDevicePayload> {
+    return await invoke<AddDevicePayload>("add_emulated_device", { name });
+  }
+
   /**
    * Abre un dispositivo para comunicacion.
    */
-  async openDevice(id: DeviceId): Promise<DeviceInfo> {
+  async openDevice(deviceId: string): Promise<DeviceInfo> {
     return await invoke<DeviceInfo>("open_device", {
-      busNumber: id.busNumber,
-      address: id.address,
+      deviceId,
+    });
+  }
+
+  /**
+   * Consulta si el dispositivo esta ocupado y cuantas operaciones esperan turno.
+   */
+  async deviceStatus(deviceId: string): Promise<WorkerStatus> {
+    return await invoke<WorkerStatus>("device_status", {
+      deviceId,
     });
   }
 
   /**
    * Lista el contenido de un directorio.
    */
-  async listDir(id: DeviceId, path: string): Promise<FileInfo[]> {
+  async listDir(deviceId: string, path: string): Promise<FileInfo[]> {
     return await invoke<FileInfo[]>("list_dir", {
-      busNumber: id.busNumber,
-      address: id.address,
+      deviceId,
       path,
     });
   }
 
   /**
-   * Descarga un archivo de la calculadora.
+   * Descarga un archivo de la calculadora. onConflict dice que hacer si ya
+   * existe en dest (por defecto, "ask": falla con already_exists); el
+   * resultado trae la ruta local final.
    */
-  async downloadFile(id: DeviceId, src: string, size: number, dest: string): Promise<void> {
-    await invoke("download_file", {
-      busNumber: id.busNumber,
-      address: id.address,
+  async downloadFile(
+    deviceId: string,
+    src: string,
+    size: number,
+    dest: string,
+    onConflict?: ConflictPolicy
+  ): Promise<FileResult> {
+    return await invoke<FileResult>("download_file", {
+      deviceId,
       src,
       size,
       dest,
+      onConflict,
     });
   }
 
   /**
-   * Sube un archivo a la calculadora.
+   * Descarga un directorio completo de la calculadora, recreando su estructura.
+   * Retorna el resultado de cada archivo; los que ya existen se tratan segun
+   * onConflict.
    */
-  async uploadFile(id: DeviceId, src: string, dest: string): Promise<void> {
-    await invoke("upload_file", {
-      busNumber: id.busNumber,
-      address: id.address,
+  async downloadDir(
+    deviceId: string,
+    src: string,
+    dest: string,
+    onConflict?: ConflictPolicy
+  ): Promise<FileResult[]> {
+    return await invoke<FileResult[]>("download_dir", {
+      deviceId,
       src,
       dest,
+      onConflict,
+    });
+  }
+
+  /**
+   * Sube un archivo a la calculadora. onConflict dice que hacer si ya existe
+   * (por defecto, se sobrescribe); el resultado trae la ruta final.
+   */
+  async uploadFile(
+    deviceId: string,
+    src: string,
+    dest: string,
+    onConflict?: ConflictPolicy
+  ): Promise<FileResult> {
+    return await invoke<FileResult>("upload_file", {
+      deviceId,
+      src,
+      dest,
+      onConflict,
+    });
+  }
+
+  /**
+   * Sube un directorio local completo a la calculadora, creando los que falten.
+   * Sin extensiones solo se suben los documentos .tns; los archivos que ya
+   * existen se tratan segun onConflict.
+   */
+  async uploadDir(
+    deviceId: string,
+    src: string,
+    dest: string,
+    extensions?: string[],
+    onConflict?: ConflictPolicy
+  ): Promise<FileResult[]> {
+    return await invoke<FileResult[]>("upload_dir", {
+      deviceId,
+      src,
+      dest,
+      extensions,
+      onConflict,
+    });
+  }
+
+  /**
+   * Calcula, sin ejecutarlo, el plan para sincronizar una carpeta local con una
+   * de la calculadora.
+   */
+  async syncPlan(
+    deviceId: string,
+    local: string,
+    remote: string,
+    mode: SyncMode
+  ): Promise<SyncPlan> {
+    return await invoke<SyncPlan>("sync_plan", {
+      deviceId,
+      local,
+      remote,
+      mode,
+    });
+  }
+
+  /**
+   * Ejecuta un plan de sincronizacion. Retorna el resultado de cada accion.
+   */
+  async syncExecute(deviceId: string, plan: SyncPlan): Promise<FileResult[]> {
+    return await invoke<FileResult[]>("sync_execute", {
+      deviceId,
+      plan,
+    });
+  }
+
+  /**
+   * Respalda toda la calculadora en un archivo .zip, .tar o .tar.zst.
+   * Retorna el resultado de cada archivo.
+   */
+  async backupDevice(deviceId: string, dest: string): Promise<FileResult[]> {
+    return await invoke<FileResult[]>("backup_device", {
+      deviceId,
+      dest,
+    });
+  }
+
+  /**
+   * Restaura un respaldo en la calculadora, que puede ser otra que la respaldada.
+   * Retorna el resultado de cada archivo.
+   */
+  async restoreBackup(deviceId: string, src: string, mode: RestoreMode): Promise<FileResult[]> {
+    return await invoke<FileResult[]>("restore_backup", {
+      deviceId,
+      src,
+      mode,
+    });
+  }
+
+  /**
+   * Ejecuta en orden varias acciones en una sola llamada. El progreso llega con
+   * el batchId como id de operacion, que tambien sirve para cancelar el lote.
+   * Las descargas sin destino van a la carpeta de descargas.
+   */
+  async runBatch(deviceId: string, actions: BatchAction[]): Promise<BatchResult> {
+    return await invoke<BatchResult>("run_batch", { deviceId, actions });
+  }
+
+  /**
+   * Toma una instantanea incremental en el repositorio (por defecto, en Descargas).
+   */
+  async snapshotCreate(deviceId: string, repo?: string): Promise<SnapshotReport> {
+    return await invoke<SnapshotReport>("snapshot_create", {
+      deviceId,
+      repo,
+    });
+  }
+
+  /**
+   * Lista las instantaneas del repositorio, solo las de un dispositivo si se indica.
+   * Las que no se pueden leer vuelven aparte, con su error.
+   */
+  async snapshotList(repo?: string, device?: string): Promise<SnapshotList> {
+    return await invoke<SnapshotList>("snapshot_list", { repo, device });
+  }
+
+  /**
+   * Compara dos instantaneas del repositorio.
+   */
+  async snapshotDiff(from: string, to: string, repo?: string): Promise<SnapshotDiff> {
+    return await invoke<SnapshotDiff>("snapshot_diff", { repo, from, to });
+  }
+
+  /**
+   * Restaura una instantanea, que puede ser de otra calculadora.
+   * Retorna el resultado de cada archivo.
+   */
+  async snapshotRestore(
+    deviceId: string,
+    snapshot: string,
+    mode: RestoreMode,
+    repo?: string
+  ): Promise<FileResult[]> {
+    return await invoke<FileResult[]>("snapshot_restore", {
+      deviceId,
+      repo,
+      snapshot,
+      mode,
     });
   }
 
   /**
    * Sube un archivo del sistema operativo.
    */
-  async uploadOs(id: DeviceId, path: string): Promise<void> {
+  async uploadOs(deviceId: string, path: string): Promise<void> {
     await invoke("upload_os", {
-      busNumber: id.busNumber,
-      address: id.address,
-      path,
+      deviceId,
+      src: path,
     });
   }
 
   /**
    * Elimina un archivo.
    */
-  async deleteFile(id: DeviceId, path: string): Promise<void> {
+  async deleteFile(deviceId: string, path: string): Promise<void> {
     await invoke("delete_file", {
-      busNumber: id.busNumber,
-      address: id.address,
+      deviceId,
       path,
     });
   }
@@ -97,21 +284,35 @@ class TauriUsbService {
   /**
    * Elimina un directorio.
    */
-  async deleteDir(id: DeviceId, path: string): Promise<void> {
+  async deleteDir(deviceId: string, path: string): Promise<void> {
     await invoke("delete_dir", {
-      busNumber: id.busNumber,
-      address: id.address,
+      deviceId,
+      path,
+    });
+  }
+
+  /**
+   * Elimina un directorio con todo su contenido.
+   * Con continueOnError sigue con el resto si algun elemento falla.
+   */
+  async deleteDirRecursive(
+    deviceId: string,
+    path: string,
+    continueOnError: boolean
+  ): Promise<FileResult[]> {
+    return await invoke<FileResult[]>("delete_dir_recursive", {
+      deviceId,
       path,
+      continueOnError,
     });
   }
 
   /**
    * Crea un directorio.
    */
-  async createDir(id: DeviceId, path: string): Promise<void> {
+  async createDir(deviceId: string, path: string): Promise<void> {
     await invoke("create_dir", {
-      busNumber: id.busNumber,
-      address: id.address,
+      deviceId,
       path,
     });
   }
@@ -119,10 +320,9 @@ class TauriUsbService {
   /**
    * Mueve un archivo o directorio.
    */
-  async move(id: DeviceId, src: string, dest: string): Promise<void> {
+  async move(deviceId: string, src: string, dest: string): Promise<void> {
     await invoke("move_file", {
-      busNumber: id.busNumber,
-      address: id.address,
+      deviceId,
       src,
       dest,
     });
@@ -131,15 +331,93 @@ class TauriUsbService {
   /**
    * Copia un archivo o directorio.
    */
-  async copy(id: DeviceId, src: string, dest: string): Promise<void> {
+  async copy(deviceId: string, src: string, dest: string): Promise<void> {
     await invoke("copy_file", {
-      busNumber: id.busNumber,
-      address: id.address,
+      deviceId,
       src,
       dest,
     });
   }
 
+  /**
+   * Cambia el tamano maximo (en bytes) de archivo que el backend acepta descargar.
+   */
+  async setDownloadLimit(bytes: number): Promise<void> {
+    await invoke("set_download_limit", { bytes });
+  }
+
+  /**
+   * Pide cancelar una transferencia en curso o en espera.
+   * Retorna false si la operacion ya habia terminado.
+   */
+  async cancelOperation(operationId: number): Promise<boolean> {
+    return await invoke<boolean>("cancel_operation", { operationId });
+  }
+
+  /**
+   * Agrega una accion a la cola del dispositivo, que el backend ejecuta en orden.
+   * Retorna el id del elemento.
+   */
+  async queueEnqueue(deviceId: string, action: QueueAction): Promise<number> {
+    return await invoke<number>("queue_enqueue", { deviceId, action });
+  }
+
+  /**
+   * Estado de la cola de un dispositivo.
+   */
+  async queueGet(deviceId: string): Promise<DeviceQueue> {
+    return await invoke<DeviceQueue>("queue_get", { deviceId });
+  }
+
+  /**
+   * Estado de las colas de todos los dispositivos.
+   */
+  async queueList(): Promise<DeviceQueue[]> {
+    return await invoke<DeviceQueue[]>("queue_list");
+  }
+
+  /**
+   * Pausa la cola; la accion en curso termina.
+   */
+  async queuePause(deviceId: string): Promise<void> {
+    await invoke("queue_pause", { deviceId });
+  }
+
+  /**
+   * Reanuda una cola pausada.
+   */
+  async queueResume(deviceId: string): Promise<void> {
+    await invoke("queue_resume", { deviceId });
+  }
+
+  /**
+   * Mueve un elemento de la cola a la posicion indicada.
+   */
+  async queueReorder(deviceId: string, itemId: number, index: number): Promise<void> {
+    await invoke("queue_reorder", { deviceId, itemId, index });
+  }
+
+  /**
+   * Vuelve a encolar los elementos que fallaron.
+   */
+  async queueRetryFailed(deviceId: string): Promise<void> {
+    await invoke("queue_retry_failed", { deviceId });
+  }
+
+  /**
+   * Quita de la cola todo salvo la accion en curso.
+   */
+  async queueClear(deviceId: string): Promise<void> {
+    await invoke("queue_clear", { deviceId });
+  }
+
+  /**
+   * Quita un elemento de la cola, o pide cancelarlo si esta en curso.
+   */
+  async queueCancel(deviceId: string, itemId: number): Promise<void> {
+    await invoke("queue_cancel", { deviceId, itemId });
+  }
+
   /**
    * Obtiene la ruta de descargas del sistema.
    */
@@ -163,6 +441,43 @@ class TauriUsbService {
     return Array.isArray(result) ? result : [result];
   }
 
+  /**
+   * Abre dialogo nativo para seleccionar una carpeta local.
+   */
+  async selectFolder(): Promise<string | null> {
+    const result = await openDialog({ directory: true, multiple: false });
+
+    if (!result) return null;
+    return Array.isArray(result) ? result[0] : result;
+  }
+
+  /**
+   * Abre dialogo nativo para elegir donde guardar un respaldo.
+   */
+  async selectBackupDestination(defaultPath: string): Promise<string | null> {
+    return await saveDialog({
+      defaultPath,
+      filters: [
+        { name: "Zip", extensions: ["zip"] },
+        { name: "tar", extensions: ["tar"] },
+        { name: "tar.zst", extensions: ["tar.zst", "tzst"] },
+      ],
+    });
+  }
+
+  /**
+   * Abre dialogo nativo para elegir un respaldo a restaurar.
+   */
+  async selectBackupFile(): Promise<string | null> {
+    const result = await openDialog({
+      multiple: false,
+      filters: [{ name: "Respaldos", extensions: ["zip", "tar", "zst", "tzst"] }],
+    });
+
+    if (!result) return null;
+    return Array.isArray(result) ? result[0] : result;
+  }
+
   /**
    * Abre dialogo nativo para seleccionar archivo OS.
    */
@@ -8,13 +8,17 @@ import { listen } from "@tauri-apps/api/event";
 import { usbService } from "@/services/usbService";
 import { PROGRESS_DEBOUNCE_MS } from "@/utils";
 import type {
+  BackendError,
+  ConflictPolicy,
   Device,
   DeviceId,
+  DeviceQueue,
+  OperationEvent,
   QueueAction,
   QueueItem,
   ProgressUpdate,
 } from "@/types";
-import { serializeDeviceId } from "@/types";
+import { isTransferConflict, serializeDeviceId } from "@/types";
 
 export const useDevicesStore = defineStore("devices", () => {
   // Estado
@@ -22,11 +26,12 @@ export const useDevicesStore = defineStore("devices", () => {
   const selectedDeviceKey = ref<string | null>(null);
   const isEnumerating = ref(false);
   const hasEnumerated = ref(false);
-  const queue = ref<Map<string, QueueItem[]>>(new Map());
-  const runningQueues = ref<Set<string>>(new Set());
-  const lastError = ref<Error | null>(null);
-
-  let queueIdCounter = 0;
+  // Copia de las colas del backend, por id estable
+  const queue = ref<Map<string, DeviceQueue>>(new Map());
+  const lastError = ref<BackendError | Error | null>(null);
+  // Operaciones largas en curso por id, con su ultimo progreso; incluye las que
+  // no vienen de la cola (lotes, comandos directos)
+  const activeOperations = ref<Map<number, ProgressUpdate | OperationEvent>>(new Map());
 
   // Debounce para actualizaciones de progreso (evita re-renders excesivos)
   let ultimoProgresoTimestamp = 0;
@@ -40,14 +45,24 @@ export const useDevicesStore = defineStore("devices", () => {
   });
 
   const selectedDeviceQueue = computed(() => {
-    if (!selectedDeviceKey.value) return [];
-    return queue.value.get(selectedDeviceKey.value) ?? [];
+    if (!selectedDeviceKey.value) return null;
+    return queue.value.get(selectedDeviceKey.value) ?? null;
   });
 
   // Acciones
+  /** Clave (id estable) del dispositivo que ocupa una ubicacion USB */
+  function keyForLocation(id: DeviceId): string | null {
+    const location = serializeDeviceId(id);
+    for (const [key, device] of devices.value) {
+      if (serializeDeviceId(device.id) === location) return key;
+    }
+    return null;
+  }
+
   function addDevice(payload: {
     busNumber: number;
     address: number;
+    stableId: string;
     name: string;
     isCxIi: boolean;
     needsDrivers: boolean;
@@ -56,10 +71,11 @@ export const useDevicesStore = defineStore("devices", () => {
       busNumber: payload.busNumber,
       address: payload.address,
     };
-    const key = serializeDeviceId(id);
+    const key = payload.stableId;
 
     devices.value.set(key, {
       id,
+      stableId: key,
       name: payload.name,
       isCxII: payload.isCxIi,
       needsDrivers: payload.needsDrivers,
@@ -74,10 +90,11 @@ export const useDevicesStore = defineStore("devices", () => {
   }
 
   function removeDevice(id: DeviceId) {
-    const key = serializeDeviceId(id);
+    const key = keyForLocation(id);
+    if (!key) return;
+
+    // El backend conserva la cola para retomarla si el dispositivo vuelve a conectarse
     devices.value.delete(key);
-    queue.value.delete(key);
-    runningQueues.value.delete(key);
 
     // Si era el seleccionado, seleccionar otro
     if (selectedDeviceKey.value === key) {
@@ -86,7 +103,72 @@ export const useDevicesStore = defineStore("devices", () => {
     }
   }
 
+  /**
+   * Mueve el estado de un dispositivo a su nuevo id estable. El backend ya
+   * movio su cola al abrirlo, y agrega un sufijo al id si otra calculadora
+   * conectada tiene el mismo nombre, asi que nunca pisa a otro dispositivo.
+   */
+  function rekeyDevice(oldKey: string, newKey: string) {
+    const device = devices.value.get(oldKey);
+    if (!device) return;
+    const other = devices.value.get(newKey);
+    if (other && serializeDeviceId(other.id) !== serializeDeviceId(device.id)) {
+      console.error(`El id estable ${newKey} ya es de otro dispositivo`);
+      return;
+    }
+
+    devices.value.delete(oldKey);
+    device.stableId = newKey;
+    devices.value.set(newKey, device);
+    queue.value.delete(oldKey);
+
+    if (selectedDeviceKey.value === oldKey) {
+      selectedDeviceKey.value = newKey;
+    }
+  }
+
+  /**
+   * Reemplaza la copia de una cola con el estado del backend, conservando el
+   * progreso de la accion en curso.
+   */
+  function setQueue(state: DeviceQueue) {
+    const previous = queue.value.get(state.device);
+    for (const item of state.items) {
+      const before = previous?.items.find((old) => old.id === item.id);
+      if (item.status === "running" && before?.operationId === item.operationId) {
+        item.progress = before.progress;
+      }
+      // Avisar de las acciones que acaban de fallar; los conflictos de las
+      // transferencias se resuelven desde la cola
+      if (
+        item.status === "failed" &&
+        before &&
+        before.status !== "failed" &&
+        item.error &&
+        !isTransferConflict(item)
+      ) {
+        lastError.value = item.error;
+      }
+    }
+    queue.value.set(state.device, state);
+  }
+
   function updateProgress(progress: ProgressUpdate) {
+    if (activeOperations.value.has(progress.operationId)) {
+      activeOperations.value.set(progress.operationId, progress);
+    }
+
+    const key = keyForLocation({
+      busNumber: progress.busNumber,
+      address: progress.address,
+    });
+    const item = key
+      ? queue.value
+          .get(key)
+          ?.items.find((item) => item.operationId === progress.operationId)
+      : undefined;
+    if (!item) return;
+
     // Debounce: ignorar actualizaciones muy frecuentes (excepto al finalizar)
     const ahora = Date.now();
     if (progress.remaining > 0 && ahora - ultimoProgresoTimestamp < PROGRESS_DEBOUNCE_MS) {
@@ -94,17 +176,13 @@ export const useDevicesStore = defineStore("devices", () => {
     }
     ultimoProgresoTimestamp = ahora;
 
-    const key = serializeDeviceId({
-      busNumber: progress.busNumber,
-      address: progress.address,
-    });
-    const deviceQueue = queue.value.get(key);
-    if (deviceQueue && deviceQueue.length > 0) {
-      deviceQueue[0].progress = {
-        current: progress.total - progress.remaining,
-        total: progress.total,
-      };
-    }
+    item.progress = {
+      current: progress.total - progress.remaining,
+      total: progress.total,
+      rate: progress.rate,
+      elapsedMs: progress.elapsedMs,
+      etaMs: progress.etaMs,
+    };
   }
 
   async function enumerate() {
@@ -118,7 +196,7 @@ export const useDevicesStore = defineStore("devices", () => {
       }
       hasEnumerated.value = true;
     } catch (e) {
-      lastError.value = e as Error;
+      lastError.value = e as BackendError;
       console.error("Error enumerando dispositivos:", e);
     } finally {
       isEnumerating.value = false;
@@ -131,10 +209,14 @@ export const useDevicesStore = defineStore("devices", () => {
 
     device.isLoading = true;
     try {
-      const info = await usbService.openDevice(device.id);
+      const info = await usbService.openDevice(key);
       device.info = info;
+      // Sin numero de serie USB el id estable se completa con el nombre de la calculadora
+      if (info.stableId !== key) {
+        rekeyDevice(key, info.stableId);
+      }
     } catch (e) {
-      lastError.value = e as Error;
+      lastError.value = e as BackendError;
       console.error("Error abriendo dispositivo:", e);
       throw e;
     } finally {
@@ -146,76 +228,84 @@ export const useDevicesStore = defineStore("devices", () => {
     selectedDeviceKey.value = key;
   }
 
-  function addToQueue(key: string, action: QueueAction) {
-    if (!queue.value.has(key)) {
-      queue.value.set(key, []);
+  async function addToQueue(key: string, action: QueueAction) {
+    try {
+      await usbService.queueEnqueue(key, action);
+    } catch (e) {
+      lastError.value = e as BackendError;
+      console.error("Error encolando:", e);
     }
-    queue.value.get(key)!.push({
-      id: queueIdCounter++,
-      action,
-    });
-    processQueue(key);
   }
 
-  async function processQueue(key: string) {
-    if (runningQueues.value.has(key)) return;
-
-    const deviceQueue = queue.value.get(key);
+  /**
+   * Pide donde guardar un respaldo completo de la calculadora y lo encola.
+   */
+  async function backupDevice(key: string) {
     const device = devices.value.get(key);
-    if (!deviceQueue || !device) return;
+    if (!device) return;
 
-    runningQueues.value.add(key);
+    const fecha = new Date().toISOString().slice(0, 10);
+    const downloadPath = await usbService.getDownloadPath();
+    const dest = await usbService.selectBackupDestination(
+      `${downloadPath}/${device.stableId}-${fecha}.zip`
+    );
+    if (!dest) return;
 
-    while (deviceQueue.length > 0) {
-      const item = deviceQueue[0];
-      try {
-        await executeQueueAction(device.id, item.action);
-      } catch (e) {
-        lastError.value = e as Error;
-        console.error("Error en cola:", e);
-      }
-      deviceQueue.shift();
+    addToQueue(key, { type: "backup", dest });
+  }
+
+  /**
+   * Ejecuta un cambio de la cola en el backend; el nuevo estado llega con el
+   * evento queueChanged.
+   */
+  async function changeQueue(change: () => Promise<unknown>) {
+    try {
+      await change();
+    } catch (e) {
+      lastError.value = e as BackendError;
+      console.error("Error en cola:", e);
     }
+  }
 
-    runningQueues.value.delete(key);
+  /**
+   * Cancela un elemento de la cola: si esta en curso se pide al backend
+   * (solo transferencias), si esta esperando o fallo se quita de la cola.
+   */
+  function cancelQueueItem(key: string, itemId: number) {
+    return changeQueue(() => usbService.queueCancel(key, itemId));
   }
 
-  async function executeQueueAction(
-    id: DeviceId,
-    action: QueueAction
-  ): Promise<void> {
-    switch (action.type) {
-      case "download": {
-        const downloadPath = await usbService.getDownloadPath();
-        const filename = action.path.split("/").pop() ?? "file";
-        await usbService.downloadFile(id, action.path, action.size, `${downloadPath}/${filename}`);
-        break;
-      }
-      case "upload": {
-        // La ruta src es la ruta absoluta del archivo en el sistema
-        await usbService.uploadFile(id, action.src, action.path);
-        break;
-      }
-      case "uploadOs":
-        await usbService.uploadOs(id, action.src);
-        break;
-      case "delete":
-        if (action.isDir) {
-          await usbService.deleteDir(id, action.path);
-        } else {
-          await usbService.deleteFile(id, action.path);
-        }
-        break;
-      case "createDir":
-        await usbService.createDir(id, action.path);
-        break;
-      case "move":
-        await usbService.move(id, action.src, action.dest);
-        break;
-      case "copy":
-        await usbService.copy(id, action.src, action.dest);
-        break;
-    }
+  function pauseQueue(key: string) {
+    return changeQueue(() => usbService.queuePause(key));
+  }
+
+  function resumeQueue(key: string) {
+    return changeQueue(() => usbService.queueResume(key));
+  }
+
+  function reorderQueueItem(key: string, itemId: number, index: number) {
+    return changeQueue(() => usbService.queueReorder(key, itemId, index));
+  }
+
+  function retryFailed(key: string) {
+    return changeQueue(() => usbService.queueRetryFailed(key));
+  }
+
+  function clearQueue(key: string) {
+    return changeQueue(() => usbService.queueClear(key));
+  }
+
+  /**
+   * Resuelve una transferencia que fallo porque el archivo ya existia: la
+   * vuelve a encolar con la politica elegida y quita la fallida.
+   */
+  function resolveConflict(key: string, item: QueueItem, policy: ConflictPolicy) {
+    if (!isTransferConflict(item)) return;
+    const action = { ...item.action, onConflict: policy } as QueueAction;
+    return changeQueue(async () => {
+      await usbService.queueEnqueue(key, action);
+      await usbService.queueCancel(key, item.id);
+    });
   }
 
   // Inicializacion de listeners de eventos Tauri
@@ -223,6 +313,7 @@ export const useDevicesStore = defineStore("devices", () => {
     await listen<{
       busNumber: number;
       address: number;
+      stableId: string;
       name: string;
       isCxIi: boolean;
       needsDrivers: boolean;
@@ -243,6 +334,33 @@ export const useDevicesStore = defineStore("devices", () => {
     await listen<ProgressUpdate>("progress", (event) => {
       updateProgress(event.payload);
     });
+
+    // En los lotes llegan tambien el inicio y el fin de cada accion, con el
+    // mismo id; la operacion termina con el evento sin batchIndex
+    await listen<OperationEvent>("operationStarted", (event) => {
+      activeOperations.value.set(event.payload.operationId, event.payload);
+    });
+
+    const endOperation = (event: { payload: OperationEvent }) => {
+      if (event.payload.batchIndex === null) {
+        activeOperations.value.delete(event.payload.operationId);
+      }
+    };
+    await listen<OperationEvent>("operationFinished", endOperation);
+    await listen<OperationEvent>("operationFailed", endOperation);
+
+    await listen<DeviceQueue>("queueChanged", (event) => {
+      setQueue(event.payload);
+    });
+
+    // Colas que ya existian, por ejemplo si se recargo la ventana
+    try {
+      for (const state of await usbService.queueList()) {
+        setQueue(state);
+      }
+    } catch (e) {
+      console.error("Error leyendo colas:", e);
+    }
   }
 
   return {
@@ -253,6 +371,7 @@ export const useDevicesStore = defineStore("devices", () => {
     hasEnumerated,
     queue,
     lastError,
+    activeOperations,
     // Getters
     deviceList,
     selectedDevice,
@@ -264,6 +383,14 @@ export const useDevicesStore = defineStore("devices", () => {
     openDevice,
     selectDevice,
     addToQueue,
+    backupDevice,
+    cancelQueueItem,
+    pauseQueue,
+    resumeQueue,
+    reorderQueueItem,
+    retryFailed,
+    clearQueue,
+    resolveConflict,
     initEventListeners,
   };
 });
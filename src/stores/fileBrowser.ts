@@ -11,8 +11,7 @@ import {
   CACHE_CLEANUP_INTERVAL_MS,
   REFRESH_AFTER_OPERATION_MS
 } from "@/utils";
-import type { FileInfo } from "@/types";
-import { serializeDeviceId } from "@/types";
+import type { BackendError, FileInfo } from "@/types";
 
 // Cache de directorios
 interface CacheEntry {
@@ -30,7 +29,7 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
   const selectedFiles = ref<Set<string>>(new Set());
   const isLoading = ref(false);
   const showHidden = ref(false);
-  const lastError = ref<Error | null>(null);
+  const lastError = ref<BackendError | Error | null>(null);
   const history = ref<string[]>([]);
   const historyIndex = ref(-1);
 
@@ -81,7 +80,7 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
     const device = devicesStore.selectedDevice;
     if (!device?.info) return;
 
-    const cacheKey = `${serializeDeviceId(device.id)}:${path}`;
+    const cacheKey = `${device.stableId}:${path}`;
     const ahora = Date.now();
 
     // Verificar cache si no se fuerza recarga
@@ -99,7 +98,7 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
     lastError.value = null;
 
     try {
-      const resultado = await usbService.listDir(device.id, path);
+      const resultado = await usbService.listDir(device.stableId, path);
       files.value = resultado;
       currentPath.value = path;
       selectedFiles.value.clear();
@@ -110,7 +109,7 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
         timestamp: ahora,
       });
     } catch (e) {
-      lastError.value = e as Error;
+      lastError.value = e as BackendError;
       console.error("Error cargando directorio:", e);
     } finally {
       isLoading.value = false;
@@ -187,7 +186,7 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
       const device = devicesStore.selectedDevice;
       if (!device) return;
 
-      const key = serializeDeviceId(device.id);
+      const key = device.stableId;
       const fullPath =
         currentPath.value === "/"
           ? `/${file.path}`
@@ -197,6 +196,7 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
         type: "download",
         path: fullPath,
         size: file.size,
+        onConflict: "ask",
       });
     }
   }
@@ -205,7 +205,7 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
     const device = devicesStore.selectedDevice;
     if (!device) return;
 
-    const key = serializeDeviceId(device.id);
+    const key = device.stableId;
 
     for (const fullPath of selectedFiles.value) {
       const file = files.value.find(
@@ -229,7 +229,7 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
     const device = devicesStore.selectedDevice;
     if (!device) return;
 
-    const key = serializeDeviceId(device.id);
+    const key = device.stableId;
     const fullPath =
       currentPath.value === "/" ? `/${name}` : `${currentPath.value}/${name}`;
 
@@ -252,19 +252,41 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
     const rutas = await usbService.selectFilesForUpload();
     if (rutas.length === 0) return;
 
-    const key = serializeDeviceId(device.id);
+    const key = device.stableId;
 
     for (const src of rutas) {
       devicesStore.addToQueue(key, {
         type: "upload",
         path: currentPath.value,
         src,
+        onConflict: "ask",
       });
     }
 
     setTimeout(() => refresh(), REFRESH_AFTER_OPERATION_MS);
   }
 
+  /**
+   * Abre dialogo nativo para seleccionar una carpeta y la sube completa,
+   * con sus subcarpetas, al directorio actual.
+   */
+  async function uploadFolder() {
+    const device = devicesStore.selectedDevice;
+    if (!device) return;
+
+    const src = await usbService.selectFolder();
+    if (!src) return;
+
+    devicesStore.addToQueue(device.stableId, {
+      type: "uploadDir",
+      path: currentPath.value,
+      src,
+      onConflict: "ask",
+    });
+
+    setTimeout(() => refresh(), REFRESH_AFTER_OPERATION_MS);
+  }
+
   /**
    * Sube archivos desde rutas absolutas (usado por drag and drop).
    * Las rutas vienen directamente del sistema de archivos.
@@ -273,13 +295,14 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
     const device = devicesStore.selectedDevice;
     if (!device) return;
 
-    const key = serializeDeviceId(device.id);
+    const key = device.stableId;
 
     for (const src of rutas) {
       devicesStore.addToQueue(key, {
         type: "upload",
         path: currentPath.value,
         src,
+        onConflict: "ask",
       });
     }
 
@@ -344,6 +367,7 @@ export const useFileBrowserStore = defineStore("fileBrowser", () => {
     deleteSelected,
     createDirectory,
     uploadFiles,
+    uploadFolder,
     uploadFilesFromPaths,
     reset,
   };
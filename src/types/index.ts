@@ -3,7 +3,7 @@
  * Define las estructuras de datos para dispositivos y archivos.
  */
 
-/** Identificador unico de dispositivo USB */
+/** Ubicacion USB del dispositivo; cambia en cada reconexion */
 export interface DeviceId {
   busNumber: number;
   address: number;
@@ -17,8 +17,27 @@ export interface FileInfo {
   size: number;
 }
 
+/** Resultado de un archivo dentro de una operacion sobre un directorio */
+export interface FileResult {
+  path: string;
+  error: BackendError | null;
+  /** Solo en las subidas y descargas: que se hizo con el archivo; null si fallo */
+  outcome: TransferOutcome | null;
+}
+
+/**
+ * Que hacer al transferir un archivo que ya existe en el destino (la
+ * calculadora al subir, el disco al descargar). Con "ask" la transferencia
+ * falla con already_exists para preguntar al usuario.
+ */
+export type ConflictPolicy = "overwrite" | "skip" | "keepBoth" | "ask";
+
+/** Lo que se hizo con un archivo transferido */
+export type TransferOutcome = "created" | "overwritten" | "skipped" | "renamed";
+
 /** Informacion del sistema de la calculadora */
 export interface DeviceInfo {
+  stableId: string;
   name: string;
   freeStorage: number;
   totalStorage: number;
@@ -40,6 +59,8 @@ export interface DeviceInfo {
 /** Dispositivo conectado */
 export interface Device {
   id: DeviceId;
+  /** Identificador estable; clave del dispositivo en los stores y en los comandos */
+  stableId: string;
   name: string;
   isCxII: boolean;
   needsDrivers: boolean;
@@ -49,30 +70,227 @@ export interface Device {
 
 /** Accion de la cola de operaciones */
 export type QueueAction =
-  | { type: "download"; path: string; size: number }
-  | { type: "upload"; path: string; src: string }
+  | {
+      type: "download";
+      path: string;
+      size: number;
+      dest?: string;
+      onConflict?: ConflictPolicy;
+    }
+  | { type: "downloadDir"; path: string; dest?: string; onConflict?: ConflictPolicy }
+  | { type: "upload"; path: string; src: string; onConflict?: ConflictPolicy }
+  | {
+      type: "uploadDir";
+      path: string;
+      src: string;
+      extensions?: string[];
+      onConflict?: ConflictPolicy;
+    }
   | { type: "uploadOs"; src: string }
   | { type: "delete"; path: string; isDir: boolean }
   | { type: "createDir"; path: string }
   | { type: "move"; src: string; dest: string }
-  | { type: "copy"; src: string; dest: string };
+  | { type: "copy"; src: string; dest: string }
+  | { type: "sync"; plan: SyncPlan }
+  | { type: "backup"; dest: string }
+  | { type: "restore"; src: string; mode: RestoreMode }
+  | { type: "snapshot"; repo?: string }
+  | { type: "snapshotRestore"; snapshot: string; mode: RestoreMode; repo?: string };
+
+/** Accion de un lote de `run_batch`: las mismas que las de la cola */
+export type BatchAction = QueueAction;
+
+/** Resultado de una accion de un lote, por su posicion */
+export interface BatchActionResult {
+  index: number;
+  error: BackendError | null;
+}
+
+/** Resultado de un lote; batchId es el id de operacion de su progreso */
+export interface BatchResult {
+  batchId: number;
+  results: BatchActionResult[];
+}
+
+/** Que hacer con el contenido de la calculadora al restaurar un respaldo */
+export type RestoreMode = "merge" | "replaceMatching" | "wipeThenRestore";
 
-/** Elemento en la cola con progreso */
+/** Instantanea del repositorio de respaldos incrementales */
+export interface SnapshotSummary {
+  id: string;
+  /** Id estable de la calculadora respaldada */
+  device: string;
+  name: string;
+  /** Segundos desde la epoca Unix */
+  created: number;
+  files: number;
+  size: number;
+}
+
+/** Instantaneas del repositorio y el error de cada una que no se pudo leer */
+export interface SnapshotList {
+  snapshots: SnapshotSummary[];
+  invalid: BackendError[];
+}
+
+/** Entrada del arbol de una instantanea */
+export interface SnapshotEntry {
+  path: string;
+  isDir: boolean;
+  size: number;
+  date: number;
+  hash?: string;
+}
+
+/** Resultado de tomar una instantanea */
+export interface SnapshotReport {
+  snapshot: SnapshotSummary;
+  downloaded: number;
+  reused: number;
+  results: FileResult[];
+}
+
+/** Diferencias entre dos instantaneas */
+export interface SnapshotDiff {
+  added: SnapshotEntry[];
+  removed: SnapshotEntry[];
+  modified: SnapshotEntry[];
+}
+
+/** Sentido de la sincronizacion de carpetas */
+export type SyncMode = "mirrorToDevice" | "mirrorToLocal" | "twoWay";
+
+/** Accion de un plan de sincronizacion; remote es de la calculadora, local del sistema */
+export type SyncAction =
+  | { type: "upload"; local: string; remote: string; size: number }
+  | { type: "download"; remote: string; local: string; size: number; date: number }
+  | { type: "createRemoteDir"; remote: string }
+  | { type: "createLocalDir"; local: string }
+  | { type: "deleteRemote"; remote: string; dir: boolean }
+  | { type: "deleteLocal"; local: string; dir: boolean };
+
+/** Plan de sincronizacion calculado por el backend, en orden de ejecucion */
+export interface SyncPlan {
+  mode: SyncMode;
+  local: string;
+  remote: string;
+  actions: SyncAction[];
+  /** Rutas con archivo en un lado y carpeta en el otro (solo modo bidireccional) */
+  conflicts: string[];
+}
+
+/** Estado de un elemento de la cola; los que terminan o se cancelan salen de ella */
+export type QueueItemStatus = "pending" | "running" | "failed";
+
+/** Elemento de la cola del backend, con el progreso que lleva el frontend */
 export interface QueueItem {
   id: number;
   action: QueueAction;
+  status: QueueItemStatus;
+  /** Error del ultimo intento si fallo */
+  error: BackendError | null;
+  /** Id de la operacion en curso, el de sus eventos de progreso */
+  operationId: number | null;
   progress?: {
     current: number;
     total: number;
+    /** Unidades de total (bytes en las transferencias) por segundo */
+    rate: number;
+    elapsedMs: number;
+    etaMs: number | null;
   };
 }
 
+/** Cola de operaciones de un dispositivo, por id estable */
+export interface DeviceQueue {
+  device: string;
+  paused: boolean;
+  /** En orden de ejecucion */
+  items: QueueItem[];
+}
+
+/** Tipo de operacion larga; los nombres son los de las acciones de la cola */
+export type OperationKind = QueueAction["type"] | "batch";
+
+/** Que hace una operacion larga; lo llevan todos sus eventos */
+export interface OperationDetails {
+  operationId: number;
+  kind: OperationKind;
+  /** Ruta de origen, de la calculadora o del sistema segun el tipo */
+  src: string | null;
+  /** Ruta de destino, de la calculadora o del sistema segun el tipo */
+  dest: string | null;
+  /** Dentro de un lote, la accion en curso; kind, src y dest son los de esa accion */
+  batchIndex: number | null;
+  batchCount: number | null;
+}
+
+/** Inicio, fin o fallo de una operacion larga o de una accion de un lote */
+export interface OperationEvent extends OperationDetails {
+  busNumber: number;
+  address: number;
+  /** Solo en los fallos; la cancelacion es un fallo con codigo cancelled */
+  error: BackendError | null;
+}
+
 /** Estado de progreso de una operacion */
-export interface ProgressUpdate {
+export interface ProgressUpdate extends OperationDetails {
   busNumber: number;
   address: number;
   remaining: number;
   total: number;
+  /** Velocidad promediada, en unidades de total (bytes en las transferencias) por segundo */
+  rate: number;
+  /** Tiempo desde el inicio de la operacion */
+  elapsedMs: number;
+  /** Tiempo restante estimado; null mientras no se conoce */
+  etaMs: number | null;
+}
+
+/** Estado de la cola de trabajo de un dispositivo abierto */
+export interface WorkerStatus {
+  busy: boolean;
+  queued: number;
+  /** Operacion del trabajo en curso */
+  running: number | null;
+  /**
+   * Operacion de cada trabajo que espera turno, en orden: el indice es la
+   * posicion en la cola. null para los trabajos sin operacion registrada.
+   */
+  waiting: (number | null)[];
+}
+
+/** Codigo estable de error devuelto por el backend */
+export type ErrorCode =
+  | "device_not_found"
+  | "device_closed"
+  | "device_already_open"
+  | "needs_drivers"
+  | "disconnected"
+  | "busy"
+  | "timeout"
+  | "cancelled"
+  | "not_cancellable"
+  | "interrupted"
+  | "no_space"
+  | "not_found"
+  | "already_exists"
+  | "file_changed"
+  | "file_too_large"
+  | "permission_denied"
+  | "invalid_argument"
+  | "protocol"
+  | "usb"
+  | "io"
+  | "internal";
+
+/** Error estructurado con el que fallan los comandos del backend */
+export interface BackendError {
+  code: ErrorCode;
+  source: "libnspire" | "rusb" | "io" | "app";
+  device: DeviceId | null;
+  path: string | null;
+  message: string;
 }
 
 /** Serializador de DeviceId para uso como clave */
@@ -85,3 +303,15 @@ export function deserializeDeviceId(key: string): DeviceId {
   const [busNumber, address] = key.split("-").map(Number);
   return { busNumber, address };
 }
+
+/** Acciones de la cola que aceptan una politica de conflictos */
+const TRANSFER_ACTIONS: QueueAction["type"][] = ["download", "downloadDir", "upload", "uploadDir"];
+
+/** Transferencia que fallo porque el archivo ya existia en el destino (politica "ask") */
+export function isTransferConflict(item: QueueItem): boolean {
+  return (
+    TRANSFER_ACTIONS.includes(item.action.type) &&
+    item.status === "failed" &&
+    item.error?.code === "already_exists"
+  );
+}
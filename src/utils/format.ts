@@ -37,3 +37,17 @@ export function formatDate(timestamp: number): string {
 export function formatVersion(version: string): string {
     return version || "N/A";
 }
+
+/**
+ * Formatea una duracion como minutos y segundos (o horas si pasa de una).
+ * @param ms Duracion en milisegundos
+ * @returns Cadena formateada (ej: "2:05" o "1:02:05")
+ */
+export function formatDuration(ms: number): string {
+    const total = Math.round(ms / 1000);
+    const horas = Math.floor(total / 3600);
+    const minutos = Math.floor((total % 3600) / 60);
+    const segundos = String(total % 60).padStart(2, "0");
+    if (horas > 0) return `${horas}:${String(minutos).padStart(2, "0")}:${segundos}`;
+    return `${minutos}:${segundos}`;
+}